@@ -4,7 +4,7 @@ use util::sext_16;
 
 use crate::instruction::{
     Instr, Reg, OP_ADDI, OP_ADDIU, OP_ANDI, OP_BEQ, OP_BEQL, OP_BGTZ, OP_BGTZL, OP_BLEZ, OP_BLEZL,
-    OP_BNE, OP_BNEL, OP_COP0, OP_COP1, OP_COP2, OP_COP_BC, OP_COP_BC_BCF, OP_COP_BC_BCFL,
+    OP_BNE, OP_BNEL, OP_CACHE, OP_COP0, OP_COP1, OP_COP2, OP_COP_BC, OP_COP_BC_BCF, OP_COP_BC_BCFL,
     OP_COP_BC_BCT, OP_COP_BC_BCTL, OP_COP_CF, OP_COP_CT, OP_COP_DMF, OP_COP_DMT, OP_COP_MF,
     OP_COP_MT, OP_DADDI, OP_DADDIU, OP_IR_BGEZ, OP_IR_BGEZAL, OP_IR_BGEZALL, OP_IR_BGEZL,
     OP_IR_BLTZ, OP_IR_BLTZAL, OP_IR_BLTZALL, OP_IR_BLTZL, OP_IR_TEQI, OP_IR_TGEI, OP_IR_TGEIU,
@@ -181,66 +181,50 @@ pub trait RawCore {
     fn do_beq(&mut self, instr: Instr, likely: bool) -> MipsResult<()> {
         let rs = self.get_reg_inatural(instr.rs())?;
         let rt = self.get_reg_inatural(instr.rt())?;
-        if rs == rt {
-            self.do_branch(instr, likely)?;
-        }
+        self.do_branch(instr, rs == rt, likely)?;
 
         Ok(())
     }
     fn do_bgez(&mut self, instr: Instr, likely: bool) -> MipsResult<()> {
         let rs = self.get_reg_inatural(instr.rs())?;
-        if rs >= 0 {
-            self.do_branch(instr, likely)?;
-        }
+        self.do_branch(instr, rs >= 0, likely)?;
 
         Ok(())
     }
     fn do_bgezal(&mut self, instr: Instr, likely: bool) -> MipsResult<()> {
         let rs = self.get_reg_inatural(instr.rs())?;
-        if rs >= 0 {
-            self.do_branch_and_link(instr, likely)?;
-        }
+        self.do_branch_and_link(instr, rs >= 0, likely)?;
 
         Ok(())
     }
     fn do_bgtz(&mut self, instr: Instr, likely: bool) -> MipsResult<()> {
         let rs = self.get_reg_inatural(instr.rs())?;
-        if rs > 0 {
-            self.do_branch(instr, likely)?;
-        }
+        self.do_branch(instr, rs > 0, likely)?;
 
         Ok(())
     }
     fn do_blez(&mut self, instr: Instr, likely: bool) -> MipsResult<()> {
         let rs = self.get_reg_inatural(instr.rs())?;
-        if rs <= 0 {
-            self.do_branch(instr, likely)?;
-        }
+        self.do_branch(instr, rs <= 0, likely)?;
 
         Ok(())
     }
     fn do_bltz(&mut self, instr: Instr, likely: bool) -> MipsResult<()> {
         let rs = self.get_reg_inatural(instr.rs())?;
-        if rs < 0 {
-            self.do_branch(instr, likely)?;
-        }
+        self.do_branch(instr, rs < 0, likely)?;
 
         Ok(())
     }
     fn do_bltzal(&mut self, instr: Instr, likely: bool) -> MipsResult<()> {
         let rs = self.get_reg_inatural(instr.rs())?;
-        if rs < 0 {
-            self.do_branch_and_link(instr, likely)?;
-        }
+        self.do_branch_and_link(instr, rs < 0, likely)?;
 
         Ok(())
     }
     fn do_bne(&mut self, instr: Instr, likely: bool) -> MipsResult<()> {
         let rs = self.get_reg_inatural(instr.rs())?;
         let rt = self.get_reg_inatural(instr.rt())?;
-        if rs != rt {
-            self.do_branch(instr, likely)?;
-        }
+        self.do_branch(instr, rs != rt, likely)?;
 
         Ok(())
     }
@@ -315,7 +299,6 @@ pub trait RawCore {
         Ok(())
     }
     fn do_div(&mut self, instr: Instr) -> MipsResult<()> {
-        self.dword_operation(instr)?;
         let rs = self.get_reg_i32(instr.rs())?;
         let rt = self.get_reg_i32(instr.rt())?;
         if rt == 0 {
@@ -329,7 +312,6 @@ pub trait RawCore {
         Ok(())
     }
     fn do_divu(&mut self, instr: Instr) -> MipsResult<()> {
-        self.dword_operation(instr)?;
         let rs = self.get_reg_u32(instr.rs())?;
         let rt = self.get_reg_u32(instr.rt())?;
         if rt == 0 {
@@ -500,7 +482,6 @@ pub trait RawCore {
         Ok(())
     }
     fn do_mult(&mut self, instr: Instr) -> MipsResult<()> {
-        self.dword_operation(instr)?;
         let rs = self.get_reg_i32(instr.rs())? as i64;
         let rt = self.get_reg_i32(instr.rt())? as i64;
         let p = rs.wrapping_mul(rt);
@@ -512,7 +493,6 @@ pub trait RawCore {
         Ok(())
     }
     fn do_multu(&mut self, instr: Instr) -> MipsResult<()> {
-        self.dword_operation(instr)?;
         let rs = self.get_reg_u32(instr.rs())? as u64;
         let rt = self.get_reg_u32(instr.rt())? as u64;
         let p = rs.wrapping_mul(rt);
@@ -621,14 +601,13 @@ pub trait RawCore {
     }
     fn do_srlv(&mut self, instr: Instr) -> MipsResult<()> {
         let rt = self.get_reg_u32(instr.rt())?;
-        let sa = self.get_reg_u32(instr.rs())? & 0x3F;
+        let sa = self.get_reg_u32(instr.rs())? & 0x1F;
         let shifted = rt >> sa;
         self.set_reg_u32(instr.rd(), shifted)?;
 
         Ok(())
     }
     fn do_sub(&mut self, instr: Instr) -> MipsResult<()> {
-        self.dword_operation(instr)?;
         let rs = self.get_reg_i32(instr.rs())?;
         let rt = self.get_reg_i32(instr.rt())?;
         let (sum, over) = rs.overflowing_sub(rt);
@@ -639,7 +618,6 @@ pub trait RawCore {
         Ok(())
     }
     fn do_subu(&mut self, instr: Instr) -> MipsResult<()> {
-        self.dword_operation(instr)?;
         let rs = self.get_reg_i32(instr.rs())?;
         let rt = self.get_reg_i32(instr.rt())?;
         let sum = rs.wrapping_sub(rt);
@@ -648,18 +626,114 @@ pub trait RawCore {
     }
     fn do_sync(&mut self, instr: Instr) -> MipsResult<()>;
     fn do_syscall(&mut self, instr: Instr) -> MipsResult<()>;
-    fn do_teq(&mut self, instr: Instr) -> MipsResult<()>;
-    fn do_teqi(&mut self, instr: Instr) -> MipsResult<()>;
-    fn do_tge(&mut self, instr: Instr) -> MipsResult<()>;
-    fn do_tgei(&mut self, instr: Instr) -> MipsResult<()>;
-    fn do_tgeiu(&mut self, instr: Instr) -> MipsResult<()>;
-    fn do_tgeu(&mut self, instr: Instr) -> MipsResult<()>;
-    fn do_tlt(&mut self, instr: Instr) -> MipsResult<()>;
-    fn do_tlti(&mut self, instr: Instr) -> MipsResult<()>;
-    fn do_tltiu(&mut self, instr: Instr) -> MipsResult<()>;
-    fn do_tltu(&mut self, instr: Instr) -> MipsResult<()>;
-    fn do_tne(&mut self, instr: Instr) -> MipsResult<()>;
-    fn do_tnei(&mut self, instr: Instr) -> MipsResult<()>;
+    fn do_teq(&mut self, instr: Instr) -> MipsResult<()> {
+        let rs = self.get_reg_inatural(instr.rs())?;
+        let rt = self.get_reg_inatural(instr.rt())?;
+        if rs == rt {
+            self.trap(instr)?;
+        }
+
+        Ok(())
+    }
+    fn do_teqi(&mut self, instr: Instr) -> MipsResult<()> {
+        let rs = self.get_reg_inatural(instr.rs())?;
+        let imm = instr.immi() as i64;
+        if rs == imm {
+            self.trap(instr)?;
+        }
+
+        Ok(())
+    }
+    fn do_tge(&mut self, instr: Instr) -> MipsResult<()> {
+        let rs = self.get_reg_inatural(instr.rs())?;
+        let rt = self.get_reg_inatural(instr.rt())?;
+        if rs >= rt {
+            self.trap(instr)?;
+        }
+
+        Ok(())
+    }
+    fn do_tgei(&mut self, instr: Instr) -> MipsResult<()> {
+        let rs = self.get_reg_inatural(instr.rs())?;
+        let imm = instr.immi() as i64;
+        if rs >= imm {
+            self.trap(instr)?;
+        }
+
+        Ok(())
+    }
+    fn do_tgeiu(&mut self, instr: Instr) -> MipsResult<()> {
+        let rs = self.get_reg_inatural(instr.rs())? as u64;
+        let imm = instr.immi() as i64 as u64;
+        if rs >= imm {
+            self.trap(instr)?;
+        }
+
+        Ok(())
+    }
+    fn do_tgeu(&mut self, instr: Instr) -> MipsResult<()> {
+        let rs = self.get_reg_inatural(instr.rs())? as u64;
+        let rt = self.get_reg_inatural(instr.rt())? as u64;
+        if rs >= rt {
+            self.trap(instr)?;
+        }
+
+        Ok(())
+    }
+    fn do_tlt(&mut self, instr: Instr) -> MipsResult<()> {
+        let rs = self.get_reg_inatural(instr.rs())?;
+        let rt = self.get_reg_inatural(instr.rt())?;
+        if rs < rt {
+            self.trap(instr)?;
+        }
+
+        Ok(())
+    }
+    fn do_tlti(&mut self, instr: Instr) -> MipsResult<()> {
+        let rs = self.get_reg_inatural(instr.rs())?;
+        let imm = instr.immi() as i64;
+        if rs < imm {
+            self.trap(instr)?;
+        }
+
+        Ok(())
+    }
+    fn do_tltiu(&mut self, instr: Instr) -> MipsResult<()> {
+        let rs = self.get_reg_inatural(instr.rs())? as u64;
+        let imm = instr.immi() as i64 as u64;
+        if rs < imm {
+            self.trap(instr)?;
+        }
+
+        Ok(())
+    }
+    fn do_tltu(&mut self, instr: Instr) -> MipsResult<()> {
+        let rs = self.get_reg_inatural(instr.rs())? as u64;
+        let rt = self.get_reg_inatural(instr.rt())? as u64;
+        if rs < rt {
+            self.trap(instr)?;
+        }
+
+        Ok(())
+    }
+    fn do_tne(&mut self, instr: Instr) -> MipsResult<()> {
+        let rs = self.get_reg_inatural(instr.rs())?;
+        let rt = self.get_reg_inatural(instr.rt())?;
+        if rs != rt {
+            self.trap(instr)?;
+        }
+
+        Ok(())
+    }
+    fn do_tnei(&mut self, instr: Instr) -> MipsResult<()> {
+        let rs = self.get_reg_inatural(instr.rs())?;
+        let imm = instr.immi() as i64;
+        if rs != imm {
+            self.trap(instr)?;
+        }
+
+        Ok(())
+    }
     fn do_xor(&mut self, instr: Instr) -> MipsResult<()> {
         let rs = self.get_reg_inatural(instr.rs())?;
         let rt = self.get_reg_inatural(instr.rt())?;
@@ -677,8 +751,10 @@ pub trait RawCore {
         Ok(())
     }
 
-    fn do_branch_and_link(&mut self, instr: Instr, likely: bool) -> MipsResult<()>;
-    fn do_branch(&mut self, instr: Instr, likely: bool) -> MipsResult<()>;
+    /// Branches to the target of `instr` after its delay slot if `taken`.
+    /// A branch-likely that is not taken nullifies its delay slot instead.
+    fn do_branch_and_link(&mut self, instr: Instr, taken: bool, likely: bool) -> MipsResult<()>;
+    fn do_branch(&mut self, instr: Instr, taken: bool, likely: bool) -> MipsResult<()>;
 
     fn get_reg_unatural(&self, reg: Reg) -> MipsResult<u64> {
         if self.is_64_bit_mode() {
@@ -765,6 +841,7 @@ pub trait RawCore {
 
     fn reserved_instruction(&mut self, instr: Instr) -> MipsResult<()>;
     fn integer_overflow(&mut self, instr: Instr) -> MipsResult<()>;
+    fn trap(&mut self, instr: Instr) -> MipsResult<()>;
     fn dword_operation(&mut self, instr: Instr) -> MipsResult<()>;
 }
 
@@ -813,6 +890,7 @@ pub trait MipsCore<T>: RawCore {
             OP_SDL => self.do_sdl(instr, bus)?,
             OP_SDR => self.do_sdr(instr, bus)?,
             OP_SWR => self.do_swr(instr, bus)?,
+            OP_CACHE => self.do_cache(instr, bus)?,
             OP_LL => self.do_ll(instr, bus)?,
             OP_LWC1 => self.do_lwcz(instr, 1, bus)?,
             OP_LWC2 => self.do_lwcz(instr, 2, bus)?,
@@ -833,6 +911,7 @@ pub trait MipsCore<T>: RawCore {
         Ok(())
     }
 
+    fn do_cache(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()>;
     fn do_lb(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()>;
     fn do_lbu(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()>;
     fn do_ld(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()>;
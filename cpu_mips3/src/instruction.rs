@@ -37,6 +37,18 @@ impl Instr {
     pub fn jump_offset(self) -> u32 {
         self.0 & 0x3FFFFFF
     }
+    pub fn fmt(self) -> u8 {
+        self.rs().0
+    }
+    pub fn ft(self) -> Reg {
+        self.rt()
+    }
+    pub fn fs(self) -> Reg {
+        self.rd()
+    }
+    pub fn fd(self) -> Reg {
+        Reg(self.sa())
+    }
 }
 
 pub const OP_SPECIAL: u8 = 0o00;
@@ -81,6 +93,7 @@ pub const OP_SW: u8 = 0o53;
 pub const OP_SDL: u8 = 0o54;
 pub const OP_SDR: u8 = 0o55;
 pub const OP_SWR: u8 = 0o56;
+pub const OP_CACHE: u8 = 0o57;
 pub const OP_LL: u8 = 0o60;
 pub const OP_LWC1: u8 = 0o61;
 pub const OP_LWC2: u8 = 0o62;
@@ -172,6 +185,99 @@ pub const OP_COP_DMT: u8 = 0o05;
 pub const OP_COP_CT: u8 = 0o06;
 pub const OP_COP_BC: u8 = 0o10;
 
+pub const OP_C0_TLBR: u8 = 0o01;
+pub const OP_C0_TLBWI: u8 = 0o02;
+pub const OP_C0_TLBWR: u8 = 0o06;
+pub const OP_C0_TLBP: u8 = 0o10;
+pub const OP_C0_ERET: u8 = 0o30;
+
+pub const FMT_S: u8 = 0o20;
+pub const FMT_D: u8 = 0o21;
+pub const FMT_W: u8 = 0o24;
+pub const FMT_L: u8 = 0o25;
+
+pub const OP_C1_ADD: u8 = 0o00;
+pub const OP_C1_SUB: u8 = 0o01;
+pub const OP_C1_MUL: u8 = 0o02;
+pub const OP_C1_DIV: u8 = 0o03;
+pub const OP_C1_SQRT: u8 = 0o04;
+pub const OP_C1_ABS: u8 = 0o05;
+pub const OP_C1_MOV: u8 = 0o06;
+pub const OP_C1_NEG: u8 = 0o07;
+pub const OP_C1_ROUND_L: u8 = 0o10;
+pub const OP_C1_TRUNC_L: u8 = 0o11;
+pub const OP_C1_CEIL_L: u8 = 0o12;
+pub const OP_C1_FLOOR_L: u8 = 0o13;
+pub const OP_C1_ROUND_W: u8 = 0o14;
+pub const OP_C1_TRUNC_W: u8 = 0o15;
+pub const OP_C1_CEIL_W: u8 = 0o16;
+pub const OP_C1_FLOOR_W: u8 = 0o17;
+pub const OP_C1_CVT_S: u8 = 0o40;
+pub const OP_C1_CVT_D: u8 = 0o41;
+pub const OP_C1_CVT_W: u8 = 0o44;
+pub const OP_C1_CVT_L: u8 = 0o45;
+pub const OP_C1_C_FIRST: u8 = 0o60;
+pub const OP_C1_C_LAST: u8 = 0o77;
+
+pub const OP_C2_VMULF: u8 = 0o00;
+pub const OP_C2_VMULU: u8 = 0o01;
+pub const OP_C2_VRNDP: u8 = 0o02;
+pub const OP_C2_VMULQ: u8 = 0o03;
+pub const OP_C2_VMUDL: u8 = 0o04;
+pub const OP_C2_VMUDM: u8 = 0o05;
+pub const OP_C2_VMUDN: u8 = 0o06;
+pub const OP_C2_VMUDH: u8 = 0o07;
+pub const OP_C2_VMACF: u8 = 0o10;
+pub const OP_C2_VMACU: u8 = 0o11;
+pub const OP_C2_VRNDN: u8 = 0o12;
+pub const OP_C2_VMACQ: u8 = 0o13;
+pub const OP_C2_VMADL: u8 = 0o14;
+pub const OP_C2_VMADM: u8 = 0o15;
+pub const OP_C2_VMADN: u8 = 0o16;
+pub const OP_C2_VMADH: u8 = 0o17;
+pub const OP_C2_VADD: u8 = 0o20;
+pub const OP_C2_VSUB: u8 = 0o21;
+pub const OP_C2_VABS: u8 = 0o23;
+pub const OP_C2_VADDC: u8 = 0o24;
+pub const OP_C2_VSUBC: u8 = 0o25;
+pub const OP_C2_VSAR: u8 = 0o35;
+pub const OP_C2_VLT: u8 = 0o40;
+pub const OP_C2_VEQ: u8 = 0o41;
+pub const OP_C2_VNE: u8 = 0o42;
+pub const OP_C2_VGE: u8 = 0o43;
+pub const OP_C2_VCL: u8 = 0o44;
+pub const OP_C2_VCH: u8 = 0o45;
+pub const OP_C2_VCR: u8 = 0o46;
+pub const OP_C2_VMRG: u8 = 0o47;
+pub const OP_C2_VAND: u8 = 0o50;
+pub const OP_C2_VNAND: u8 = 0o51;
+pub const OP_C2_VOR: u8 = 0o52;
+pub const OP_C2_VNOR: u8 = 0o53;
+pub const OP_C2_VXOR: u8 = 0o54;
+pub const OP_C2_VNXOR: u8 = 0o55;
+pub const OP_C2_VRCP: u8 = 0o60;
+pub const OP_C2_VRCPL: u8 = 0o61;
+pub const OP_C2_VRCPH: u8 = 0o62;
+pub const OP_C2_VMOV: u8 = 0o63;
+pub const OP_C2_VRSQ: u8 = 0o64;
+pub const OP_C2_VRSQL: u8 = 0o65;
+pub const OP_C2_VRSQH: u8 = 0o66;
+pub const OP_C2_VNOP: u8 = 0o67;
+pub const OP_C2_VNULL: u8 = 0o77;
+
+pub const OP_C2_MEM_BV: u8 = 0o00;
+pub const OP_C2_MEM_SV: u8 = 0o01;
+pub const OP_C2_MEM_LV: u8 = 0o02;
+pub const OP_C2_MEM_DV: u8 = 0o03;
+pub const OP_C2_MEM_QV: u8 = 0o04;
+pub const OP_C2_MEM_RV: u8 = 0o05;
+pub const OP_C2_MEM_PV: u8 = 0o06;
+pub const OP_C2_MEM_UV: u8 = 0o07;
+pub const OP_C2_MEM_HV: u8 = 0o10;
+pub const OP_C2_MEM_FV: u8 = 0o11;
+pub const OP_C2_MEM_WV: u8 = 0o12;
+pub const OP_C2_MEM_TV: u8 = 0o13;
+
 pub const OP_COP_BC_BCF: u8 = 0o00;
 pub const OP_COP_BC_BCT: u8 = 0o01;
 pub const OP_COP_BC_BCFL: u8 = 0o02;
@@ -225,6 +331,7 @@ pub fn print_instr(o: &mut impl Write, instr: Instr) -> io::Result<()> {
         OP_SDL => print_sdl(o, instr)?,
         OP_SDR => print_sdr(o, instr)?,
         OP_SWR => print_swr(o, instr)?,
+        OP_CACHE => print_cache(o, instr)?,
         OP_LL => print_ll(o, instr)?,
         OP_LWC1 => print_lwcz(o, instr, 1)?,
         OP_LWC2 => print_lwcz(o, instr, 2)?,
@@ -539,7 +646,23 @@ fn print_cfcz(o: &mut impl Write, instr: Instr, cop: u8) -> io::Result<()> {
     print_cop_mov_instr(o, &format!("CFC{cop}"), instr)?;
     Ok(())
 }
-fn print_copz(o: &mut impl Write, _instr: Instr, cop: u8) -> io::Result<()> {
+fn print_cache(o: &mut impl Write, instr: Instr) -> io::Result<()> {
+    write!(o, "CACHE 0x{:x}, 0x{:x}(", instr.rt().0, instr.immi())?;
+    print_gp_reg(o, instr.base())?;
+    write!(o, ")")?;
+    Ok(())
+}
+fn print_copz(o: &mut impl Write, instr: Instr, cop: u8) -> io::Result<()> {
+    if cop == 0 {
+        match instr.funct() {
+            OP_C0_TLBR => return write!(o, "TLBR"),
+            OP_C0_TLBWI => return write!(o, "TLBWI"),
+            OP_C0_TLBWR => return write!(o, "TLBWR"),
+            OP_C0_TLBP => return write!(o, "TLBP"),
+            OP_C0_ERET => return write!(o, "ERET"),
+            _ => (),
+        }
+    }
     write!(o, "COP{cop}")?;
     Ok(())
 }
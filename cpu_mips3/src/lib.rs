@@ -1,4 +1,5 @@
 pub mod core;
 pub mod instruction;
+pub mod rsp;
 pub mod vr4300;
 pub mod word;
@@ -0,0 +1,465 @@
+use crate::{
+    core::{MipsCore, MipsErr, MipsResult, RawCore},
+    instruction::{Instr, Reg},
+};
+
+mod cop2;
+
+pub use cop2::{Cop2, Vector};
+
+
+/// The scalar unit of the RCP's signal processor.
+///
+/// It is a 32-bit MIPS core without multiplication, division, 64-bit operations, likely branches or exceptions.
+/// Code runs from IMEM, and loads and stores only reach DMEM, wrapping around at its end.
+/// Its COP0 registers are the SP and DP registers of the RCP, and COP2 is the vector unit.
+///
+/// Instructions the RSP lacks stop emulation with an error, since well-behaved microcode never uses them.
+pub struct RspCore {
+    pc: u32,
+    gp: [i32; 31],
+    branch: Option<u32>,
+    cop2: Cop2,
+    /// Work an instruction has to hand to the bus, which the decoder does not pass to COP0 operations.
+    deferred: Option<Deferred>,
+}
+impl RspCore {
+    pub fn init() -> Self {
+        Self {
+            pc: 0,
+            gp: [0; 31],
+            branch: None,
+            cop2: Cop2::init(),
+            deferred: None,
+        }
+    }
+
+    /// Executes the instruction at the PC the bus holds, and writes back the address of the next one.
+    pub fn step_forward(&mut self, bus: &mut impl RspBus) -> Result<(), MipsErr> {
+        let branch = self.branch.take();
+        self.pc = bus.pc() & PC_MASK;
+        let instr = Instr(bus.read_imem(self.pc));
+
+        if let Err(Some(e)) = self.do_instruction(instr, bus) {
+            return Err(e);
+        }
+        match self.deferred.take() {
+            Some(Deferred::Break) => bus.on_break(),
+            Some(Deferred::ReadCop0 { rt, rd }) => {
+                let value = bus.read_cop0(rd)?;
+                self.write_gp(rt, value);
+            }
+            Some(Deferred::WriteCop0 { rd, value }) => bus.write_cop0(rd, value)?,
+            None => (),
+        }
+        bus.set_pc(branch.unwrap_or(self.pc.wrapping_add(4)) & PC_MASK);
+        Ok(())
+    }
+
+    pub fn gp(&self, reg: Reg) -> u32 {
+        match reg.0 {
+            0 => 0,
+            r => self.gp[r as usize - 1] as u32,
+        }
+    }
+    pub fn cop2(&self) -> &Cop2 {
+        &self.cop2
+    }
+    fn write_gp(&mut self, reg: Reg, value: u32) {
+        if reg.0 != 0 {
+            self.gp[reg.0 as usize - 1] = value as i32;
+        }
+    }
+
+    fn mem_addr(&self, instr: Instr) -> MipsResult<u32> {
+        let base = self.get_reg_u32(instr.base())?;
+        Ok(base.wrapping_add(instr.immi() as i32 as u32) & DMEM_MASK)
+    }
+    /// Reads `bytes` bytes big endian, wrapping around the end of DMEM.
+    fn read_dmem(&self, addr: u32, bytes: u32, bus: &mut impl RspBus) -> u32 {
+        (0..bytes).fold(0, |value, i| value << 8 | bus.read_dmem((addr + i) & DMEM_MASK) as u32)
+    }
+    fn write_dmem(&self, addr: u32, bytes: u32, value: u32, bus: &mut impl RspBus) {
+        for i in 0..bytes {
+            let byte = (value >> ((bytes - 1 - i) * 8)) as u8;
+            bus.write_dmem((addr + i) & DMEM_MASK, byte);
+        }
+    }
+
+    fn jump_target(&self, instr: Instr) -> u32 {
+        instr.jump_offset() << 2
+    }
+    /// Stores the return address of the current branch, which is the instruction after its delay slot.
+    fn link(&mut self, reg: Reg) -> MipsResult<()> {
+        self.set_reg_u32(reg, self.pc.wrapping_add(8) & PC_MASK)
+    }
+}
+
+/// The parts of the RCP the RSP is wired to.
+pub trait RspBus {
+    /// The program counter lives in the SP registers, so the CPU can set it while the RSP is halted.
+    fn pc(&self) -> u32;
+    fn set_pc(&mut self, pc: u32);
+
+    /// Reads an instruction from IMEM, by its address within IMEM.
+    fn read_imem(&mut self, addr: u32) -> u32;
+    fn read_dmem(&mut self, addr: u32) -> u8;
+    fn write_dmem(&mut self, addr: u32, value: u8);
+
+    /// Reads a COP0 register. Registers 0 to 7 are the SP registers, 8 to 15 the DP command registers.
+    fn read_cop0(&mut self, reg: u8) -> Result<u32, MipsErr>;
+    fn write_cop0(&mut self, reg: u8, value: u32) -> Result<(), MipsErr>;
+
+    /// Called when the RSP executes BREAK.
+    fn on_break(&mut self);
+}
+
+enum Deferred {
+    Break,
+    ReadCop0 { rt: Reg, rd: u8 },
+    WriteCop0 { rd: u8, value: u32 },
+}
+
+fn missing<R>(instr: Instr) -> MipsResult<R> {
+    Err(Some(MipsErr::new(format!("the RSP has no instruction {:0>8x}", instr.0))))
+}
+
+impl RawCore for RspCore {
+    fn do_bczf(&mut self, instr: Instr, _likely: bool, _cop: u8) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_bczt(&mut self, instr: Instr, _likely: bool, _cop: u8) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_break(&mut self, _instr: Instr) -> MipsResult<()> {
+        self.deferred = Some(Deferred::Break);
+        Ok(())
+    }
+    fn do_cfcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
+        match cop {
+            2 => self.do_cfc2(instr),
+            _ => missing(instr),
+        }
+    }
+    fn do_copz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
+        match cop {
+            2 => self.do_cop2_op(instr),
+            _ => missing(instr),
+        }
+    }
+    fn do_ctcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
+        match cop {
+            2 => self.do_ctc2(instr),
+            _ => missing(instr),
+        }
+    }
+    fn do_dmfcz(&mut self, instr: Instr, _cop: u8) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_dmtcz(&mut self, instr: Instr, _cop: u8) -> MipsResult<()> {
+        missing(instr)
+    }
+
+    fn do_j(&mut self, instr: Instr) -> MipsResult<()> {
+        self.branch = Some(self.jump_target(instr));
+        Ok(())
+    }
+    fn do_jal(&mut self, instr: Instr) -> MipsResult<()> {
+        self.link(Reg(31))?;
+        self.branch = Some(self.jump_target(instr));
+        Ok(())
+    }
+    fn do_jarl(&mut self, instr: Instr) -> MipsResult<()> {
+        let target = self.get_reg_u32(instr.rs())?;
+        self.link(instr.rd())?;
+        self.branch = Some(target);
+        Ok(())
+    }
+    fn do_jr(&mut self, instr: Instr) -> MipsResult<()> {
+        self.branch = Some(self.get_reg_u32(instr.rs())?);
+        Ok(())
+    }
+
+    fn do_mfcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
+        match cop {
+            0 => {
+                self.deferred = Some(Deferred::ReadCop0 {
+                    rt: instr.rt(),
+                    rd: instr.rd().0 & 15,
+                });
+                Ok(())
+            }
+            2 => self.do_mfc2(instr),
+            _ => missing(instr),
+        }
+    }
+    fn do_mtcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
+        match cop {
+            0 => {
+                let value = self.get_reg_u32(instr.rt())?;
+                self.deferred = Some(Deferred::WriteCop0 {
+                    rd: instr.rd().0 & 15,
+                    value,
+                });
+                Ok(())
+            }
+            2 => self.do_mtc2(instr),
+            _ => missing(instr),
+        }
+    }
+    fn do_sync(&mut self, instr: Instr) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_syscall(&mut self, instr: Instr) -> MipsResult<()> {
+        missing(instr)
+    }
+
+    fn do_branch_and_link(&mut self, instr: Instr, taken: bool, likely: bool) -> MipsResult<()> {
+        // The link register is written whether or not the branch is taken
+        self.link(Reg(31))?;
+        self.do_branch(instr, taken, likely)
+    }
+    fn do_branch(&mut self, instr: Instr, taken: bool, likely: bool) -> MipsResult<()> {
+        if likely {
+            return missing(instr);
+        }
+        if taken {
+            let offset = (instr.branch_offset() as i32) << 2;
+            self.branch = Some(self.pc.wrapping_add(4).wrapping_add_signed(offset));
+        }
+        Ok(())
+    }
+
+    fn get_reg_i64(&self, reg: Reg) -> MipsResult<i64> {
+        Ok(self.gp(reg) as i32 as i64)
+    }
+    fn set_reg_i64(&mut self, reg: Reg, to: i64) -> MipsResult<()> {
+        self.write_gp(reg, to as u32);
+        Ok(())
+    }
+
+    fn set_lo_i64(&mut self, _to: i64) -> MipsResult<()> {
+        Err(Some(MipsErr::new("the RSP has no LO register")))
+    }
+    fn set_hi_i64(&mut self, _to: i64) -> MipsResult<()> {
+        Err(Some(MipsErr::new("the RSP has no HI register")))
+    }
+    fn get_lo_natural(&mut self) -> MipsResult<i64> {
+        Err(Some(MipsErr::new("the RSP has no LO register")))
+    }
+    fn get_hi_natural(&mut self) -> MipsResult<i64> {
+        Err(Some(MipsErr::new("the RSP has no HI register")))
+    }
+
+    fn is_64_bit_mode(&self) -> bool {
+        false
+    }
+
+    fn reserved_instruction(&mut self, instr: Instr) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn integer_overflow(&mut self, _instr: Instr) -> MipsResult<()> {
+        // Without exceptions, ADD and ADDI simply wrap around like their unsigned versions
+        Ok(())
+    }
+    fn trap(&mut self, instr: Instr) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn dword_operation(&mut self, instr: Instr) -> MipsResult<()> {
+        missing(instr)
+    }
+}
+impl<T: RspBus> MipsCore<T> for RspCore {
+    fn do_cache(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+
+    fn do_lb(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
+        let addr = self.mem_addr(instr)?;
+        let value = self.read_dmem(addr, 1, bus);
+        self.set_reg_i32(instr.rt(), value as i8 as i32)
+    }
+    fn do_lbu(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
+        let addr = self.mem_addr(instr)?;
+        let value = self.read_dmem(addr, 1, bus);
+        self.set_reg_u32(instr.rt(), value)
+    }
+    fn do_lh(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
+        let addr = self.mem_addr(instr)?;
+        let value = self.read_dmem(addr, 2, bus);
+        self.set_reg_i32(instr.rt(), value as i16 as i32)
+    }
+    fn do_lhu(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
+        let addr = self.mem_addr(instr)?;
+        let value = self.read_dmem(addr, 2, bus);
+        self.set_reg_u32(instr.rt(), value)
+    }
+    fn do_lw(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
+        let addr = self.mem_addr(instr)?;
+        let value = self.read_dmem(addr, 4, bus);
+        self.set_reg_u32(instr.rt(), value)
+    }
+    /// With 32-bit registers, there is nothing to zero-extend into.
+    fn do_lwu(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
+        self.do_lw(instr, bus)
+    }
+    fn do_sb(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
+        let addr = self.mem_addr(instr)?;
+        let value = self.get_reg_u32(instr.rt())?;
+        self.write_dmem(addr, 1, value, bus);
+        Ok(())
+    }
+    fn do_sh(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
+        let addr = self.mem_addr(instr)?;
+        let value = self.get_reg_u32(instr.rt())?;
+        self.write_dmem(addr, 2, value, bus);
+        Ok(())
+    }
+    fn do_sw(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
+        let addr = self.mem_addr(instr)?;
+        let value = self.get_reg_u32(instr.rt())?;
+        self.write_dmem(addr, 4, value, bus);
+        Ok(())
+    }
+
+    fn do_ld(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_ldcz(&mut self, instr: Instr, _cop: u8, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_ldl(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_ldr(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_ll(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_lld(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_lwcz(&mut self, instr: Instr, cop: u8, bus: &mut T) -> MipsResult<()> {
+        match cop {
+            2 => self.do_lwc2(instr, bus),
+            _ => missing(instr),
+        }
+    }
+    fn do_lwl(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_lwr(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_sc(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_scd(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_sd(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_sdcz(&mut self, instr: Instr, _cop: u8, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_sdl(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_sdr(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_swcz(&mut self, instr: Instr, cop: u8, bus: &mut T) -> MipsResult<()> {
+        match cop {
+            2 => self.do_swc2(instr, bus),
+            _ => missing(instr),
+        }
+    }
+    fn do_swl(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+    fn do_swr(&mut self, instr: Instr, _bus: &mut T) -> MipsResult<()> {
+        missing(instr)
+    }
+}
+
+const PC_MASK: u32 = 0xFFC;
+const DMEM_MASK: u32 = 0xFFF;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a program from IMEM, shared with the vector unit tests.
+    pub(super) struct TestBus {
+        pc: u32,
+        imem: Vec<u32>,
+        pub(super) dmem: [u8; 0x1000],
+    }
+    impl TestBus {
+        pub(super) fn with_program(program: &[u32]) -> Self {
+            Self {
+                pc: 0,
+                imem: program.to_vec(),
+                dmem: [0; 0x1000],
+            }
+        }
+    }
+    impl RspBus for TestBus {
+        fn pc(&self) -> u32 {
+            self.pc
+        }
+        fn set_pc(&mut self, pc: u32) {
+            self.pc = pc;
+        }
+        fn read_imem(&mut self, addr: u32) -> u32 {
+            self.imem.get(addr as usize / 4).copied().unwrap_or(0)
+        }
+        fn read_dmem(&mut self, addr: u32) -> u8 {
+            self.dmem[addr as usize]
+        }
+        fn write_dmem(&mut self, addr: u32, value: u8) {
+            self.dmem[addr as usize] = value;
+        }
+        fn read_cop0(&mut self, _reg: u8) -> Result<u32, MipsErr> {
+            Ok(0)
+        }
+        fn write_cop0(&mut self, _reg: u8, _value: u32) -> Result<(), MipsErr> {
+            Ok(())
+        }
+        fn on_break(&mut self) {}
+    }
+
+    fn run(program: &[u32]) -> (RspCore, Result<(), MipsErr>) {
+        let mut rsp = RspCore::init();
+        let mut bus = TestBus::with_program(program);
+        let result = (0..program.len()).try_for_each(|_| rsp.step_forward(&mut bus));
+        (rsp, result)
+    }
+
+    #[test]
+    fn sub_and_subu() {
+        let (rsp, result) = run(&[
+            0x24010005, // addiu at, zero, 5
+            0x24030007, // addiu v1, zero, 7
+            0x00231023, // subu v0, at, v1
+            0x00612022, // sub a0, v1, at
+            0x3C058000, // lui a1, 0x8000
+            0x00A13022, // sub a2, a1, at
+        ]);
+        assert!(result.is_ok(), "{result:?}");
+        assert_eq!(rsp.gp(Reg(2)), 0xFFFF_FFFE);
+        assert_eq!(rsp.gp(Reg(4)), 2);
+        // Overflow can not trap on the RSP
+        assert_eq!(rsp.gp(Reg(6)), 0x7FFF_FFFB);
+    }
+
+    #[test]
+    fn doubleword_operations_are_missing() {
+        let (_, result) = run(&[
+            0x0023102F, // dsubu v0, at, v1
+        ]);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,919 @@
+use crate::{
+    core::{MipsErr, MipsResult, RawCore},
+    instruction::{
+        Instr, Reg, OP_C2_MEM_BV, OP_C2_MEM_DV, OP_C2_MEM_FV, OP_C2_MEM_HV, OP_C2_MEM_LV,
+        OP_C2_MEM_PV, OP_C2_MEM_QV, OP_C2_MEM_RV, OP_C2_MEM_SV, OP_C2_MEM_TV, OP_C2_MEM_UV,
+        OP_C2_MEM_WV, OP_C2_VABS, OP_C2_VADD, OP_C2_VADDC, OP_C2_VAND, OP_C2_VCH, OP_C2_VCL,
+        OP_C2_VCR, OP_C2_VEQ, OP_C2_VGE, OP_C2_VLT, OP_C2_VMACF, OP_C2_VMACQ, OP_C2_VMACU,
+        OP_C2_VMADH, OP_C2_VMADL, OP_C2_VMADM, OP_C2_VMADN, OP_C2_VMOV, OP_C2_VMRG, OP_C2_VMUDH,
+        OP_C2_VMUDL, OP_C2_VMUDM, OP_C2_VMUDN, OP_C2_VMULF, OP_C2_VMULQ, OP_C2_VMULU,
+        OP_C2_VNAND, OP_C2_VNE, OP_C2_VNOP, OP_C2_VNOR, OP_C2_VNULL, OP_C2_VNXOR, OP_C2_VOR,
+        OP_C2_VRCP, OP_C2_VRCPH, OP_C2_VRCPL, OP_C2_VRNDN, OP_C2_VRNDP, OP_C2_VRSQ,
+        OP_C2_VRSQH, OP_C2_VRSQL, OP_C2_VSAR, OP_C2_VSUB, OP_C2_VSUBC, OP_C2_VXOR,
+    },
+};
+
+use super::{RspBus, RspCore, DMEM_MASK};
+
+/// The vector unit: 32 registers of eight 16-bit elements, a 48-bit accumulator per element,
+/// the flag registers and the state the two-step divide instructions pass along.
+pub struct Cop2 {
+    vpr: [Vector; 32],
+    acc: [u64; 8],
+    /// Bit n holds the carry of element n, bit 8 + n whether it was not equal.
+    vco: u16,
+    /// Bit n holds the compare result of element n, bit 8 + n its clip result.
+    vcc: u16,
+    /// Bit n is set where VCH found element n to be exactly one less than the negated clip value.
+    vce: u8,
+    div_in: u16,
+    div_out: u16,
+    div_dp: bool,
+}
+impl Cop2 {
+    pub fn init() -> Self {
+        Self {
+            vpr: [Vector::default(); 32],
+            acc: [0; 8],
+            vco: 0,
+            vcc: 0,
+            vce: 0,
+            div_in: 0,
+            div_out: 0,
+            div_dp: false,
+        }
+    }
+
+    pub fn vector(&self, reg: Reg) -> Vector {
+        self.vpr[reg.0 as usize & 31]
+    }
+    /// The 48-bit accumulator of one element.
+    pub fn accumulator(&self, element: usize) -> u64 {
+        self.acc[element & 7]
+    }
+
+    /// Reads a flag register as CFC2 does: VCO, VCC, then VCE for both remaining numbers.
+    fn control(&self, reg: u8) -> u16 {
+        match reg & 3 {
+            0 => self.vco,
+            1 => self.vcc,
+            _ => self.vce as u16,
+        }
+    }
+    fn set_control(&mut self, reg: u8, value: u16) {
+        match reg & 3 {
+            0 => self.vco = value,
+            1 => self.vcc = value,
+            _ => self.vce = value as u8,
+        }
+    }
+
+    fn acc_signed(&self, n: usize) -> i64 {
+        (self.acc[n] << 16) as i64 >> 16
+    }
+    fn set_acc(&mut self, n: usize, value: i64) {
+        self.acc[n] = value as u64 & ACC_MASK;
+    }
+    fn set_acc_low(&mut self, n: usize, value: u16) {
+        self.acc[n] = self.acc[n] & !0xFFFF | value as u64;
+    }
+    fn set_acc_low_all(&mut self, value: Vector) {
+        for n in 0..8 {
+            self.set_acc_low(n, value.0[n]);
+        }
+    }
+
+    fn multiply(&mut self, op: VectorOp, accumulate: bool, product: fn(u16, u16) -> i64, clamp: fn(u64) -> u16) {
+        let (vs, vt) = self.operands(op);
+        let mut vd = Vector::default();
+        for n in 0..8 {
+            let base = if accumulate { self.acc_signed(n) } else { 0 };
+            self.set_acc(n, base + product(vs.0[n], vt.0[n]));
+            vd.0[n] = clamp(self.acc[n]);
+        }
+        self.vpr[op.vd] = vd;
+    }
+    fn vmulq(&mut self, op: VectorOp) {
+        let (vs, vt) = self.operands(op);
+        let mut vd = Vector::default();
+        for n in 0..8 {
+            let mut product = vs.0[n] as i16 as i32 * vt.0[n] as i16 as i32;
+            if product < 0 {
+                product += 31;
+            }
+            self.set_acc(n, (product as i64) << 16);
+            vd.0[n] = clamp_i16(product >> 1) & !15;
+        }
+        self.vpr[op.vd] = vd;
+    }
+    fn vmacq(&mut self, op: VectorOp) {
+        let mut vd = Vector::default();
+        for n in 0..8 {
+            let mut product = (self.acc[n] >> 16) as i32;
+            if product & 1 << 5 == 0 {
+                if product < 0 {
+                    product += 32;
+                }
+                else if product >= 32 {
+                    product -= 32;
+                }
+            }
+            self.acc[n] = (product as u32 as u64) << 16 | self.acc[n] & 0xFFFF;
+            vd.0[n] = clamp_i16(product >> 1) & !15;
+        }
+        self.vpr[op.vd] = vd;
+    }
+    /// VRNDP and VRNDN round the accumulator towards or away from zero by adding vt,
+    /// shifted up by 16 bits when the vs field is odd. The vs field is not a register here.
+    fn round(&mut self, op: VectorOp, positive: bool) {
+        let vt = self.vpr[op.vt].select(op.e);
+        let mut vd = Vector::default();
+        for n in 0..8 {
+            let mut product = vt.0[n] as i16 as i64;
+            if op.vs & 1 != 0 {
+                product <<= 16;
+            }
+            let acc = self.acc_signed(n);
+            if (acc >= 0) == positive {
+                self.set_acc(n, acc + product);
+            }
+            vd.0[n] = clamp_signed(self.acc[n]);
+        }
+        self.vpr[op.vd] = vd;
+    }
+
+    /// VADD and VSUB fold in the carry left by VADDC and VSUBC, and clear it.
+    fn add(&mut self, op: VectorOp, subtract: bool) {
+        let (vs, vt) = self.operands(op);
+        let mut vd = Vector::default();
+        for n in 0..8 {
+            let carry = (self.vco >> n & 1) as i32;
+            let (s, t) = (vs.0[n] as i16 as i32, vt.0[n] as i16 as i32);
+            let result = if subtract { s - t - carry } else { s + t + carry };
+            self.set_acc_low(n, result as u16);
+            vd.0[n] = clamp_i16(result);
+        }
+        self.vco = 0;
+        self.vpr[op.vd] = vd;
+    }
+    fn add_carry(&mut self, op: VectorOp, subtract: bool) {
+        let (vs, vt) = self.operands(op);
+        let mut vd = Vector::default();
+        let mut vco = 0;
+        for n in 0..8 {
+            let (s, t) = (vs.0[n] as i32, vt.0[n] as i32);
+            let result = if subtract { s - t } else { s + t };
+            let carry = if subtract { result < 0 } else { result > 0xFFFF };
+            let not_equal = subtract && result != 0;
+            vco |= (carry as u16) << n | (not_equal as u16) << (n + 8);
+            self.set_acc_low(n, result as u16);
+            vd.0[n] = result as u16;
+        }
+        self.vco = vco;
+        self.vpr[op.vd] = vd;
+    }
+    fn vabs(&mut self, op: VectorOp) {
+        let (vs, vt) = self.operands(op);
+        let mut vd = Vector::default();
+        for n in 0..8 {
+            let (s, t) = (vs.0[n] as i16, vt.0[n] as i16);
+            let (acc, result) = match s {
+                ..0 if t == i16::MIN => (i16::MIN, i16::MAX),
+                ..0 => (-t, -t),
+                0 => (0, 0),
+                _ => (t, t),
+            };
+            self.set_acc_low(n, acc as u16);
+            vd.0[n] = result as u16;
+        }
+        self.vpr[op.vd] = vd;
+    }
+    fn vsar(&mut self, op: VectorOp) {
+        let shift = match op.e {
+            8 => 32,
+            9 => 16,
+            10 => 0,
+            _ => {
+                self.vpr[op.vd] = Vector::default();
+                return;
+            }
+        };
+        let mut vd = Vector::default();
+        for n in 0..8 {
+            vd.0[n] = (self.acc[n] >> shift) as u16;
+        }
+        self.vpr[op.vd] = vd;
+    }
+
+    /// VLT, VEQ, VNE and VGE set the compare flags from `compare`, which also sees the carry and
+    /// not-equal flags of element n, and select vs where the flag is set and vt elsewhere.
+    fn compare(&mut self, op: VectorOp, compare: fn(i16, i16, bool, bool) -> bool) {
+        let (vs, vt) = self.operands(op);
+        let mut vcc = 0;
+        for n in 0..8 {
+            let carry = self.vco >> n & 1 != 0;
+            let not_equal = self.vco >> (n + 8) & 1 != 0;
+            let flag = compare(vs.0[n] as i16, vt.0[n] as i16, carry, not_equal);
+            vcc |= (flag as u16) << n;
+        }
+        self.vcc = vcc;
+        self.vco = 0;
+        self.merge(op, vs, vt);
+    }
+    fn vmrg(&mut self, op: VectorOp) {
+        let (vs, vt) = self.operands(op);
+        self.vco = 0;
+        self.merge(op, vs, vt);
+    }
+    fn merge(&mut self, op: VectorOp, vs: Vector, vt: Vector) {
+        let mut vd = Vector::default();
+        for n in 0..8 {
+            vd.0[n] = if self.vcc >> n & 1 != 0 { vs.0[n] } else { vt.0[n] };
+        }
+        self.set_acc_low_all(vd);
+        self.vpr[op.vd] = vd;
+    }
+
+    /// VCL finishes a double precision clip that VCH started on the upper halves.
+    fn vcl(&mut self, op: VectorOp) {
+        let (vs, vt) = self.operands(op);
+        let mut vd = Vector::default();
+        for n in 0..8 {
+            let (s, t) = (vs.0[n], vt.0[n]);
+            let carry = self.vco >> n & 1 != 0;
+            let not_equal = self.vco >> (n + 8) & 1 != 0;
+            let mut le = self.vcc >> n & 1 != 0;
+            let mut ge = self.vcc >> (n + 8) & 1 != 0;
+
+            if carry {
+                if !not_equal {
+                    let (sum, overflow) = s.overflowing_add(t);
+                    le = if self.vce >> n & 1 != 0 { sum == 0 || !overflow } else { sum == 0 && !overflow };
+                }
+                vd.0[n] = if le { t.wrapping_neg() } else { s };
+            }
+            else {
+                if !not_equal {
+                    ge = s >= t;
+                }
+                vd.0[n] = if ge { t } else { s };
+            }
+            self.vcc = self.vcc & !(0x101 << n) | (le as u16) << n | (ge as u16) << (n + 8);
+        }
+        self.vco = 0;
+        self.vce = 0;
+        self.set_acc_low_all(vd);
+        self.vpr[op.vd] = vd;
+    }
+    fn vch(&mut self, op: VectorOp) {
+        let (vs, vt) = self.operands(op);
+        let mut vd = Vector::default();
+        let (mut vcc, mut vco, mut vce) = (0, 0, 0);
+        for n in 0..8 {
+            let (s, t) = (vs.0[n] as i16, vt.0[n] as i16);
+            let sign = (s ^ t) < 0;
+            let (le, ge, result) = if sign {
+                let sum = s.wrapping_add(t);
+                vce |= ((sum == -1) as u8) << n;
+                (sum <= 0, t < 0, if sum <= 0 { t.wrapping_neg() } else { s })
+            }
+            else {
+                let difference = s.wrapping_sub(t);
+                (t < 0, difference >= 0, if difference >= 0 { t } else { s })
+            };
+            let equal = if sign { s.wrapping_add(t) == 0 } else { s == t };
+            let not_equal = !equal && s as u16 != !(t as u16);
+            vcc |= (le as u16) << n | (ge as u16) << (n + 8);
+            vco |= (sign as u16) << n | (not_equal as u16) << (n + 8);
+            vd.0[n] = result as u16;
+        }
+        self.vcc = vcc;
+        self.vco = vco;
+        self.vce = vce;
+        self.set_acc_low_all(vd);
+        self.vpr[op.vd] = vd;
+    }
+    /// VCR clips against the one's complement of vt, for single precision clipping.
+    fn vcr(&mut self, op: VectorOp) {
+        let (vs, vt) = self.operands(op);
+        let mut vd = Vector::default();
+        let mut vcc = 0;
+        for n in 0..8 {
+            let (s, t) = (vs.0[n] as i16 as i32, vt.0[n] as i16 as i32);
+            let (le, ge, result) = if (s ^ t) < 0 {
+                let le = s + t < 0;
+                (le, t < 0, if le { !t } else { s })
+            }
+            else {
+                let ge = s - t >= 0;
+                (t < 0, ge, if ge { t } else { s })
+            };
+            vcc |= (le as u16) << n | (ge as u16) << (n + 8);
+            vd.0[n] = result as u16;
+        }
+        self.vcc = vcc;
+        self.vco = 0;
+        self.vce = 0;
+        self.set_acc_low_all(vd);
+        self.vpr[op.vd] = vd;
+    }
+
+    fn logical(&mut self, op: VectorOp, logic: fn(u16, u16) -> u16) {
+        let (vs, vt) = self.operands(op);
+        let mut vd = Vector::default();
+        for n in 0..8 {
+            vd.0[n] = logic(vs.0[n], vt.0[n]);
+        }
+        self.set_acc_low_all(vd);
+        self.vpr[op.vd] = vd;
+    }
+
+    /// VRCP, VRSQ and their low halves. The vs field selects the element of vd that receives the result.
+    /// A preceding VRCPH or VRSQH supplies the upper half of a 32-bit input to the low variants.
+    fn divide(&mut self, op: VectorOp, low: bool, square_root: bool) {
+        let vt = self.vpr[op.vt];
+        let element = vt.0[op.e as usize & 7];
+        let input = if low && self.div_dp { (self.div_in as i32) << 16 | element as i32 } else { element as i16 as i32 };
+
+        let mask = input >> 31;
+        let mut data = input ^ mask;
+        if input > -32768 {
+            data = data.wrapping_sub(mask);
+        }
+        let result = if data == 0 {
+            0x7FFF_FFFF
+        }
+        else if input == -32768 {
+            0xFFFF_0000
+        }
+        else {
+            let data = data as u32;
+            let shift = data.leading_zeros();
+            let index = ((data as u64) << shift & 0x7FC0_0000) >> 22;
+            let (value, shift) = if square_root {
+                (INVERSE_SQUARE_ROOTS[(index & 0x1FE | shift as u64 & 1) as usize], (31 - shift) >> 1)
+            }
+            else {
+                (RECIPROCALS[index as usize], 31 - shift)
+            };
+            ((0x10000 | value as u32) << 14 >> shift) ^ mask as u32
+        };
+
+        self.div_dp = false;
+        self.div_out = (result >> 16) as u16;
+        self.set_acc_low_all(vt.select(op.e));
+        self.vpr[op.vd].0[op.vs & 7] = result as u16;
+    }
+    fn divide_high(&mut self, op: VectorOp) {
+        let vt = self.vpr[op.vt];
+        self.set_acc_low_all(vt.select(op.e));
+        self.div_dp = true;
+        self.div_in = vt.0[op.e as usize & 7];
+        self.vpr[op.vd].0[op.vs & 7] = self.div_out;
+    }
+    fn vmov(&mut self, op: VectorOp) {
+        let vt = self.vpr[op.vt].select(op.e);
+        self.set_acc_low_all(vt);
+        let element = op.vs & 7;
+        self.vpr[op.vd].0[element] = vt.0[element];
+    }
+    /// The unassigned opcodes still run the adder: the accumulator receives vs + vt, vd is cleared.
+    fn reserved(&mut self, op: VectorOp) {
+        let (vs, vt) = self.operands(op);
+        for n in 0..8 {
+            self.set_acc_low(n, vs.0[n].wrapping_add(vt.0[n]));
+        }
+        self.vpr[op.vd] = Vector::default();
+    }
+
+    fn operands(&self, op: VectorOp) -> (Vector, Vector) {
+        (self.vpr[op.vs], self.vpr[op.vt].select(op.e))
+    }
+}
+
+/// A vector register, element 0 first. Its bytes are numbered big endian, starting at the top of element 0.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Vector(pub [u16; 8]);
+impl Vector {
+    pub fn byte(&self, index: usize) -> u8 {
+        let element = self.0[(index / 2) & 7];
+        if index.is_multiple_of(2) { (element >> 8) as u8 } else { element as u8 }
+    }
+    pub fn set_byte(&mut self, index: usize, value: u8) {
+        let element = &mut self.0[(index / 2) & 7];
+        *element = if index.is_multiple_of(2) {
+            *element & 0x00FF | (value as u16) << 8
+        }
+        else {
+            *element & 0xFF00 | value as u16
+        };
+    }
+
+    /// Applies the element field of a computational instruction to vt.
+    /// 0 and 1 leave it as is, 2 and 3 broadcast within pairs, 4 to 7 within halves and 8 to 15 broadcast a single element.
+    pub fn select(&self, e: u8) -> Self {
+        let e = e as usize;
+        let mut selected = Self::default();
+        for n in 0..8 {
+            let source = match e {
+                0..=1 => n,
+                2..=3 => n & !1 | e & 1,
+                4..=7 => n & !3 | e & 3,
+                _ => e & 7,
+            };
+            selected.0[n] = self.0[source];
+        }
+        selected
+    }
+}
+
+/// The fields of a computational vector instruction.
+#[derive(Copy, Clone, Debug)]
+struct VectorOp {
+    vd: usize,
+    vs: usize,
+    vt: usize,
+    e: u8,
+}
+impl VectorOp {
+    fn of(instr: Instr) -> Self {
+        Self {
+            vd: instr.sa() as usize,
+            vs: instr.rd().0 as usize,
+            vt: instr.rt().0 as usize,
+            e: (instr.0 >> 21 & 0xF) as u8,
+        }
+    }
+}
+
+impl RspCore {
+    /// The element field of the moves and of LWC2 and SWC2 sits in bits 7 to 10.
+    fn move_element(instr: Instr) -> usize {
+        (instr.0 >> 7 & 0xF) as usize
+    }
+
+    pub(super) fn do_mfc2(&mut self, instr: Instr) -> MipsResult<()> {
+        let e = Self::move_element(instr);
+        let vs = self.cop2.vector(instr.rd());
+        let value = (vs.byte(e) as u16) << 8 | vs.byte((e + 1) & 15) as u16;
+        self.set_reg_i32(instr.rt(), value as i16 as i32)
+    }
+    pub(super) fn do_mtc2(&mut self, instr: Instr) -> MipsResult<()> {
+        let e = Self::move_element(instr);
+        let value = self.get_reg_u32(instr.rt())?;
+        let vs = &mut self.cop2.vpr[instr.rd().0 as usize];
+        vs.set_byte(e, (value >> 8) as u8);
+        if e != 15 {
+            vs.set_byte(e + 1, value as u8);
+        }
+        Ok(())
+    }
+    pub(super) fn do_cfc2(&mut self, instr: Instr) -> MipsResult<()> {
+        let value = self.cop2.control(instr.rd().0);
+        self.set_reg_i32(instr.rt(), value as i16 as i32)
+    }
+    pub(super) fn do_ctc2(&mut self, instr: Instr) -> MipsResult<()> {
+        let value = self.get_reg_u32(instr.rt())?;
+        self.cop2.set_control(instr.rd().0, value as u16);
+        Ok(())
+    }
+
+    pub(super) fn do_cop2_op(&mut self, instr: Instr) -> MipsResult<()> {
+        let op = VectorOp::of(instr);
+        let vu = &mut self.cop2;
+        match instr.funct() {
+            OP_C2_VMULF => vu.multiply(op, false, |s, t| fraction(s, t) + 0x8000, clamp_signed),
+            OP_C2_VMULU => vu.multiply(op, false, |s, t| fraction(s, t) + 0x8000, clamp_unsigned),
+            OP_C2_VRNDP => vu.round(op, true),
+            OP_C2_VMULQ => vu.vmulq(op),
+            OP_C2_VMUDL => vu.multiply(op, false, low_product, clamp_low),
+            OP_C2_VMUDM => vu.multiply(op, false, mid_product, clamp_signed),
+            OP_C2_VMUDN => vu.multiply(op, false, |s, t| mid_product(t, s), clamp_low),
+            OP_C2_VMUDH => vu.multiply(op, false, high_product, clamp_signed),
+            OP_C2_VMACF => vu.multiply(op, true, fraction, clamp_signed),
+            OP_C2_VMACU => vu.multiply(op, true, fraction, clamp_unsigned),
+            OP_C2_VRNDN => vu.round(op, false),
+            OP_C2_VMACQ => vu.vmacq(op),
+            OP_C2_VMADL => vu.multiply(op, true, low_product, clamp_low),
+            OP_C2_VMADM => vu.multiply(op, true, mid_product, clamp_signed),
+            OP_C2_VMADN => vu.multiply(op, true, |s, t| mid_product(t, s), clamp_low),
+            OP_C2_VMADH => vu.multiply(op, true, high_product, clamp_signed),
+            OP_C2_VADD => vu.add(op, false),
+            OP_C2_VSUB => vu.add(op, true),
+            OP_C2_VABS => vu.vabs(op),
+            OP_C2_VADDC => vu.add_carry(op, false),
+            OP_C2_VSUBC => vu.add_carry(op, true),
+            OP_C2_VSAR => vu.vsar(op),
+            OP_C2_VLT => vu.compare(op, |s, t, carry, ne| s < t || s == t && carry && ne),
+            OP_C2_VEQ => vu.compare(op, |s, t, _, ne| s == t && !ne),
+            OP_C2_VNE => vu.compare(op, |s, t, _, ne| s != t || ne),
+            OP_C2_VGE => vu.compare(op, |s, t, carry, ne| s > t || s == t && !(carry && ne)),
+            OP_C2_VCL => vu.vcl(op),
+            OP_C2_VCH => vu.vch(op),
+            OP_C2_VCR => vu.vcr(op),
+            OP_C2_VMRG => vu.vmrg(op),
+            OP_C2_VAND => vu.logical(op, |s, t| s & t),
+            OP_C2_VNAND => vu.logical(op, |s, t| !(s & t)),
+            OP_C2_VOR => vu.logical(op, |s, t| s | t),
+            OP_C2_VNOR => vu.logical(op, |s, t| !(s | t)),
+            OP_C2_VXOR => vu.logical(op, |s, t| s ^ t),
+            OP_C2_VNXOR => vu.logical(op, |s, t| !(s ^ t)),
+            OP_C2_VRCP => vu.divide(op, false, false),
+            OP_C2_VRCPL => vu.divide(op, true, false),
+            OP_C2_VRCPH | OP_C2_VRSQH => vu.divide_high(op),
+            OP_C2_VMOV => vu.vmov(op),
+            OP_C2_VRSQ => vu.divide(op, false, true),
+            OP_C2_VRSQL => vu.divide(op, true, true),
+            OP_C2_VNOP | OP_C2_VNULL => (),
+            _ => vu.reserved(op),
+        }
+        Ok(())
+    }
+
+    pub(super) fn do_lwc2(&mut self, instr: Instr, bus: &mut impl RspBus) -> MipsResult<()> {
+        let (vt, e) = (instr.rt().0 as usize, Self::move_element(instr));
+        let op = instr.rd().0;
+        let addr = self.vector_addr(instr, op)?;
+        let mut read = |addr: u32| bus.read_dmem(addr & DMEM_MASK);
+        let vpr = &mut self.cop2.vpr;
+
+        match op {
+            OP_C2_MEM_BV | OP_C2_MEM_SV | OP_C2_MEM_LV | OP_C2_MEM_DV => {
+                let bytes = 1 << op;
+                for offset in e..(e + bytes).min(16) {
+                    vpr[vt].set_byte(offset, read(addr.wrapping_add((offset - e) as u32)));
+                }
+            }
+            OP_C2_MEM_QV => {
+                let end = (e + 16 - (addr & 15) as usize).min(16);
+                for offset in e..end {
+                    vpr[vt].set_byte(offset, read(addr.wrapping_add((offset - e) as u32)));
+                }
+            }
+            OP_C2_MEM_RV => {
+                let start = e + 16 - (addr & 15) as usize;
+                let aligned = addr & !15;
+                for offset in start..16 {
+                    vpr[vt].set_byte(offset, read(aligned + (offset - start) as u32));
+                }
+            }
+            OP_C2_MEM_PV | OP_C2_MEM_UV => {
+                let shift = if op == OP_C2_MEM_PV { 8 } else { 7 };
+                let (aligned, index) = (addr & !7, (addr & 7).wrapping_sub(e as u32));
+                for n in 0..8 {
+                    let byte = read(aligned + (index.wrapping_add(n as u32) & 15));
+                    vpr[vt].0[n] = (byte as u16) << shift;
+                }
+            }
+            OP_C2_MEM_HV => {
+                let (aligned, index) = (addr & !7, (addr & 7).wrapping_sub(e as u32));
+                for n in 0..8 {
+                    let byte = read(aligned + (index.wrapping_add(n as u32 * 2) & 15));
+                    vpr[vt].0[n] = (byte as u16) << 7;
+                }
+            }
+            OP_C2_MEM_FV => {
+                let (aligned, index) = (addr & !7, (addr & 7).wrapping_sub(e as u32));
+                let mut packed = Vector::default();
+                for n in 0..4 {
+                    let offset = index.wrapping_add(n as u32 * 4);
+                    packed.0[n] = (read(aligned + (offset & 15)) as u16) << 7;
+                    packed.0[n + 4] = (read(aligned + (offset.wrapping_add(8) & 15)) as u16) << 7;
+                }
+                for offset in e..(e + 8).min(16) {
+                    vpr[vt].set_byte(offset, packed.byte(offset));
+                }
+            }
+            // There is no LWV; the slot does nothing
+            OP_C2_MEM_WV => (),
+            OP_C2_MEM_TV => {
+                let begin = addr & !7;
+                let mut addr = begin + ((e as u32 + (addr & 8)) & 15);
+                let base = vt & !7;
+                let mut reg = e >> 1;
+                for n in 0..8 {
+                    for half in 0..2 {
+                        vpr[base + reg].set_byte(n * 2 + half, read(addr));
+                        addr += 1;
+                        if addr == begin + 16 {
+                            addr = begin;
+                        }
+                    }
+                    reg = (reg + 1) & 7;
+                }
+            }
+            _ => return Err(Some(MipsErr::new(format!("the RSP has no vector load {op:#x}")))),
+        }
+        Ok(())
+    }
+    pub(super) fn do_swc2(&mut self, instr: Instr, bus: &mut impl RspBus) -> MipsResult<()> {
+        let (vt, e) = (instr.rt().0 as usize, Self::move_element(instr));
+        let op = instr.rd().0;
+        let addr = self.vector_addr(instr, op)?;
+        let mut write = |addr: u32, value: u8| bus.write_dmem(addr & DMEM_MASK, value);
+        let vpr = &self.cop2.vpr;
+        let v = vpr[vt];
+
+        match op {
+            OP_C2_MEM_BV | OP_C2_MEM_SV | OP_C2_MEM_LV | OP_C2_MEM_DV => {
+                let bytes = 1 << op;
+                for offset in 0..bytes {
+                    write(addr.wrapping_add(offset as u32), v.byte((e + offset) & 15));
+                }
+            }
+            OP_C2_MEM_QV => {
+                for offset in 0..16 - (addr & 15) {
+                    write(addr + offset, v.byte((e + offset as usize) & 15));
+                }
+            }
+            OP_C2_MEM_RV => {
+                let base = 16 - (addr & 15) as usize;
+                let aligned = addr & !15;
+                for offset in 0..(addr & 15) as usize {
+                    write(aligned + offset as u32, v.byte((e + offset + base) & 15));
+                }
+            }
+            OP_C2_MEM_PV | OP_C2_MEM_UV => {
+                for n in 0..8 {
+                    let index = e + n;
+                    // SPV stores the top byte of each element where SUV stores bits 7 to 14, and the other way around past byte 7
+                    let high = (index & 15 < 8) == (op == OP_C2_MEM_PV);
+                    let value = if high { v.byte((index & 7) << 1) } else { (v.0[index & 7] >> 7) as u8 };
+                    write(addr.wrapping_add(n as u32), value);
+                }
+            }
+            OP_C2_MEM_HV => {
+                let (aligned, index) = (addr & !7, addr & 7);
+                for n in 0..8 {
+                    let byte = e + n * 2;
+                    let value = v.byte(byte & 15) << 1 | v.byte((byte + 1) & 15) >> 7;
+                    write(aligned + ((index + n as u32 * 2) & 15), value);
+                }
+            }
+            OP_C2_MEM_FV => {
+                let (aligned, base) = (addr & !7, addr & 7);
+                let elements: Option<[usize; 4]> = match e {
+                    0 | 15 => Some([0, 1, 2, 3]),
+                    1 => Some([6, 7, 4, 5]),
+                    4 => Some([1, 2, 3, 0]),
+                    5 => Some([7, 4, 5, 6]),
+                    8 => Some([4, 5, 6, 7]),
+                    11 => Some([3, 0, 1, 2]),
+                    12 => Some([5, 6, 7, 4]),
+                    _ => None,
+                };
+                for n in 0..4 {
+                    let value = elements.map_or(0, |elements| (v.0[elements[n]] >> 7) as u8);
+                    write(aligned + ((base + n as u32 * 4) & 15), value);
+                }
+            }
+            OP_C2_MEM_WV => {
+                let (aligned, base) = (addr & !7, addr & 7);
+                for n in 0..16 {
+                    write(aligned + ((base + n as u32) & 15), v.byte((e + n) & 15));
+                }
+            }
+            OP_C2_MEM_TV => {
+                let (aligned, mut base) = (addr & !7, (addr & 7).wrapping_sub(e as u32 & !1));
+                let mut element = 16 - (e & !1);
+                for reg in &vpr[vt & !7..(vt & !7) + 8] {
+                    for _ in 0..2 {
+                        write(aligned + (base & 15), reg.byte(element & 15));
+                        base = base.wrapping_add(1);
+                        element += 1;
+                    }
+                }
+            }
+            _ => return Err(Some(MipsErr::new(format!("the RSP has no vector store {op:#x}")))),
+        }
+        Ok(())
+    }
+
+    /// The offset of a vector load or store is a signed 7-bit field, scaled by the size of the access.
+    fn vector_addr(&self, instr: Instr, op: u8) -> MipsResult<u32> {
+        let scale = match op {
+            OP_C2_MEM_BV | OP_C2_MEM_SV | OP_C2_MEM_LV | OP_C2_MEM_DV => 1 << op,
+            OP_C2_MEM_PV | OP_C2_MEM_UV => 8,
+            _ => 16,
+        };
+        let offset = ((instr.0 << 25) as i32 >> 25) * scale;
+        let base = self.get_reg_u32(instr.base())?;
+        Ok(base.wrapping_add(offset as u32) & DMEM_MASK)
+    }
+}
+
+/// The signed product of two fractions, which keeps the binary point in place.
+fn fraction(s: u16, t: u16) -> i64 {
+    (s as i16 as i64 * t as i16 as i64) << 1
+}
+/// The upper half of the product of two unsigned low halves.
+fn low_product(s: u16, t: u16) -> i64 {
+    (s as i64 * t as i64) >> 16
+}
+/// A signed vs times an unsigned vt, as used by VMUDM and VMADM. VMUDN and VMADN swap the operands.
+fn mid_product(s: u16, t: u16) -> i64 {
+    s as i16 as i64 * t as i64
+}
+fn high_product(s: u16, t: u16) -> i64 {
+    (s as i16 as i64 * t as i16 as i64) << 16
+}
+
+fn clamp_i16(value: i32) -> u16 {
+    value.clamp(i16::MIN as i32, i16::MAX as i32) as u16
+}
+/// The middle of the accumulator, saturated to a signed 16-bit value.
+fn clamp_signed(acc: u64) -> u16 {
+    clamp_i16((acc >> 16) as u32 as i32)
+}
+/// The middle of the accumulator, saturated to the range VMULU and VMACU produce.
+fn clamp_unsigned(acc: u64) -> u16 {
+    match (acc >> 16) as u32 as i32 {
+        ..0 => 0,
+        0x8000.. => 0xFFFF,
+        value => value as u16,
+    }
+}
+/// The bottom of the accumulator, or its saturated value if the upper 32 bits do not fit in 16.
+fn clamp_low(acc: u64) -> u16 {
+    match (acc >> 16) as u32 as i32 {
+        ..-0x8000 => 0,
+        0x8000.. => 0xFFFF,
+        _ => acc as u16,
+    }
+}
+
+const ACC_MASK: u64 = 0xFFFF_FFFF_FFFF;
+
+/// The reciprocal ROM, holding the fraction of 1 / (1 + index / 512) in 16 bits below an implied leading one.
+const RECIPROCALS: [u16; 512] = reciprocals();
+/// The inverse square root ROM. Even entries hold 1 / sqrt(1 + index / 512), odd entries the same for
+/// a mantissa scaled by two, so the instruction can pick the half that matches the parity of the shift.
+const INVERSE_SQUARE_ROOTS: [u16; 512] = inverse_square_roots();
+
+const fn reciprocals() -> [u16; 512] {
+    let mut table = [0; 512];
+    let mut index = 0;
+    while index < 512 {
+        let value = ((1 << 34) / (index as u64 + 512) + 1) >> 8;
+        // 1 / 1 does not fit below the implied one, so the ROM saturates
+        table[index] = if value > 0x1FFFF { 0xFFFF } else { value as u16 };
+        index += 1;
+    }
+    table
+}
+const fn inverse_square_roots() -> [u16; 512] {
+    let mut table = [0; 512];
+    let mut index = 0;
+    while index < 512 {
+        let a = (index as u64 + 512) >> (index % 2);
+        // The largest b with a * b * b below 2^44
+        let b = ((1 << 44) - 1) / a;
+        table[index] = (b.isqrt() >> 1) as u16;
+        index += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsp::tests::TestBus;
+
+    /// Encodes a computational vector instruction.
+    fn vector(funct: u8, vd: u32, vs: u32, vt: u32, e: u32) -> u32 {
+        0x4A00_0000 | e << 21 | vt << 16 | vs << 11 | vd << 6 | funct as u32
+    }
+
+    /// Runs a program on an RSP whose vector registers start out as given.
+    fn run_on(bus: &mut TestBus, vectors: &[(usize, [u16; 8])], program: &[u32]) -> RspCore {
+        let mut rsp = RspCore::init();
+        for &(reg, elements) in vectors {
+            rsp.cop2.vpr[reg] = Vector(elements);
+        }
+        for _ in program {
+            rsp.step_forward(bus).unwrap();
+        }
+        rsp
+    }
+    fn run(vectors: &[(usize, [u16; 8])], program: &[u32]) -> RspCore {
+        run_on(&mut TestBus::with_program(program), vectors, program)
+    }
+    fn vpr(rsp: &RspCore, reg: u8) -> [u16; 8] {
+        rsp.cop2().vector(Reg(reg)).0
+    }
+
+    #[test]
+    fn vmulf_rounds_and_clamps() {
+        let rsp = run(
+            &[
+                (1, [0x4000, 0x8000, 0x7FFF, 0x0001, 0xC000, 0x0000, 0xFFFF, 0x2000]),
+                (2, [0x4000, 0x8000, 0x7FFF, 0x0001, 0x4000, 0x1234, 0x0001, 0x2000]),
+            ],
+            &[vector(OP_C2_VMULF, 3, 1, 2, 0)],
+        );
+        assert_eq!(vpr(&rsp, 3), [0x2000, 0x7FFF, 0x7FFE, 0x0000, 0xE000, 0x0000, 0x0000, 0x0800]);
+        // -1 * -1 overflows the result but not the accumulator
+        assert_eq!(rsp.cop2().accumulator(1), 0x0000_8000_8000);
+        assert_eq!(rsp.cop2().accumulator(4), 0xFFFF_E000_8000);
+    }
+
+    #[test]
+    fn vadd_folds_in_the_vaddc_carry() {
+        let rsp = run(
+            &[
+                (1, [0xFFFF, 0x8000, 0x0001, 0x0000, 0x7FFF, 0, 0, 0]),
+                (2, [0x0001, 0x8000, 0x0001, 0x0000, 0x0001, 0, 0, 0]),
+            ],
+            &[
+                vector(OP_C2_VADDC, 3, 1, 2, 0),
+                0x48410000, // cfc2 at, vco
+                vector(OP_C2_VADD, 4, 1, 2, 0),
+                0x48420000, // cfc2 v0, vco
+            ],
+        );
+        assert_eq!(vpr(&rsp, 3), [0x0000, 0x0000, 0x0002, 0x0000, 0x8000, 0, 0, 0]);
+        assert_eq!(rsp.gp(Reg(1)), 0b11);
+        assert_eq!(vpr(&rsp, 4), [0x0001, 0x8000, 0x0002, 0x0000, 0x7FFF, 0, 0, 0]);
+        assert_eq!(rsp.gp(Reg(2)), 0);
+    }
+
+    #[test]
+    fn vch_and_vcl_clip_double_precision() {
+        // Clips 5, 0x20, -0x20 and -5 against +-0x10, with the upper halves in v1 and v2
+        let rsp = run(
+            &[
+                (1, [0x0000, 0x0000, 0xFFFF, 0xFFFF, 0, 0, 0, 0]),
+                (2, [0x0000, 0x0000, 0x0000, 0x0000, 0, 0, 0, 0]),
+                (5, [0x0005, 0x0020, 0xFFE0, 0xFFFB, 0, 0, 0, 0]),
+                (6, [0x0010, 0x0010, 0x0010, 0x0010, 0, 0, 0, 0]),
+            ],
+            &[
+                vector(OP_C2_VCH, 3, 1, 2, 0),
+                0x48411000, // cfc2 at, vce
+                vector(OP_C2_VCL, 4, 5, 6, 0),
+                0x48420800, // cfc2 v0, vcc
+            ],
+        );
+        assert_eq!(rsp.gp(Reg(1)), 0b1100);
+        assert_eq!(vpr(&rsp, 4), [0x0005, 0x0010, 0xFFF0, 0xFFFB, 0, 0, 0, 0]);
+        assert_eq!(rsp.gp(Reg(2)), 0xF204_u16 as i16 as i32 as u32);
+    }
+
+    #[test]
+    fn the_rom_tables_match_the_hardware() {
+        assert_eq!(RECIPROCALS[..4], [0xFFFF, 0xFF00, 0xFE01, 0xFD04]);
+        assert_eq!(INVERSE_SQUARE_ROOTS[..2], [0x6A09, 0xFFFF]);
+    }
+
+    #[test]
+    fn reciprocals_and_square_roots() {
+        let rsp = run(
+            &[(1, [1, 2, 0xFFFF, 0, 4, 0x0001, 0x0000, 0])],
+            &[
+                // 1 / 1, then 1 / 0x10000 in double precision
+                vector(OP_C2_VRCP, 2, 0, 1, 8),
+                vector(OP_C2_VRCPH, 2, 1, 1, 13),
+                vector(OP_C2_VRCPL, 2, 2, 1, 14),
+                vector(OP_C2_VRCPH, 2, 3, 1, 8),
+                // 1 / 2, 1 / -1 and 1 / 0
+                vector(OP_C2_VRCP, 3, 0, 1, 9),
+                vector(OP_C2_VRCPH, 3, 1, 1, 8),
+                vector(OP_C2_VRCP, 3, 2, 1, 10),
+                vector(OP_C2_VRCPH, 3, 3, 1, 8),
+                vector(OP_C2_VRCP, 3, 4, 1, 11),
+                vector(OP_C2_VRCPH, 3, 5, 1, 8),
+                // 1 / sqrt(1), 1 / sqrt(2) and 1 / sqrt(4)
+                vector(OP_C2_VRSQ, 4, 0, 1, 8),
+                vector(OP_C2_VRSQH, 4, 1, 1, 8),
+                vector(OP_C2_VRSQ, 4, 2, 1, 9),
+                vector(OP_C2_VRSQH, 4, 3, 1, 8),
+                vector(OP_C2_VRSQ, 4, 4, 1, 12),
+                vector(OP_C2_VRSQH, 4, 5, 1, 8),
+            ],
+        );
+        assert_eq!(vpr(&rsp, 2)[..4], [0xC000, 0x7FFF, 0x7FFF, 0x0000]);
+        assert_eq!(vpr(&rsp, 3)[..6], [0xE000, 0x3FFF, 0x3FFF, 0x8000, 0xFFFF, 0x7FFF]);
+        assert_eq!(vpr(&rsp, 4)[..6], [0xC000, 0x7FFF, 0x4000, 0x5A82, 0xE000, 0x3FFF]);
+    }
+
+    #[test]
+    fn unaligned_quad_loads_and_stores() {
+        let program = [
+            0x24010013, // addiu at, zero, 0x13
+            0x24020043, // addiu v0, zero, 0x43
+            0xC8212000, // lqv v1[0], 0(at)
+            0xC8212801, // lrv v1[0], 16(at)
+            0xE8412000, // sqv v1[0], 0(v0)
+            0xE8412801, // srv v1[0], 16(v0)
+        ];
+        let mut bus = TestBus::with_program(&program);
+        bus.dmem = std::array::from_fn(|i| i as u8);
+        let rsp = run_on(&mut bus, &[], &program);
+
+        assert_eq!(vpr(&rsp, 1), [0x1314, 0x1516, 0x1718, 0x191A, 0x1B1C, 0x1D1E, 0x1F20, 0x2122]);
+        let stored: Vec<u8> = (0x13..0x23).collect();
+        assert_eq!(bus.dmem[0x43..0x53], stored);
+        // The bytes around the stored ones are untouched
+        assert_eq!((bus.dmem[0x42], bus.dmem[0x53]), (0x42, 0x53));
+    }
+}
@@ -1,15 +1,24 @@
 use std::ops::RangeInclusive;
 
-use cop0::{Config, Cop0, Mode, Status};
+use cache::{Cache, CacheOp, DCache, ICache};
+use cop0::{Cop0, Index, Mode};
+use cop1::Cop1;
+use tlb::{Tlb, TlbEntry, TlbLookup};
 use util::sext_32;
 
 use crate::{
     core::{MipsCore, MipsErr, MipsResult, RawCore},
-    instruction::{Instr, Reg},
+    instruction::{Instr, Reg, OP_C0_ERET, OP_C0_TLBP, OP_C0_TLBR, OP_C0_TLBWI, OP_C0_TLBWR},
     word::Word,
 };
 
+mod cache;
 mod cop0;
+mod cop1;
+mod exception;
+mod tlb;
+
+pub use exception::Exception;
 
 pub struct Vr4300 {
     cycle: u64,
@@ -18,7 +27,13 @@ pub struct Vr4300 {
     lohi: [i64; 2],
 
     branch: Option<u64>,
+    delay_slot: bool,
+    ll_bit: bool,
     cop0: Cop0,
+    cop1: Cop1,
+    tlb: Tlb,
+    icache: ICache,
+    dcache: DCache,
 }
 impl Vr4300 {
     pub fn init() -> Self {
@@ -29,16 +44,27 @@ impl Vr4300 {
             lohi: [0; 2],
 
             branch: None,
+            delay_slot: false,
+            ll_bit: false,
             cop0: Cop0::init(),
+            cop1: Cop1::init(),
+            tlb: Tlb::init(),
+            icache: ICache::init(),
+            dcache: DCache::init(),
         }
     }
     pub fn step_forward(&mut self, bus: &mut impl SysAd) -> Result<(), MipsErr> {
         fn inner(cpu: &mut Vr4300, bus: &mut impl SysAd) -> MipsResult<()> {
-            let instr = cpu.fetch(bus)?;
             let branch = cpu.branch.take();
+            cpu.delay_slot = branch.is_some();
+            if cpu.cop0.interrupt_pending() {
+                return cpu.take_exception(Exception::Interrupt);
+            }
+            let instr = cpu.fetch(bus)?;
+            cpu.cop0.tick_random();
             cpu.do_instruction(instr, bus)?;
 
-            cpu.pc = branch.unwrap_or(cpu.pc + 4);
+            cpu.pc = branch.unwrap_or(cpu.pc.wrapping_add(4));
             Ok(())
         }
 
@@ -46,9 +72,22 @@ impl Vr4300 {
             return Err(e)
         }
         self.cycle += 1;
+        // Count runs at half the pipeline clock
+        if self.cycle.is_multiple_of(2) {
+            self.cop0.tick_count();
+        }
         Ok(())
     }
 
+    /// Raises or lowers one of the external interrupt lines, which are visible as IP2 through IP6 in Cause.
+    pub fn set_interrupt(&mut self, line: u8, raised: bool) {
+        assert!((2..=6).contains(&line), "IP{line} is not an external interrupt line");
+        let bit = 1 << line;
+        let ip = self.cop0.cause.ip();
+        let ip = if raised { ip | bit } else { ip & !bit };
+        self.cop0.cause.set_ip(ip);
+    }
+
     pub fn cycle(&self) -> u64 {
         self.cycle
     }
@@ -57,10 +96,13 @@ impl Vr4300 {
     }
     pub fn translate_address_debug(&self, addr: u64) -> Option<TranslatedAddr> {
         let addr = if self.is_64_bit_mode() { addr } else { sext_32(addr as u32) };
-        match self.cop0.mode() {
-            Mode::Kernel => self.translate_static_kernel_addr(addr).ok().flatten(),
-            Mode::Supervisor => None,
-            Mode::User => None,
+        match self.segment(addr) {
+            Segment::Unmapped(phys) => Some(phys),
+            Segment::Mapped => match self.tlb.translate(addr, self.cop0.entry_hi.asid()) {
+                TlbLookup::Hit { addr, cached, .. } => Some(TranslatedAddr { addr, cached }),
+                TlbLookup::Invalid | TlbLookup::Miss => None,
+            },
+            Segment::Invalid => None,
         }
     }
     pub fn is_big_endian(&self) -> bool {
@@ -69,204 +111,508 @@ impl Vr4300 {
 
     fn fetch(&mut self, bus: &mut impl SysAd) -> MipsResult<Instr> {
         if self.pc % 4 != 0 {
-            return Err(Some(MipsErr::new("handling of unaligned instruction fetches is not implemented")));
+            return self.take_address_exception(Exception::AddressErrorLoad, self.pc);
         }
 
-        let phys = self.translate_address(self.pc)?;
-        if phys.cached {
-            return Err(Some(MipsErr::new("handling of cached instruction fetches is not implemented")));
+        let phys = self.translate_address(self.pc, Access::Read)?;
+        let word = if phys.cached {
+            self.icache.read(self.pc, phys.addr, bus)?
         }
         else {
-            let word = bus.read_word(phys.addr)?;
-            let val = word.to_u32(self.cop0.is_big_endian());
-            Ok(Instr(val))
-        }
+            bus.read_word(phys.addr)?
+        };
+        let val = word.to_u32(self.cop0.is_big_endian());
+        Ok(Instr(val))
     }
 
-    fn translate_address(&mut self, addr: u64) -> MipsResult<TranslatedAddr> {
+    fn translate_address(&mut self, addr: u64, access: Access) -> MipsResult<TranslatedAddr> {
         let addr = if self.is_64_bit_mode() { addr } else { sext_32(addr as u32) };
 
-        match self.cop0.mode() {
-            Mode::Kernel => self.translate_kernel_addr(addr),
-            Mode::Supervisor => Err(Some(MipsErr::new("translating supervisor addresses is unimplemented"))),
-            Mode::User => Err(Some(MipsErr::new("translating user addresses is unimplemented"))),
+        match self.segment(addr) {
+            Segment::Unmapped(phys) => Ok(phys),
+            Segment::Mapped => self.translate_mapped_addr(addr, access),
+            Segment::Invalid => {
+                let exception = match access {
+                    Access::Read => Exception::AddressErrorLoad,
+                    Access::Write => Exception::AddressErrorStore,
+                };
+                self.take_address_exception(exception, addr)
+            }
         }
     }
-    fn translate_kernel_addr(&mut self, addr: u64) -> MipsResult<TranslatedAddr> {
-        if let Some(phys) = self.translate_static_kernel_addr(addr)? {
-            Ok(phys)
+    fn translate_mapped_addr(&mut self, addr: u64, access: Access) -> MipsResult<TranslatedAddr> {
+        let asid = self.cop0.entry_hi.asid();
+        match self.tlb.translate(addr, asid) {
+            TlbLookup::Hit { addr: phys, cached, dirty } => {
+                if access == Access::Write && !dirty {
+                    return self.take_tlb_exception(Exception::TlbModification, addr);
+                }
+                Ok(TranslatedAddr {
+                    addr: phys,
+                    cached,
+                })
+            }
+            TlbLookup::Invalid => {
+                let exception = match access {
+                    Access::Read => Exception::TlbInvalidLoad,
+                    Access::Write => Exception::TlbInvalidStore,
+                };
+                self.take_tlb_exception(exception, addr)
+            }
+            TlbLookup::Miss => {
+                let exception = match access {
+                    Access::Read => Exception::TlbRefillLoad,
+                    Access::Write => Exception::TlbRefillStore,
+                };
+                self.take_tlb_exception(exception, addr)
+            }
         }
-        else {
-            Err(Some(MipsErr::new("translating TLB mapped kernel addresses is unimplemented")))
+    }
+    fn segment(&self, addr: u64) -> Segment {
+        match self.cop0.mode() {
+            Mode::Kernel => self.kernel_segment(addr),
+            Mode::Supervisor => {
+                if XSUSEG.contains(&addr) || XSSEG.contains(&addr) || CSSEG.contains(&addr) {
+                    Segment::Mapped
+                }
+                else {
+                    Segment::Invalid
+                }
+            }
+            Mode::User => {
+                if XUSEG.contains(&addr) {
+                    Segment::Mapped
+                }
+                else {
+                    Segment::Invalid
+                }
+            }
         }
     }
-    fn translate_static_kernel_addr(&self, addr: u64) -> MipsResult<Option<TranslatedAddr>> {
-        if XKPHYS.contains(&addr) {
-            Err(Some(MipsErr::new("translating XKPHYS addresses is unimplemented")))
+    fn kernel_segment(&self, addr: u64) -> Segment {
+        if XKUSEG.contains(&addr) {
+            // With ERL set, the 32 bit kuseg becomes an unmapped, uncached window onto physical memory
+            if self.cop0.status.erl() && addr <= KUSEG_LAST {
+                Segment::Unmapped(TranslatedAddr {
+                    addr: addr as u32,
+                    cached: false,
+                })
+            }
+            else {
+                Segment::Mapped
+            }
+        }
+        else if XKPHYS.contains(&addr) {
+            if addr & XKPHYS_RESERVED != 0 {
+                return Segment::Invalid;
+            }
+            let attribute = ((addr >> 59) & 0b111) as u8;
+            Segment::Unmapped(TranslatedAddr {
+                addr: addr as u32,
+                cached: attribute != 0b010,
+            })
         }
         else if CKSEG0.contains(&addr) {
             let addr = (addr - CKSEG0.start()) as u32;
             let cached = self.cop0.is_ksg0_cached();
-            Ok(Some(TranslatedAddr {
+            Segment::Unmapped(TranslatedAddr {
                 addr,
                 cached,
-            }))
+            })
         }
         else if CKSEG1.contains(&addr) {
             let addr = (addr - CKSEG1.start()) as u32;
-            Ok(Some(TranslatedAddr {
+            Segment::Unmapped(TranslatedAddr {
                 addr,
                 cached: false,
-            }))
+            })
+        }
+        else if XKSSEG.contains(&addr) || XKSEG.contains(&addr) || CKSSEG.contains(&addr) || CKSEG3.contains(&addr) {
+            Segment::Mapped
         }
         else {
-            Ok(None)
+            Segment::Invalid
         }
     }
 
-    fn mem_addr(&mut self, instr: Instr) -> MipsResult<TranslatedAddr> {
+    fn mem_vaddr(&mut self, instr: Instr) -> MipsResult<u64> {
         let base = self.get_reg_unatural(instr.base())?;
         let offset = instr.immi() as i64;
-        let virt = base.wrapping_add_signed(offset);
-        self.translate_address(virt)
-    }
+        Ok(base.wrapping_add_signed(offset))
+    }
+    fn data_addr(&mut self, vaddr: u64, align: u64, access: Access) -> MipsResult<TranslatedAddr> {
+        if !vaddr.is_multiple_of(align) {
+            let exception = match access {
+                Access::Read => Exception::AddressErrorLoad,
+                Access::Write => Exception::AddressErrorStore,
+            };
+            return self.take_address_exception(exception, vaddr);
+        }
 
-    fn do_mtc0(&mut self, instr: Instr) -> MipsResult<()> {
-        let data = self.get_reg_u32(instr.rt())?;
-        match instr.rd().0 {
-            12 => self.cop0.status = Status::from_bits(data),
-            16 => self.cop0.config = Config::from_bits(data),
-            31.. => unreachable!(),
-            or => return Err(Some(MipsErr::new(format!("Writing to CP0 register {or} is not implemented")))),
+        self.translate_address(vaddr, access)
+    }
+    /// Translates the address of a partial word access, which may be unaligned, to its aligned physical word or dword.
+    /// Exceptions report the address the instruction used, not the aligned one.
+    fn partial_addr(&mut self, vaddr: u64, bytes: u32, access: Access) -> MipsResult<TranslatedAddr> {
+        let phys = self.translate_address(vaddr, access)?;
+        Ok(phys.with_addr(phys.addr & !(bytes - 1)))
+    }
+    /// Reads the aligned word at `phys`, through the data cache if it is cached.
+    fn load_word(&mut self, vaddr: u64, phys: TranslatedAddr, bus: &mut impl SysAd) -> MipsResult<Word> {
+        if phys.cached {
+            Ok(self.dcache.read(vaddr, phys.addr, bus)?)
+        }
+        else {
+            Ok(bus.read_word(phys.addr)?)
+        }
+    }
+    /// Writes `size` bytes starting at `phys`, through the data cache if it is cached.
+    fn store_word(&mut self, vaddr: u64, phys: TranslatedAddr, size: WriteSize, data: Word, bus: &mut impl SysAd) -> MipsResult<()> {
+        if phys.cached {
+            self.dcache.write(vaddr, phys.addr, size, data, bus)?;
+        }
+        else {
+            bus.write_word(phys.addr, size, data)?;
         }
         Ok(())
     }
 
-}
-impl RawCore for Vr4300 {
-    fn do_bczf(&mut self, instr: Instr, likely: bool, cop: u8) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    /// Reads a naturally aligned 1, 2 or 4 byte value, zero extended.
+    fn read_data(&mut self, vaddr: u64, bytes: u32, bus: &mut impl SysAd) -> MipsResult<u32> {
+        let phys = self.data_addr(vaddr, bytes as u64, Access::Read)?;
+        let offset = phys.addr & 3;
+        let word = self.load_word(vaddr & !3, phys.with_addr(phys.addr & !3), bus)?;
+        let word = word.to_u32(self.is_big_endian());
+        let shift = self.lane_shift(offset, bytes);
+        Ok((word >> shift) & low_mask_32(bytes))
     }
-
-    fn do_bczt(&mut self, instr: Instr, likely: bool, cop: u8) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn read_data_word(&mut self, vaddr: u64, bus: &mut impl SysAd) -> MipsResult<u32> {
+        self.read_data(vaddr, 4, bus)
     }
-
-    fn do_break(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn read_data_dword(&mut self, vaddr: u64, bus: &mut impl SysAd) -> MipsResult<u64> {
+        let phys = self.data_addr(vaddr, 8, Access::Read)?;
+        self.load_dword(vaddr, phys, bus)
     }
-
-    fn do_cfcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    /// Reads the aligned dword at `phys` as two words, through the data cache if it is cached.
+    fn load_dword(&mut self, vaddr: u64, phys: TranslatedAddr, bus: &mut impl SysAd) -> MipsResult<u64> {
+        let be = self.is_big_endian();
+        let first = self.load_word(vaddr, phys, bus)?.to_u32(be) as u64;
+        let second = self.load_word(vaddr + 4, phys.with_addr(phys.addr + 4), bus)?.to_u32(be) as u64;
+        if be {
+            Ok(first << 32 | second)
+        }
+        else {
+            Ok(second << 32 | first)
+        }
+    }
+    /// Writes a naturally aligned 1, 2 or 4 byte value.
+    fn write_data(&mut self, vaddr: u64, bytes: u32, value: u32, bus: &mut impl SysAd) -> MipsResult<()> {
+        let phys = self.data_addr(vaddr, bytes as u64, Access::Write)?;
+        let shift = self.lane_shift(phys.addr & 3, bytes);
+        let aligned = phys.with_addr(phys.addr & !3);
+        self.write_masked_word(vaddr & !3, aligned, value << shift, low_mask_32(bytes) << shift, bus)
     }
+    fn write_data_word(&mut self, vaddr: u64, value: u32, bus: &mut impl SysAd) -> MipsResult<()> {
+        self.write_data(vaddr, 4, value, bus)
+    }
+    fn write_data_dword(&mut self, vaddr: u64, value: u64, bus: &mut impl SysAd) -> MipsResult<()> {
+        let phys = self.data_addr(vaddr, 8, Access::Write)?;
+        let be = self.is_big_endian();
+        let high = Word::from_u32((value >> 32) as u32, be);
+        let low = Word::from_u32(value as u32, be);
+        let [first, second] = if be { [high, low] } else { [low, high] };
 
-    fn do_copz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        if phys.cached {
+            self.store_word(vaddr, phys, WriteSize::Four, first, bus)?;
+            self.store_word(vaddr + 4, phys.with_addr(phys.addr + 4), WriteSize::Four, second, bus)
+        }
+        else {
+            bus.write_dword(phys.addr, [first, second])?;
+            Ok(())
+        }
     }
 
-    fn do_ctcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    /// Writes those bytes of an aligned word that are selected by `mask`, which has to select a contiguous run of bytes.
+    fn write_masked_word(&mut self, vaddr: u64, phys: TranslatedAddr, value: u32, mask: u32, bus: &mut impl SysAd) -> MipsResult<()> {
+        let be = self.is_big_endian();
+        let lanes = Word::from_u32(mask, be);
+        let Some(first) = lanes.0.iter().position(|&b| b != 0) else { return Ok(()) };
+        let count = lanes.0.iter().filter(|&&b| b != 0).count();
+
+        let size = WriteSize::from_bytes(count as u8);
+        let phys = phys.with_addr(phys.addr + first as u32);
+        self.store_word(vaddr, phys, size, Word::from_u32(value, be), bus)
+    }
+    /// Like [Self::write_masked_word], but for an aligned dword, split into the two word-sized writes.
+    fn write_masked_dword(&mut self, vaddr: u64, phys: TranslatedAddr, value: u64, mask: u64, bus: &mut impl SysAd) -> MipsResult<()> {
+        let high = if self.is_big_endian() { 0 } else { 4 };
+        let low = high ^ 4;
+        let (high_mask, low_mask) = ((mask >> 32) as u32, mask as u32);
+        self.write_masked_word(vaddr + high, phys.with_addr(phys.addr + high as u32), (value >> 32) as u32, high_mask, bus)?;
+        self.write_masked_word(vaddr + low, phys.with_addr(phys.addr + low as u32), value as u32, low_mask, bus)
+    }
+    /// The position of a value of the given size and byte offset within the numeric value of its word.
+    fn lane_shift(&self, offset: u32, bytes: u32) -> u32 {
+        if self.is_big_endian() {
+            (4 - bytes - offset) * 8
+        }
+        else {
+            offset * 8
+        }
+    }
+    /// The byte offset of an address as seen by the partial word loads and stores,
+    /// which are specified for big endian and mirrored for little endian.
+    fn partial_offset(&self, vaddr: u64, bytes: u64) -> u32 {
+        let offset = vaddr & (bytes - 1);
+        let offset = if self.is_big_endian() { offset } else { bytes - 1 - offset };
+        offset as u32
     }
 
-    fn do_dmfcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    /// Sets the link bit for a following SC or SCD and records the physical address in LLAddr.
+    fn link_load(&mut self, vaddr: u64) -> MipsResult<()> {
+        let phys = self.translate_address(vaddr, Access::Read)?;
+        self.cop0.ll_addr.0 = phys.addr >> 4;
+        self.ll_bit = true;
+        Ok(())
     }
 
-    fn do_dmtcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn jump_target(&self, instr: Instr) -> u64 {
+        let region = self.pc.wrapping_add(4) & !0x0FFF_FFFF;
+        region | (instr.jump_offset() as u64) << 2
+    }
+    /// Stores the return address of the current branch, which is the instruction after its delay slot.
+    fn link(&mut self, reg: Reg) -> MipsResult<()> {
+        self.set_reg_u64(reg, self.pc.wrapping_add(8))
     }
 
-    fn do_j(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn require_cop(&mut self, cop: u8) -> MipsResult<()> {
+        let status = self.cop0.status;
+        let usable = match cop {
+            0 => status.cu0() || self.cop0.mode() == Mode::Kernel,
+            1 => status.cu1(),
+            2 => status.cu2(),
+            3 => status.cu3(),
+            _ => unreachable!(),
+        };
+
+        if usable {
+            Ok(())
+        }
+        else {
+            self.take_exception(Exception::CoprocessorUnusable(cop))
+        }
     }
 
-    fn do_jal(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_cop0_op(&mut self, instr: Instr) -> MipsResult<()> {
+        self.require_cop(0)?;
+        match instr.funct() {
+            OP_C0_TLBR => self.do_tlbr(),
+            OP_C0_TLBWI => self.do_tlbwi(),
+            OP_C0_TLBWR => self.do_tlbwr(),
+            OP_C0_TLBP => self.do_tlbp(),
+            OP_C0_ERET => self.do_eret(),
+            _ => self.reserved_instruction(instr),
+        }
     }
+    fn do_eret(&mut self) -> MipsResult<()> {
+        let status = &mut self.cop0.status;
+        let target = if status.erl() {
+            status.set_erl(false);
+            self.cop0.error_epc.0
+        }
+        else {
+            status.set_exl(false);
+            self.cop0.epc.0
+        };
+        self.ll_bit = false;
 
-    fn do_jarl(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        // ERET has no delay slot, so jump directly, undoing the increment that follows every instruction
+        self.pc = target.wrapping_sub(4);
+        Ok(())
+    }
+    fn do_tlbr(&mut self) -> MipsResult<()> {
+        let entry = self.tlb.read(self.cop0.index.index() as usize);
+        self.cop0.page_mask = entry.page_mask;
+        self.cop0.entry_hi = entry.entry_hi;
+        self.cop0.entry_lo0 = entry.entry_lo0;
+        self.cop0.entry_lo1 = entry.entry_lo1;
+        Ok(())
+    }
+    fn do_tlbwi(&mut self) -> MipsResult<()> {
+        let index = self.cop0.index.index() as usize;
+        self.tlb.write(index, self.tlb_entry_from_cop0());
+        Ok(())
+    }
+    fn do_tlbwr(&mut self) -> MipsResult<()> {
+        let index = self.cop0.random.random() as usize;
+        self.tlb.write(index, self.tlb_entry_from_cop0());
+        Ok(())
+    }
+    fn do_tlbp(&mut self) -> MipsResult<()> {
+        let entry_hi = self.cop0.entry_hi;
+        match self.tlb.probe(entry_hi.into_bits(), entry_hi.asid()) {
+            Some(index) => self.cop0.index = Index::new().with_index(index as u8),
+            None => self.cop0.index.set_p(true),
+        }
+        Ok(())
+    }
+    fn tlb_entry_from_cop0(&self) -> TlbEntry {
+        TlbEntry::from_cop0(
+            self.cop0.page_mask,
+            self.cop0.entry_hi,
+            self.cop0.entry_lo0,
+            self.cop0.entry_lo1,
+        )
     }
 
-    fn do_jr(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_mfc0(&mut self, instr: Instr) -> MipsResult<()> {
+        self.require_cop(0)?;
+        let value = self.cop0.read(instr.rd().0);
+        self.set_reg_u32(instr.rt(), value as u32)
+    }
+    fn do_dmfc0(&mut self, instr: Instr) -> MipsResult<()> {
+        self.require_cop(0)?;
+        self.dword_operation(instr)?;
+        let value = self.cop0.read(instr.rd().0);
+        self.set_reg_u64(instr.rt(), value)
+    }
+    fn do_mtc0(&mut self, instr: Instr) -> MipsResult<()> {
+        self.require_cop(0)?;
+        let value = self.get_reg_u32(instr.rt())? as i32 as u64;
+        self.cop0.write(instr.rd().0, value);
+        Ok(())
+    }
+    fn do_dmtc0(&mut self, instr: Instr) -> MipsResult<()> {
+        self.require_cop(0)?;
+        self.dword_operation(instr)?;
+        let value = self.get_reg_u64(instr.rt())?;
+        self.cop0.write(instr.rd().0, value);
+        Ok(())
     }
 
-    fn do_mfcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+}
+impl RawCore for Vr4300 {
+    fn do_bczf(&mut self, instr: Instr, likely: bool, cop: u8) -> MipsResult<()> {
+        match cop {
+            1 => self.do_bc1(instr, likely, false),
+            _ => Err(Some(MipsErr::new(format!("BC{cop}F is not implemented")))),
+        }
     }
 
-    fn do_mtcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
+    fn do_bczt(&mut self, instr: Instr, likely: bool, cop: u8) -> MipsResult<()> {
         match cop {
-            0 => self.do_mtc0(instr),
-            1 => Err(Some(MipsErr::new(format!("MTC1 is not implemented")))),
-            2 => Err(Some(MipsErr::new(format!("MTC2 is not implemented")))),
-            _ => unreachable!(),
+            1 => self.do_bc1(instr, likely, true),
+            _ => Err(Some(MipsErr::new(format!("BC{cop}T is not implemented")))),
         }
     }
 
-    fn do_sync(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_break(&mut self, _instr: Instr) -> MipsResult<()> {
+        self.take_exception(Exception::Breakpoint)
     }
 
-    fn do_syscall(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_cfcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
+        match cop {
+            1 => self.do_cfc1(instr),
+            _ => Err(Some(MipsErr::new(format!("CFC{cop} is not implemented")))),
+        }
     }
 
-    fn do_teq(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_copz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
+        match cop {
+            0 => self.do_cop0_op(instr),
+            1 => self.do_cop1_op(instr),
+            _ => Err(Some(MipsErr::new(format!("COP{cop} operations are not implemented")))),
+        }
     }
 
-    fn do_teqi(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_ctcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
+        match cop {
+            1 => self.do_ctc1(instr),
+            _ => Err(Some(MipsErr::new(format!("CTC{cop} is not implemented")))),
+        }
     }
 
-    fn do_tge(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_dmfcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
+        match cop {
+            0 => self.do_dmfc0(instr),
+            1 => self.do_dmfc1(instr),
+            _ => Err(Some(MipsErr::new(format!("DMFC{cop} is not implemented")))),
+        }
     }
 
-    fn do_tgei(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_dmtcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
+        match cop {
+            0 => self.do_dmtc0(instr),
+            1 => self.do_dmtc1(instr),
+            _ => Err(Some(MipsErr::new(format!("DMTC{cop} is not implemented")))),
+        }
     }
 
-    fn do_tgeiu(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_j(&mut self, instr: Instr) -> MipsResult<()> {
+        self.branch = Some(self.jump_target(instr));
+        Ok(())
     }
 
-    fn do_tgeu(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_jal(&mut self, instr: Instr) -> MipsResult<()> {
+        self.link(Reg(31))?;
+        self.branch = Some(self.jump_target(instr));
+        Ok(())
     }
 
-    fn do_tlt(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_jarl(&mut self, instr: Instr) -> MipsResult<()> {
+        let target = self.get_reg_u64(instr.rs())?;
+        self.link(instr.rd())?;
+        self.branch = Some(target);
+        Ok(())
     }
 
-    fn do_tlti(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_jr(&mut self, instr: Instr) -> MipsResult<()> {
+        self.branch = Some(self.get_reg_u64(instr.rs())?);
+        Ok(())
     }
 
-    fn do_tltiu(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_mfcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
+        match cop {
+            0 => self.do_mfc0(instr),
+            1 => self.do_mfc1(instr),
+            _ => Err(Some(MipsErr::new(format!("MFC{cop} is not implemented")))),
+        }
     }
 
-    fn do_tltu(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_mtcz(&mut self, instr: Instr, cop: u8) -> MipsResult<()> {
+        match cop {
+            0 => self.do_mtc0(instr),
+            1 => self.do_mtc1(instr),
+            2 => Err(Some(MipsErr::new(format!("MTC2 is not implemented")))),
+            _ => unreachable!(),
+        }
     }
 
-    fn do_tne(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_sync(&mut self, _instr: Instr) -> MipsResult<()> {
+        // Memory accesses are never reordered, so there is nothing to wait for
+        Ok(())
     }
 
-    fn do_tnei(&mut self, instr: Instr) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_syscall(&mut self, _instr: Instr) -> MipsResult<()> {
+        self.take_exception(Exception::Syscall)
     }
 
-    fn do_branch_and_link(&mut self, instr: Instr, likely: bool) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_branch_and_link(&mut self, instr: Instr, taken: bool, likely: bool) -> MipsResult<()> {
+        // The link register is written whether or not the branch is taken
+        self.link(Reg(31))?;
+        self.do_branch(instr, taken, likely)
     }
 
-    fn do_branch(&mut self, instr: Instr, likely: bool) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+    fn do_branch(&mut self, instr: Instr, taken: bool, likely: bool) -> MipsResult<()> {
+        if taken {
+            let offset = (instr.branch_offset() as i64) << 2;
+            self.branch = Some(self.pc.wrapping_add(4).wrapping_add_signed(offset));
+        }
+        else if likely {
+            // Nullify the delay slot by stepping over it
+            self.pc = self.pc.wrapping_add(4);
+        }
+        Ok(())
     }
 
     fn get_reg_i64(&self, reg: crate::instruction::Reg) -> MipsResult<i64> {
@@ -318,149 +664,296 @@ impl RawCore for Vr4300 {
         self.cop0.is_64_bit_mode()
     }
 
-    fn reserved_instruction(&mut self, instr: Instr) -> MipsResult<()> {
-        Err(Some(MipsErr::new(format!("handling of reserved instr {:x} is unimplemented", instr.0))))
+    fn reserved_instruction(&mut self, _instr: Instr) -> MipsResult<()> {
+        self.take_exception(Exception::ReservedInstruction)
+    }
+
+    fn integer_overflow(&mut self, _instr: Instr) -> MipsResult<()> {
+        self.take_exception(Exception::IntegerOverflow)
     }
 
-    fn integer_overflow(&mut self, instr: Instr) -> MipsResult<()> {
-        Err(Some(MipsErr::new(format!("handling of integer overflow in instruction {:x} is unimplemented", instr.0))))
+    fn trap(&mut self, _instr: Instr) -> MipsResult<()> {
+        self.take_exception(Exception::Trap)
     }
 
-    fn dword_operation(&mut self, instr: Instr) -> MipsResult<()> {
+    fn dword_operation(&mut self, _instr: Instr) -> MipsResult<()> {
         if self.is_64_bit_mode() || self.cop0.mode() == Mode::Kernel {
             Ok(())
         }
         else {
-            Err(Some(MipsErr::new(format!("handling of disallowed dword instruction {:x} is unimplemented", instr.0))))
+            self.take_exception(Exception::ReservedInstruction)
         }
     }
 }
 impl<T: SysAd> MipsCore<T> for Vr4300 {
+    fn do_cache(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
+        self.require_cop(0)?;
+        let vaddr = self.mem_vaddr(instr)?;
+        let Some((cache, op)) = CacheOp::decode(instr.rt().0) else { return Ok(()) };
+
+        // Index operations use the virtual address as is, only hit operations translate it
+        let paddr = if op.is_hit_op() { self.translate_address(vaddr, Access::Read)?.addr } else { 0 };
+
+        match (cache, op) {
+            (Cache::Instruction, CacheOp::IndexInvalidate) => self.icache.index_invalidate(vaddr),
+            (Cache::Instruction, CacheOp::IndexLoadTag) => self.cop0.tag_lo = self.icache.load_tag(vaddr),
+            (Cache::Instruction, CacheOp::IndexStoreTag) => self.icache.store_tag(vaddr, self.cop0.tag_lo),
+            (Cache::Instruction, CacheOp::HitInvalidate) => self.icache.hit_invalidate(vaddr, paddr),
+            (Cache::Instruction, CacheOp::Fill) => self.icache.fill(vaddr, paddr, bus)?,
+            // Instruction cache lines are never dirty, so there is never anything to write back
+            (Cache::Instruction, _) => (),
+            (Cache::Data, CacheOp::IndexWriteBackInvalidate) => self.dcache.index_write_back_invalidate(vaddr, bus)?,
+            (Cache::Data, CacheOp::IndexLoadTag) => self.cop0.tag_lo = self.dcache.load_tag(vaddr),
+            (Cache::Data, CacheOp::IndexStoreTag) => self.dcache.store_tag(vaddr, self.cop0.tag_lo),
+            (Cache::Data, CacheOp::CreateDirtyExclusive) => self.dcache.create_dirty_exclusive(vaddr, paddr, bus)?,
+            (Cache::Data, CacheOp::HitInvalidate) => self.dcache.hit_invalidate(vaddr, paddr),
+            (Cache::Data, CacheOp::HitWriteBackInvalidate) => self.dcache.hit_write_back(vaddr, paddr, true, bus)?,
+            (Cache::Data, CacheOp::HitWriteBack) => self.dcache.hit_write_back(vaddr, paddr, false, bus)?,
+            (Cache::Data, _) => (),
+        }
+        Ok(())
+    }
+
     fn do_lb(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.read_data(vaddr, 1, bus)?;
+        self.set_reg_i64(instr.rt(), value as i8 as i64)
     }
 
     fn do_lbu(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.read_data(vaddr, 1, bus)?;
+        self.set_reg_u64(instr.rt(), value as u64)
     }
 
     fn do_ld(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        self.dword_operation(instr)?;
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.read_data_dword(vaddr, bus)?;
+        self.set_reg_u64(instr.rt(), value)
     }
 
     fn do_ldcz(&mut self, instr: Instr, cop: u8, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        match cop {
+            1 => self.do_ldc1(instr, bus),
+            _ => Err(Some(MipsErr::new(format!("LDC{cop} is not implemented")))),
+        }
     }
 
     fn do_ldl(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        self.dword_operation(instr)?;
+        let vaddr = self.mem_vaddr(instr)?;
+        let offset = self.partial_offset(vaddr, 8);
+        let phys = self.partial_addr(vaddr, 8, Access::Read)?;
+        let dword = self.load_dword(vaddr & !7, phys, bus)?;
+        let old = self.get_reg_u64(instr.rt())?;
+
+        let shift = offset * 8;
+        let kept = old & low_mask_64(offset);
+        self.set_reg_u64(instr.rt(), (dword << shift) | kept)
     }
 
     fn do_ldr(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        self.dword_operation(instr)?;
+        let vaddr = self.mem_vaddr(instr)?;
+        let offset = self.partial_offset(vaddr, 8);
+        let phys = self.partial_addr(vaddr, 8, Access::Read)?;
+        let dword = self.load_dword(vaddr & !7, phys, bus)?;
+        let old = self.get_reg_u64(instr.rt())?;
+
+        let shift = (7 - offset) * 8;
+        let loaded = low_mask_64(offset + 1);
+        self.set_reg_u64(instr.rt(), (old & !loaded) | (dword >> shift))
     }
 
     fn do_lh(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.read_data(vaddr, 2, bus)?;
+        self.set_reg_i64(instr.rt(), value as i16 as i64)
     }
 
     fn do_lhu(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.read_data(vaddr, 2, bus)?;
+        self.set_reg_u64(instr.rt(), value as u64)
     }
 
     fn do_ll(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.read_data_word(vaddr, bus)?;
+        self.link_load(vaddr)?;
+        self.set_reg_u32(instr.rt(), value)
     }
 
     fn do_lld(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        self.dword_operation(instr)?;
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.read_data_dword(vaddr, bus)?;
+        self.link_load(vaddr)?;
+        self.set_reg_u64(instr.rt(), value)
     }
 
     fn do_lw(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        let phys = self.mem_addr(instr)?;
-        if phys.cached {
-            return Err(Some(MipsErr::new("cached data accesses are not implemented")));
-        }
-        if phys.addr % 4 != 0 {
-            return Err(Some(MipsErr::new("handling of unaligned address exceptions is not implemented")));
-        }
-
-        let word = bus.read_word(phys.addr)?;
-        let be = self.is_big_endian();
-        let value = word.to_u32(be);
-
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.read_data_word(vaddr, bus)?;
         self.set_reg_u32(instr.rt(), value)?;
 
         Ok(())
     }
 
     fn do_lwcz(&mut self, instr: Instr, cop: u8, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        match cop {
+            1 => self.do_lwc1(instr, bus),
+            _ => Err(Some(MipsErr::new(format!("LWC{cop} is not implemented")))),
+        }
     }
 
     fn do_lwl(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        let vaddr = self.mem_vaddr(instr)?;
+        let offset = self.partial_offset(vaddr, 4);
+        let phys = self.partial_addr(vaddr, 4, Access::Read)?;
+        let word = self.load_word(vaddr & !3, phys, bus)?.to_u32(self.is_big_endian());
+        let old = self.get_reg_u32(instr.rt())?;
+
+        let shift = offset * 8;
+        let kept = old & low_mask_32(offset);
+        self.set_reg_u32(instr.rt(), (word << shift) | kept)
     }
 
     fn do_lwr(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        let vaddr = self.mem_vaddr(instr)?;
+        let offset = self.partial_offset(vaddr, 4);
+        let phys = self.partial_addr(vaddr, 4, Access::Read)?;
+        let word = self.load_word(vaddr & !3, phys, bus)?.to_u32(self.is_big_endian());
+
+        // Only a load that reaches the most significant byte sign extends; otherwise the upper half survives
+        if offset == 3 {
+            return self.set_reg_u32(instr.rt(), word);
+        }
+        let old = self.get_reg_u64(instr.rt())?;
+        let shift = (3 - offset) * 8;
+        let loaded = low_mask_32(offset + 1) as u64;
+        self.set_reg_u64(instr.rt(), (old & !loaded) | (word >> shift) as u64)
     }
 
     fn do_lwu(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        self.dword_operation(instr)?;
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.read_data_word(vaddr, bus)?;
+        self.set_reg_u64(instr.rt(), value as u64)
     }
 
     fn do_sb(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.get_reg_u32(instr.rt())?;
+        self.write_data(vaddr, 1, value & 0xFF, bus)
     }
 
     fn do_sc(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        let vaddr = self.mem_vaddr(instr)?;
+        if self.ll_bit {
+            let value = self.get_reg_u32(instr.rt())?;
+            self.write_data_word(vaddr, value, bus)?;
+        }
+        else {
+            self.data_addr(vaddr, 4, Access::Write)?;
+        }
+        self.set_reg_u64(instr.rt(), self.ll_bit as u64)
     }
 
     fn do_scd(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        self.dword_operation(instr)?;
+        let vaddr = self.mem_vaddr(instr)?;
+        if self.ll_bit {
+            let value = self.get_reg_u64(instr.rt())?;
+            self.write_data_dword(vaddr, value, bus)?;
+        }
+        else {
+            self.data_addr(vaddr, 8, Access::Write)?;
+        }
+        self.set_reg_u64(instr.rt(), self.ll_bit as u64)
     }
 
     fn do_sd(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        self.dword_operation(instr)?;
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.get_reg_u64(instr.rt())?;
+        self.write_data_dword(vaddr, value, bus)
     }
 
     fn do_sdcz(&mut self, instr: Instr, cop: u8, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        match cop {
+            1 => self.do_sdc1(instr, bus),
+            _ => Err(Some(MipsErr::new(format!("SDC{cop} is not implemented")))),
+        }
     }
 
     fn do_sdl(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        self.dword_operation(instr)?;
+        let vaddr = self.mem_vaddr(instr)?;
+        let offset = self.partial_offset(vaddr, 8);
+        let value = self.get_reg_u64(instr.rt())?;
+        let phys = self.partial_addr(vaddr, 8, Access::Write)?;
+
+        let shift = offset * 8;
+        self.write_masked_dword(vaddr & !7, phys, value >> shift, u64::MAX >> shift, bus)
     }
 
     fn do_sdr(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        self.dword_operation(instr)?;
+        let vaddr = self.mem_vaddr(instr)?;
+        let offset = self.partial_offset(vaddr, 8);
+        let value = self.get_reg_u64(instr.rt())?;
+        let phys = self.partial_addr(vaddr, 8, Access::Write)?;
+
+        let shift = (7 - offset) * 8;
+        self.write_masked_dword(vaddr & !7, phys, value << shift, u64::MAX << shift, bus)
     }
 
     fn do_sh(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.get_reg_u32(instr.rt())?;
+        self.write_data(vaddr, 2, value & 0xFFFF, bus)
     }
 
     fn do_sw(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.get_reg_u32(instr.rt())?;
+        self.write_data_word(vaddr, value, bus)
     }
 
     fn do_swcz(&mut self, instr: Instr, cop: u8, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        match cop {
+            1 => self.do_swc1(instr, bus),
+            _ => Err(Some(MipsErr::new(format!("SWC{cop} is not implemented")))),
+        }
     }
 
     fn do_swl(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        let vaddr = self.mem_vaddr(instr)?;
+        let offset = self.partial_offset(vaddr, 4);
+        let value = self.get_reg_u32(instr.rt())?;
+        let phys = self.partial_addr(vaddr, 4, Access::Write)?;
+
+        let shift = offset * 8;
+        self.write_masked_word(vaddr & !3, phys, value >> shift, u32::MAX >> shift, bus)
     }
 
     fn do_swr(&mut self, instr: Instr, bus: &mut T) -> MipsResult<()> {
-        return Err(Some(MipsErr::new("unimplemented")))
+        let vaddr = self.mem_vaddr(instr)?;
+        let offset = self.partial_offset(vaddr, 4);
+        let value = self.get_reg_u32(instr.rt())?;
+        let phys = self.partial_addr(vaddr, 4, Access::Write)?;
+
+        let shift = (3 - offset) * 8;
+        self.write_masked_word(vaddr & !3, phys, value << shift, u32::MAX << shift, bus)
     }
 }
 
 pub trait SysAd {
     fn read_word(&mut self, addr: u32) -> Result<Word, MipsErr>;
+    /// Writes `size` bytes starting at `addr`, which need not be word aligned.
+    /// The bytes to write are taken from the same byte lanes of `data` that they occupy in the addressed word.
     fn write_word(
         &mut self,
         addr: u32,
@@ -475,12 +968,39 @@ pub trait SysAd {
 }
 
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum WriteSize {
     One,
     Two,
     Three,
     Four,
 }
+impl WriteSize {
+    pub fn from_bytes(bytes: u8) -> Self {
+        match bytes {
+            1 => Self::One,
+            2 => Self::Two,
+            3 => Self::Three,
+            4 => Self::Four,
+            _ => unreachable!(),
+        }
+    }
+    pub fn bytes(self) -> u8 {
+        match self {
+            Self::One => 1,
+            Self::Two => 2,
+            Self::Three => 3,
+            Self::Four => 4,
+        }
+    }
+}
+
+fn low_mask_32(bytes: u32) -> u32 {
+    u32::MAX.checked_shr(32 - bytes * 8).unwrap_or(0)
+}
+fn low_mask_64(bytes: u32) -> u64 {
+    u64::MAX.checked_shr(64 - bytes * 8).unwrap_or(0)
+}
 
 const RESET_VECTOR: u64 = 0xFFFF_FFFF_BFC0_0000;
 
@@ -499,7 +1019,322 @@ const CKSEG1: RangeInclusive<u64> = 0xFFFFFFFFA0000000..=0xFFFFFFFFBFFFFFFF;
 const CKSSEG: RangeInclusive<u64> = CSSEG;
 const CKSEG3: RangeInclusive<u64> = 0xFFFFFFFFE0000000..=0xFFFFFFFFFFFFFFFF;
 
+const KUSEG_LAST: u64 = 0x7FFF_FFFF;
+const XKPHYS_RESERVED: u64 = 0x07FF_FFFF_0000_0000;
+
+#[derive(Copy, Clone, Debug)]
 pub struct TranslatedAddr {
     pub addr: u32,
     pub cached: bool,
 }
+impl TranslatedAddr {
+    fn with_addr(self, addr: u32) -> Self {
+        Self { addr, ..self }
+    }
+}
+
+enum Segment {
+    Unmapped(TranslatedAddr),
+    Mapped,
+    Invalid,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cop0::{EntryHi, EntryLo, PageMask};
+
+    /// Runs a program from the reset vector, with every other address reading as NOP.
+    struct TestBus {
+        program: Vec<u32>,
+    }
+    impl SysAd for TestBus {
+        fn read_word(&mut self, addr: u32) -> Result<Word, MipsErr> {
+            let index = addr.wrapping_sub(RESET_VECTOR as u32 & 0x1FFF_FFFF) as usize / 4;
+            Ok(Word::from_u32_be(self.program.get(index).copied().unwrap_or(0)))
+        }
+        fn write_word(&mut self, _addr: u32, _size: WriteSize, _data: Word) -> Result<(), MipsErr> {
+            Ok(())
+        }
+        fn write_dword(&mut self, _addr: u32, _data: [Word; 2]) -> Result<(), MipsErr> {
+            Ok(())
+        }
+        fn read_cached_data(&mut self, _addr: u32) -> Result<[Word; 4], MipsErr> {
+            Ok([Word::zero(); 4])
+        }
+        fn read_cached_inst(&mut self, _addr: u32) -> Result<[Word; 8], MipsErr> {
+            Ok([Word::zero(); 8])
+        }
+        fn write_cached_data(&mut self, _addr: u32, _data: [Word; 4]) -> Result<(), MipsErr> {
+            Ok(())
+        }
+    }
+
+    /// Runs as many instructions as the program has, following any exceptions to the (empty) handler.
+    fn run(program: &[u32]) -> Vr4300 {
+        run_on(Vr4300::init(), program)
+    }
+    /// Like `run`, but starting from the given CPU state.
+    fn run_on(mut cpu: Vr4300, program: &[u32]) -> Vr4300 {
+        let mut bus = TestBus { program: program.to_vec() };
+        for _ in 0..program.len() {
+            cpu.step_forward(&mut bus).unwrap();
+        }
+        cpu
+    }
+    fn bad_v_addr_after(program: &[u32]) -> u64 {
+        run(program).cop0.bad_v_addr.0
+    }
+
+    #[test]
+    fn partial_stores_report_the_unaligned_address() {
+        // swl and swr zero, 1(t0), sdl and sdr zero, 5(t0), all of which miss the TLB
+        for (store, offset) in [(0xA9000001, 1), (0xB9000001, 1), (0xB1000005, 5), (0xB5000005, 5)] {
+            let program = [
+                0x3C08C000, // lui t0, 0xC000
+                store,
+            ];
+            assert_eq!(bad_v_addr_after(&program), 0xFFFF_FFFF_C000_0000 | offset, "{store:08x}");
+        }
+    }
+
+    #[test]
+    fn partial_loads_report_the_unaligned_address() {
+        let program = [
+            0x3C08C000, // lui t0, 0xC000
+            0x89020003, // lwl v0, 3(t0)
+        ];
+        assert_eq!(bad_v_addr_after(&program), 0xFFFF_FFFF_C000_0003);
+    }
+
+    #[test]
+    fn variable_shifts_use_the_low_five_bits() {
+        let program = [
+            0x24080024, // addiu t0, zero, 36
+            0x3C098000, // lui t1, 0x8000
+            0x01095006, // srlv t2, t1, t0
+        ];
+        assert_eq!(run(&program).gp[10 - 1], 0x0800_0000);
+    }
+
+    /// Enables the FPU with the given value of Status.FR.
+    fn enable_fpu(fr: bool) -> [u32; 2] {
+        [
+            if fr { 0x3C082400 } else { 0x3C082000 }, // lui t0, CU1 | FR
+            0x40886000,                               // mtc0 t0, Status
+        ]
+    }
+
+    #[test]
+    fn odd_fprs_pair_with_even_ones_without_fr() {
+        let program = |fr| {
+            let mut program = enable_fpu(fr).to_vec();
+            program.extend([
+                0x24091111, // addiu t1, zero, 0x1111
+                0x240A2222, // addiu t2, zero, 0x2222
+                0x44890000, // mtc1 t1, f0
+                0x448A0800, // mtc1 t2, f1
+                0x442B0000, // dmfc1 t3, f0
+                0x442C0800, // dmfc1 t4, f1
+            ]);
+            program
+        };
+
+        let cpu = run(&program(false));
+        assert_eq!(cpu.gp[11 - 1], 0x2222_0000_1111);
+        // Doubleword accesses to an odd register reach the even one
+        assert_eq!(cpu.gp[12 - 1], 0x2222_0000_1111);
+
+        let cpu = run(&program(true));
+        assert_eq!(cpu.gp[11 - 1], 0x1111);
+        assert_eq!(cpu.gp[12 - 1], 0x2222);
+    }
+
+    #[test]
+    fn cvt_w_s_follows_the_rounding_mode() {
+        // 2.5 and -1.5 in each mode: nearest, zero, up and down
+        for (mode, expected) in [(0, [2, -2]), (1, [2, -1]), (2, [3, -1]), (3, [2, -2])] {
+            let mut program = enable_fpu(false).to_vec();
+            program.extend([
+                0x3C094020,        // lui t1, 0x4020
+                0x44891000,        // mtc1 t1, f2
+                0x3C0ABFC0,        // lui t2, 0xBFC0
+                0x448A2000,        // mtc1 t2, f4
+                0x340B0000 | mode, // ori t3, zero, mode
+                0x44CBF800,        // ctc1 t3, FCR31
+                0x460011A4,        // cvt.w.s f6, f2
+                0x46002224,        // cvt.w.s f8, f4
+                0x440C3000,        // mfc1 t4, f6
+                0x440D4000,        // mfc1 t5, f8
+            ]);
+            let cpu = run(&program);
+            assert_eq!([cpu.gp[12 - 1], cpu.gp[13 - 1]], expected, "rounding mode {mode}");
+            // Only the inexact flag is raised
+            assert_eq!(cpu.cop1.fcr31.flags(), 1 << 0);
+        }
+    }
+
+    #[test]
+    fn converting_nan_is_unimplemented() {
+        let mut program = enable_fpu(false).to_vec();
+        program.extend([
+            0x3C097FC0, // lui t1, 0x7FC0
+            0x44891000, // mtc1 t1, f2
+            0x460011A4, // cvt.w.s f6, f2
+        ]);
+        let cpu = run(&program);
+        assert_eq!(cpu.cop0.cause.exec_code(), Exception::FloatingPoint.exec_code());
+        assert_eq!(cpu.cop0.epc.0, RESET_VECTOR + 16);
+        // Only the unimplemented operation cause is set
+        assert_eq!(cpu.cop1.fcr31.cause(), 1 << 5);
+        assert_eq!(cpu.cop1.get_s(Reg(6), false), 0);
+    }
+
+    /// The start of kseg2, which is mapped through the TLB even with ERL set.
+    const MAPPED: u64 = 0xFFFF_FFFF_C000_0000;
+
+    fn entry_hi(vaddr: u64, asid: u8) -> EntryHi {
+        EntryHi::new()
+            .with_asid(asid)
+            .with_vpn2((vaddr >> 13) as u32 & 0x7FF_FFFF)
+            .with_r((vaddr >> 62) as u8)
+    }
+    fn entry_lo(pfn: u32, valid: bool, dirty: bool, global: bool) -> EntryLo {
+        EntryLo::new().with_pfn(pfn).with_v(valid).with_d(dirty).with_g(global).with_c(3)
+    }
+    /// A CPU with a single entry mapping the 16K pages at MAPPED and MAPPED + 0x4000 for the given ASID.
+    fn with_entry(asid: u8, lo0: EntryLo, lo1: EntryLo) -> Vr4300 {
+        let mut cpu = Vr4300::init();
+        let page_mask = PageMask::new().with_mask(0x3);
+        cpu.tlb.write(0, TlbEntry::from_cop0(page_mask, entry_hi(MAPPED, asid), lo0, lo1));
+        cpu
+    }
+
+    #[test]
+    fn tlb_writes_are_read_back_and_probed() {
+        let mut cpu = Vr4300::init();
+        cpu.cop0.index = Index::new().with_index(7);
+        cpu.cop0.page_mask = PageMask::new().with_mask(0x3);
+        // The low VPN2 bit lies inside the 16K page pair and is dropped on write
+        cpu.cop0.entry_hi = entry_hi(MAPPED + 0x6000, 5);
+        cpu.cop0.entry_lo0 = entry_lo(0x100, true, true, true);
+        cpu.cop0.entry_lo1 = entry_lo(0x104, true, false, false);
+        let program = [
+            0x42000002, // tlbwi
+            0x40800000, // mtc0 zero, Index
+            0x42000008, // tlbp
+            0x42000001, // tlbr
+        ];
+        let cpu = run_on(cpu, &program);
+
+        assert_eq!(cpu.cop0.index.index(), 7);
+        assert!(!cpu.cop0.index.p());
+        assert_eq!(cpu.cop0.page_mask.mask(), 0x3);
+        assert_eq!(cpu.cop0.entry_hi.into_bits(), entry_hi(MAPPED, 5).into_bits());
+        // The entry is only global if both halves are
+        assert!(!cpu.cop0.entry_lo0.g());
+        assert_eq!(cpu.cop0.entry_lo0.pfn(), 0x100);
+        assert_eq!(cpu.cop0.entry_lo1.pfn(), 0x104);
+        assert!(cpu.cop0.entry_lo0.d());
+        assert!(!cpu.cop0.entry_lo1.d());
+    }
+
+    #[test]
+    fn tlb_random_writes_use_the_random_register() {
+        let mut cpu = Vr4300::init();
+        cpu.cop0.entry_hi = entry_hi(MAPPED, 0);
+        cpu.cop0.entry_lo0 = entry_lo(0x100, true, true, false);
+        let program = [
+            0x00000000, // nop
+            0x00000000, // nop
+            0x42000006, // tlbwr
+        ];
+        let cpu = run_on(cpu, &program);
+
+        // Random counts down once per instruction, starting from 31
+        let index = cpu.cop0.random.random() as usize;
+        assert_eq!(index, 28);
+        assert_eq!(cpu.tlb.read(index).entry_hi.into_bits(), entry_hi(MAPPED, 0).into_bits());
+        assert_eq!(cpu.tlb.probe(MAPPED, 0), Some(index));
+    }
+
+    #[test]
+    fn tlb_probes_match_the_asid_unless_global() {
+        let probe = |cpu: Vr4300, asid| {
+            let mut cpu = cpu;
+            cpu.cop0.entry_hi = entry_hi(MAPPED, asid);
+            run_on(cpu, &[0x42000008]).cop0.index // tlbp
+        };
+
+        let private = || with_entry(5, entry_lo(0x100, true, true, false), entry_lo(0x200, true, true, false));
+        assert!(!probe(private(), 5).p());
+        assert_eq!(probe(private(), 5).index(), 0);
+        assert!(probe(private(), 6).p());
+
+        // Both halves must be global for the ASID to be ignored
+        let half = with_entry(5, entry_lo(0x100, true, true, true), entry_lo(0x200, true, true, false));
+        assert!(probe(half, 6).p());
+        let global = with_entry(5, entry_lo(0x100, true, true, true), entry_lo(0x200, true, true, true));
+        assert!(!probe(global, 6).p());
+    }
+
+    #[test]
+    fn tlb_translation_selects_the_page_by_the_page_mask() {
+        let mut cpu = with_entry(5, entry_lo(0x100, true, true, false), entry_lo(0x200, true, true, false));
+        cpu.cop0.entry_hi = entry_hi(0, 5);
+        let translate = |vaddr| cpu.translate_address_debug(vaddr).map(|phys| phys.addr);
+
+        assert_eq!(translate(MAPPED + 0x0010), Some(0x0010_0010));
+        // Offsets above 4K still fall into the even 16K page
+        assert_eq!(translate(MAPPED + 0x2345), Some(0x0010_2345));
+        assert_eq!(translate(MAPPED + 0x4010), Some(0x0020_0010));
+        assert_eq!(translate(MAPPED + 0x7FFC), Some(0x0020_3FFC));
+        assert_eq!(translate(MAPPED + 0x8000), None);
+
+        cpu.cop0.entry_hi = entry_hi(0, 6);
+        let translate = |vaddr| cpu.translate_address_debug(vaddr).map(|phys| phys.addr);
+        assert_eq!(translate(MAPPED + 0x0010), None);
+    }
+
+    #[test]
+    fn tlb_exceptions_distinguish_refill_invalid_and_modification() {
+        const REFILL: u64 = 0xFFFF_FFFF_BFC0_0200;
+        const GENERAL: u64 = 0xFFFF_FFFF_BFC0_0380;
+
+        // The even page is clean, the odd page is invalid and nothing maps MAPPED + 0x8000
+        let cases = [
+            (0x3C08C000, 0xAD090000, Exception::TlbModification, GENERAL, MAPPED), // sw t1, 0(t0)
+            (0x3C08C000, 0x8D094000, Exception::TlbInvalidLoad, GENERAL, MAPPED + 0x4000), // lw t1, 0x4000(t0)
+            (0x3C08C000, 0xAD094000, Exception::TlbInvalidStore, GENERAL, MAPPED + 0x4000), // sw t1, 0x4000(t0)
+            (0x3C08C001, 0x8D090000, Exception::TlbRefillLoad, REFILL, MAPPED + 0x1_0000), // lw t1, 0(t0)
+            (0x3C08C001, 0xAD090000, Exception::TlbRefillStore, REFILL, MAPPED + 0x1_0000), // sw t1, 0(t0)
+        ];
+        for (lui, access, exception, vector, vaddr) in cases {
+            let cpu = with_entry(0, entry_lo(0x100, true, false, false), entry_lo(0x200, false, true, false));
+            let cpu = run_on(cpu, &[lui, access]);
+            assert_eq!(cpu.cop0.cause.exec_code(), exception.exec_code(), "{access:08x}");
+            assert_eq!(cpu.pc, vector, "{access:08x}");
+            assert_eq!(cpu.cop0.bad_v_addr.0, vaddr, "{access:08x}");
+            assert_eq!(cpu.cop0.entry_hi.vpn2(), entry_hi(vaddr, 0).vpn2(), "{access:08x}");
+        }
+
+        // Loads from the clean page succeed
+        let cpu = with_entry(0, entry_lo(0x100, true, false, false), entry_lo(0x200, false, true, false));
+        let cpu = run_on(cpu, &[0x3C08C000, 0x8D090000]); // lui t0, 0xC000; lw t1, 0(t0)
+        assert!(!cpu.cop0.status.exl());
+        assert_eq!(cpu.pc, RESET_VECTOR + 8);
+
+        // A refill during another exception goes to the general vector
+        let mut cpu = with_entry(0, entry_lo(0x100, true, false, false), entry_lo(0x200, false, true, false));
+        cpu.cop0.status.set_exl(true);
+        let cpu = run_on(cpu, &[0x3C08C001, 0x8D090000]); // lui t0, 0xC001; lw t1, 0(t0)
+        assert_eq!(cpu.cop0.cause.exec_code(), Exception::TlbRefillLoad.exec_code());
+        assert_eq!(cpu.pc, GENERAL);
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{core::MipsErr, word::Word};
+
+use super::{cop0::TagLo, SysAd, WriteSize};
+
+/// The 16 KB direct-mapped instruction cache, with lines of eight words.
+pub struct ICache {
+    lines: Vec<CacheLine<8>>,
+}
+impl ICache {
+    pub fn init() -> Self {
+        Self {
+            lines: vec![CacheLine::default(); ICACHE_LINES],
+        }
+    }
+
+    pub fn read(&mut self, vaddr: u64, paddr: u32, bus: &mut impl SysAd) -> Result<Word, MipsErr> {
+        let line = &mut self.lines[Self::index(vaddr)];
+        if !line.hit(paddr) {
+            line.words = bus.read_cached_inst(paddr & !ICACHE_LINE_MASK)?;
+            line.fill(paddr);
+        }
+        Ok(line.words[word_in_line(paddr, ICACHE_LINE_MASK)])
+    }
+
+    pub fn index_invalidate(&mut self, vaddr: u64) {
+        self.lines[Self::index(vaddr)].valid = false;
+    }
+    pub fn load_tag(&self, vaddr: u64) -> TagLo {
+        self.lines[Self::index(vaddr)].tag_lo()
+    }
+    pub fn store_tag(&mut self, vaddr: u64, tag: TagLo) {
+        self.lines[Self::index(vaddr)].set_tag_lo(tag);
+    }
+    pub fn hit_invalidate(&mut self, vaddr: u64, paddr: u32) {
+        let line = &mut self.lines[Self::index(vaddr)];
+        if line.hit(paddr) {
+            line.valid = false;
+        }
+    }
+    pub fn fill(&mut self, vaddr: u64, paddr: u32, bus: &mut impl SysAd) -> Result<(), MipsErr> {
+        let line = &mut self.lines[Self::index(vaddr)];
+        line.words = bus.read_cached_inst(paddr & !ICACHE_LINE_MASK)?;
+        line.fill(paddr);
+        Ok(())
+    }
+
+    fn index(vaddr: u64) -> usize {
+        (vaddr as usize >> 5) % ICACHE_LINES
+    }
+}
+
+/// The 8 KB direct-mapped write-back data cache, with lines of four words.
+pub struct DCache {
+    lines: Vec<CacheLine<4>>,
+}
+impl DCache {
+    pub fn init() -> Self {
+        Self {
+            lines: vec![CacheLine::default(); DCACHE_LINES],
+        }
+    }
+
+    pub fn read(&mut self, vaddr: u64, paddr: u32, bus: &mut impl SysAd) -> Result<Word, MipsErr> {
+        let line = self.line(vaddr, paddr, bus)?;
+        Ok(line.words[word_in_line(paddr, DCACHE_LINE_MASK)])
+    }
+    /// Writes `size` bytes starting at `paddr`, following the byte lane convention of [SysAd::write_word].
+    pub fn write(&mut self, vaddr: u64, paddr: u32, size: WriteSize, data: Word, bus: &mut impl SysAd) -> Result<(), MipsErr> {
+        let line = self.line(vaddr, paddr, bus)?;
+        let word = &mut line.words[word_in_line(paddr, DCACHE_LINE_MASK)];
+        word.overwrite(data, (paddr & 3) as u8, size.bytes());
+        line.dirty = true;
+        Ok(())
+    }
+
+    pub fn index_write_back_invalidate(&mut self, vaddr: u64, bus: &mut impl SysAd) -> Result<(), MipsErr> {
+        let index = Self::index(vaddr);
+        Self::write_back(&mut self.lines[index], index, bus)?;
+        self.lines[index].valid = false;
+        Ok(())
+    }
+    pub fn load_tag(&self, vaddr: u64) -> TagLo {
+        self.lines[Self::index(vaddr)].tag_lo()
+    }
+    pub fn store_tag(&mut self, vaddr: u64, tag: TagLo) {
+        self.lines[Self::index(vaddr)].set_tag_lo(tag);
+    }
+    /// Claims the line for `paddr` without reading memory, so the whole line can be overwritten.
+    pub fn create_dirty_exclusive(&mut self, vaddr: u64, paddr: u32, bus: &mut impl SysAd) -> Result<(), MipsErr> {
+        let index = Self::index(vaddr);
+        let line = &mut self.lines[index];
+        if !line.hit(paddr) {
+            Self::write_back(line, index, bus)?;
+        }
+        line.fill(paddr);
+        line.dirty = true;
+        Ok(())
+    }
+    pub fn hit_invalidate(&mut self, vaddr: u64, paddr: u32) {
+        let line = &mut self.lines[Self::index(vaddr)];
+        if line.hit(paddr) {
+            line.valid = false;
+        }
+    }
+    pub fn hit_write_back(&mut self, vaddr: u64, paddr: u32, invalidate: bool, bus: &mut impl SysAd) -> Result<(), MipsErr> {
+        let index = Self::index(vaddr);
+        let line = &mut self.lines[index];
+        if line.hit(paddr) {
+            Self::write_back(line, index, bus)?;
+            line.valid = !invalidate;
+        }
+        Ok(())
+    }
+
+    /// Returns the line holding `paddr`, writing back the previous contents and refilling it on a miss.
+    fn line(&mut self, vaddr: u64, paddr: u32, bus: &mut impl SysAd) -> Result<&mut CacheLine<4>, MipsErr> {
+        let index = Self::index(vaddr);
+        let line = &mut self.lines[index];
+        if !line.hit(paddr) {
+            Self::write_back(line, index, bus)?;
+            line.words = bus.read_cached_data(paddr & !DCACHE_LINE_MASK)?;
+            line.fill(paddr);
+        }
+        Ok(line)
+    }
+    /// Writes a valid, dirty line back to memory and marks it clean.
+    fn write_back(line: &mut CacheLine<4>, index: usize, bus: &mut impl SysAd) -> Result<(), MipsErr> {
+        if line.valid && line.dirty {
+            // The index bits above the page offset come from the virtual address, so they are not part of the physical address
+            let offset = (index as u32) << 4 & 0xFFF;
+            bus.write_cached_data(line.tag << 12 | offset, line.words)?;
+        }
+        line.dirty = false;
+        Ok(())
+    }
+
+    fn index(vaddr: u64) -> usize {
+        (vaddr as usize >> 4) % DCACHE_LINES
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct CacheLine<const N: usize> {
+    valid: bool,
+    dirty: bool,
+    /// Bits 12 and up of the physical address of the cached data.
+    tag: u32,
+    words: [Word; N],
+}
+impl<const N: usize> CacheLine<N> {
+    fn hit(&self, paddr: u32) -> bool {
+        self.valid && self.tag == paddr >> 12
+    }
+    fn fill(&mut self, paddr: u32) {
+        self.valid = true;
+        self.dirty = false;
+        self.tag = paddr >> 12;
+    }
+
+    fn tag_lo(&self) -> TagLo {
+        let pstate = (self.valid as u8) << 1 | self.dirty as u8;
+        TagLo::new().with_pstate(pstate).with_ptag_lo(self.tag)
+    }
+    fn set_tag_lo(&mut self, tag: TagLo) {
+        self.valid = tag.pstate() & 0b10 != 0;
+        self.dirty = tag.pstate() & 0b01 != 0;
+        self.tag = tag.ptag_lo();
+    }
+}
+impl<const N: usize> Default for CacheLine<N> {
+    fn default() -> Self {
+        Self {
+            valid: false,
+            dirty: false,
+            tag: 0,
+            words: [Word::zero(); N],
+        }
+    }
+}
+
+fn word_in_line(paddr: u32, line_mask: u32) -> usize {
+    ((paddr & line_mask) >> 2) as usize
+}
+
+const ICACHE_LINES: usize = 512;
+const ICACHE_LINE_MASK: u32 = 0x1F;
+const DCACHE_LINES: usize = 512;
+const DCACHE_LINE_MASK: u32 = 0xF;
+
+/// The cache an operation of the CACHE instruction applies to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cache {
+    Instruction,
+    Data,
+}
+
+/// The operations the CACHE instruction can perform.
+/// Index operations select a cache line by the low bits of the virtual address,
+/// hit operations only affect the line that currently holds the translated address.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CacheOp {
+    IndexInvalidate,
+    IndexWriteBackInvalidate,
+    IndexLoadTag,
+    IndexStoreTag,
+    CreateDirtyExclusive,
+    HitInvalidate,
+    HitWriteBackInvalidate,
+    HitWriteBack,
+    Fill,
+}
+impl CacheOp {
+    /// Decodes the `op` field of a CACHE instruction.
+    /// Returns None for the combinations the VR4300 leaves undefined, including all secondary cache operations.
+    pub fn decode(op: u8) -> Option<(Cache, Self)> {
+        let cache = match op & 0b11 {
+            0 => Cache::Instruction,
+            1 => Cache::Data,
+            _ => return None,
+        };
+
+        let operation = match (cache, op >> 2) {
+            (Cache::Instruction, 0) => Self::IndexInvalidate,
+            (Cache::Data, 0) => Self::IndexWriteBackInvalidate,
+            (_, 1) => Self::IndexLoadTag,
+            (_, 2) => Self::IndexStoreTag,
+            (Cache::Data, 3) => Self::CreateDirtyExclusive,
+            (_, 4) => Self::HitInvalidate,
+            (Cache::Instruction, 5) => Self::Fill,
+            (Cache::Data, 5) => Self::HitWriteBackInvalidate,
+            (_, 6) => Self::HitWriteBack,
+            _ => return None,
+        };
+
+        Some((cache, operation))
+    }
+
+    /// Whether the operation looks up the cache by the translated address, rather than by index.
+    pub fn is_hit_op(self) -> bool {
+        matches!(
+            self,
+            Self::CreateDirtyExclusive | Self::HitInvalidate | Self::HitWriteBackInvalidate | Self::HitWriteBack | Self::Fill
+        )
+    }
+}
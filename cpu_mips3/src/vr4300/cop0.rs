@@ -44,22 +44,158 @@ impl Cop0 {
     pub fn is_ksg0_cached(&self) -> bool {
         self.config.is_kseg0_cached()
     }
+
+    /// Random decrements once per instruction, wrapping back to 31 when it passes Wired.
+    pub fn tick_random(&mut self) {
+        let random = self.random.random();
+        let next = if random <= self.wired.wired() { 31 } else { random - 1 };
+        self.random.set_random(next);
+    }
+    /// Advances Count by one, raising the timer interrupt when it reaches Compare.
+    pub fn tick_count(&mut self) {
+        self.count.0 = self.count.0.wrapping_add(1);
+        if self.count.0 == self.compare.0 {
+            self.cause.set_ip(self.cause.ip() | TIMER_INTERRUPT);
+        }
+    }
+    /// Whether any unmasked interrupt is pending and interrupts are currently enabled.
+    pub fn interrupt_pending(&self) -> bool {
+        let status = self.status;
+        let enabled = status.ie() && !status.exl() && !status.erl();
+        enabled && self.cause.ip() & status.im() != 0
+    }
+
+    /// Reads a register as DMFC0 sees it. MFC0 uses the low 32 bits of this.
+    pub fn read(&self, reg: u8) -> u64 {
+        match reg {
+            REG_INDEX => self.index.into_bits() as u64,
+            REG_RANDOM => self.random.into_bits() as u64,
+            REG_ENTRY_LO0 => self.entry_lo0.into_bits() as u64,
+            REG_ENTRY_LO1 => self.entry_lo1.into_bits() as u64,
+            REG_CONTEXT => self.context.into_bits(),
+            REG_PAGE_MASK => self.page_mask.into_bits() as u64,
+            REG_WIRED => self.wired.into_bits() as u64,
+            REG_BAD_V_ADDR => self.bad_v_addr.0,
+            REG_COUNT => self.count.0 as u64,
+            REG_ENTRY_HI => self.entry_hi.into_bits(),
+            REG_COMPARE => self.compare.0 as u64,
+            REG_STATUS => self.status.into_bits() as u64,
+            REG_CAUSE => self.cause.into_bits() as u64,
+            REG_EPC => self.epc.0,
+            REG_PR_ID => PR_ID,
+            REG_CONFIG => self.config.into_bits() as u64,
+            REG_LL_ADDR => self.ll_addr.0 as u64,
+            REG_WATCH_LO => self.watch_lo.into_bits() as u64,
+            REG_WATCH_HI => self.watch_hi.into_bits() as u64,
+            REG_X_CONTEXT => self.x_context.into_bits(),
+            REG_P_ERR => self.p_err.into_bits() as u64,
+            REG_TAG_LO => self.tag_lo.into_bits() as u64,
+            REG_ERROR_EPC => self.error_epc.0,
+            // CacheErr is never set because parity errors do not happen, and TagHi is reserved
+            _ => 0,
+        }
+    }
+    /// Writes a register as DMTC0 does. Only the writable bits of each register are changed.
+    pub fn write(&mut self, reg: u8, value: u64) {
+        match reg {
+            REG_INDEX => self.index = Index::from_bits(masked_32(self.index.into_bits(), value, INDEX_MASK)),
+            REG_ENTRY_LO0 => self.entry_lo0 = EntryLo::from_bits(value as u32 & ENTRY_LO_MASK),
+            REG_ENTRY_LO1 => self.entry_lo1 = EntryLo::from_bits(value as u32 & ENTRY_LO_MASK),
+            REG_CONTEXT => self.context = Context::from_bits(masked_64(self.context.into_bits(), value, CONTEXT_MASK)),
+            REG_PAGE_MASK => self.page_mask = PageMask::from_bits(value as u32 & PAGE_MASK_MASK),
+            REG_WIRED => {
+                self.wired = Wired::from_bits(value as u32 & WIRED_MASK);
+                self.random.set_random(31);
+            }
+            REG_COUNT => self.count.0 = value as u32,
+            REG_ENTRY_HI => self.entry_hi = EntryHi::from_bits(value & ENTRY_HI_MASK),
+            REG_COMPARE => {
+                self.compare.0 = value as u32;
+                self.cause.set_ip(self.cause.ip() & !TIMER_INTERRUPT);
+            }
+            REG_STATUS => self.status = Status::from_bits(masked_32(self.status.into_bits(), value, STATUS_MASK)),
+            REG_CAUSE => self.cause = Cause::from_bits(masked_32(self.cause.into_bits(), value, CAUSE_MASK)),
+            REG_EPC => self.epc.0 = value,
+            REG_CONFIG => self.config = Config::from_bits(masked_32(self.config.into_bits(), value, CONFIG_MASK)),
+            REG_LL_ADDR => self.ll_addr.0 = value as u32,
+            REG_WATCH_LO => self.watch_lo = WatchLo::from_bits(value as u32 & WATCH_LO_MASK),
+            REG_WATCH_HI => self.watch_hi = WatchHi::from_bits(value as u32 & WATCH_HI_MASK),
+            REG_X_CONTEXT => self.x_context = XContext::from_bits(masked_64(self.x_context.into_bits(), value, X_CONTEXT_MASK)),
+            REG_P_ERR => self.p_err = PErr::from_bits(value as u32 & P_ERR_MASK),
+            REG_TAG_LO => self.tag_lo = TagLo::from_bits(value as u32 & TAG_LO_MASK),
+            REG_ERROR_EPC => self.error_epc.0 = value,
+            // Random, BadVAddr, PRId and CacheErr are read-only, TagHi and the remaining numbers are reserved
+            _ => (),
+        }
+    }
+}
+
+/// Replaces the bits of `old` selected by `mask` with those of `new`.
+fn masked_32(old: u32, new: u64, mask: u32) -> u32 {
+    (old & !mask) | (new as u32 & mask)
+}
+fn masked_64(old: u64, new: u64, mask: u64) -> u64 {
+    (old & !mask) | (new & mask)
 }
 
+const REG_INDEX: u8 = 0;
+const REG_RANDOM: u8 = 1;
+const REG_ENTRY_LO0: u8 = 2;
+const REG_ENTRY_LO1: u8 = 3;
+const REG_CONTEXT: u8 = 4;
+const REG_PAGE_MASK: u8 = 5;
+const REG_WIRED: u8 = 6;
+const REG_BAD_V_ADDR: u8 = 8;
+const REG_COUNT: u8 = 9;
+const REG_ENTRY_HI: u8 = 10;
+const REG_COMPARE: u8 = 11;
+const REG_STATUS: u8 = 12;
+const REG_CAUSE: u8 = 13;
+const REG_EPC: u8 = 14;
+const REG_PR_ID: u8 = 15;
+const REG_CONFIG: u8 = 16;
+const REG_LL_ADDR: u8 = 17;
+const REG_WATCH_LO: u8 = 18;
+const REG_WATCH_HI: u8 = 19;
+const REG_X_CONTEXT: u8 = 20;
+const REG_P_ERR: u8 = 26;
+const REG_TAG_LO: u8 = 28;
+const REG_ERROR_EPC: u8 = 30;
+
+/// The timer interrupt is wired to IP7.
+const TIMER_INTERRUPT: u8 = 1 << 7;
+
+const PR_ID: u64 = 0x0B22;
+
+const INDEX_MASK: u32 = 0x8000_003F;
+const ENTRY_LO_MASK: u32 = 0x3FFF_FFFF;
+const CONTEXT_MASK: u64 = 0xFFFF_FFFF_FF80_0000;
+const PAGE_MASK_MASK: u32 = 0x01FF_E000;
+const WIRED_MASK: u32 = 0x3F;
+const ENTRY_HI_MASK: u64 = 0xC000_00FF_FFFF_E0FF;
+const STATUS_MASK: u32 = 0xFF57_FFFF;
+const CAUSE_MASK: u32 = 0x0000_0300;
+const CONFIG_MASK: u32 = 0x0F00_800F;
+const WATCH_LO_MASK: u32 = 0xFFFF_FFFB;
+const WATCH_HI_MASK: u32 = 0x0000_000F;
+const X_CONTEXT_MASK: u64 = 0xFFFF_FFFE_0000_0000;
+const P_ERR_MASK: u32 = 0x0000_00FF;
+const TAG_LO_MASK: u32 = 0x0FFF_FFC0;
+
 #[bitfield(u32)]
 pub struct Index {
     #[bits(5)]
-    index: u8,
+    pub index: u8,
     bit: bool,
     #[bits(25)]
     _rfu: usize,
-    p: bool,
+    pub p: bool,
 }
 
 #[bitfield(u32)]
 pub struct Random {
     #[bits(5, default = 31)]
-    random: u8,
+    pub random: u8,
     bit: bool,
     #[bits(26)]
     _rfu: usize,
@@ -67,13 +203,13 @@ pub struct Random {
 
 #[bitfield(u32)]
 pub struct EntryLo {
-    g: bool,
-    v: bool,
-    d: bool,
+    pub g: bool,
+    pub v: bool,
+    pub d: bool,
     #[bits(3)]
-    c: u8,
+    pub c: u8,
     #[bits(20)]
-    pfn: u32,
+    pub pfn: u32,
     #[bits(6)]
     _rfu: usize,
 }
@@ -83,9 +219,9 @@ pub struct Context {
     #[bits(4)]
     _rfu: usize,
     #[bits(19)]
-    bad_vpn2: u32,
+    pub bad_vpn2: u32,
     #[bits(41)]
-    pte_base: u64,
+    pub pte_base: u64,
 }
 
 #[bitfield(u32)]
@@ -93,7 +229,7 @@ pub struct PageMask {
     #[bits(13)]
     _rfu: usize,
     #[bits(12)]
-    mask: u16,
+    pub mask: u16,
     #[bits(7)]
     _rfu: usize,
 }
@@ -101,45 +237,45 @@ pub struct PageMask {
 #[bitfield(u32)]
 pub struct Wired {
     #[bits(6)]
-    wired: u8,
+    pub wired: u8,
     #[bits(26)]
     _rfu: usize,
 }
 
 #[derive(Copy, Clone, Debug, Default)]
-pub struct BadVAddr(u64);
+pub struct BadVAddr(pub u64);
 
 #[derive(Copy, Clone, Debug, Default)]
-pub struct Count(u32);
+pub struct Count(pub u32);
 
 #[bitfield(u64)]
 pub struct EntryHi {
-    asid: u8,
+    pub asid: u8,
     #[bits(5)]
     _rfu: usize,
     #[bits(27)]
-    vpn2: u32,
+    pub vpn2: u32,
     #[bits(22)]
     _fill: usize,
     #[bits(2)]
-    r: u8,
+    pub r: u8,
 }
 
 #[derive(Copy, Clone, Debug, Default)]
-pub struct Compare(u32);
+pub struct Compare(pub u32);
 
 #[bitfield(u32)]
 pub struct Status {
-    ie: bool,
-    exl: bool,
+    pub ie: bool,
+    pub exl: bool,
     #[bits(default = true)]
-    erl: bool,
+    pub erl: bool,
     #[bits(2)]
-    ksu: u8,
-    ux: bool,
-    sx: bool,
-    kx: bool,
-    im: u8,
+    pub ksu: u8,
+    pub ux: bool,
+    pub sx: bool,
+    pub kx: bool,
+    pub im: u8,
     de: bool,
     ce: bool,
     ch: bool,
@@ -147,16 +283,16 @@ pub struct Status {
     sr: bool,
     ts: bool,
     #[bits(default = true)]
-    bev: bool,
+    pub bev: bool,
     _rfu: bool,
     its: bool,
     re: bool,
-    fr: bool,
+    pub fr: bool,
     rp: bool,
-    cu0: bool,
-    cu1: bool,
-    cu2: bool,
-    cu3: bool,
+    pub cu0: bool,
+    pub cu1: bool,
+    pub cu2: bool,
+    pub cu3: bool,
 }
 impl Status {
     pub fn mode(self) -> Mode {
@@ -186,19 +322,19 @@ pub struct Cause {
     #[bits(2)]
     _rfu: usize,
     #[bits(5)]
-    exec_code: u8,
+    pub exec_code: u8,
     _rfu: bool,
-    ip: u8,
+    pub ip: u8,
     #[bits(12)]
     _rfu: u16,
     #[bits(2)]
-    ce: u8,
+    pub ce: u8,
     _rfu: bool,
-    bd: bool,
+    pub bd: bool,
 }
 
 #[derive(Copy, Clone, Debug, Default)]
-pub struct EPC(u64);
+pub struct EPC(pub u64);
 
 #[bitfield(u32)]
 pub struct Config {
@@ -224,7 +360,7 @@ impl Config {
 }
 
 #[derive(Copy, Clone, Debug, Default)]
-pub struct LLAddr(u32);
+pub struct LLAddr(pub u32);
 
 #[bitfield(u32)]
 pub struct WatchLo {
@@ -248,11 +384,11 @@ pub struct XContext {
     #[bits(4)]
     _rfu: usize,
     #[bits(27)]
-    bad_vpn2: u32,
+    pub bad_vpn2: u32,
     #[bits(2)]
-    r: u8,
+    pub r: u8,
     #[bits(31)]
-    pte_base: u32,
+    pub pte_base: u32,
 }
 
 #[bitfield(u32)]
@@ -267,15 +403,15 @@ pub struct TagLo {
     #[bits(6)]
     _rfu: usize,
     #[bits(2)]
-    pstate: u8,
+    pub pstate: u8,
     #[bits(20)]
-    ptag_lo: u32,
+    pub ptag_lo: u32,
     #[bits(4)]
     _rfu: usize,
 }
 
 #[derive(Copy, Clone, Debug, Default)]
-pub struct ErrorEPC(u64);
+pub struct ErrorEPC(pub u64);
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Mode {
@@ -0,0 +1,693 @@
+use bitfield_struct::bitfield;
+use util::sext_32;
+
+use crate::{
+    core::{MipsResult, RawCore},
+    instruction::{
+        Instr, Reg, FMT_D, FMT_L, FMT_S, FMT_W, OP_C1_ABS, OP_C1_ADD, OP_C1_CEIL_L, OP_C1_CEIL_W,
+        OP_C1_CVT_D, OP_C1_CVT_L, OP_C1_CVT_S, OP_C1_CVT_W, OP_C1_C_FIRST, OP_C1_C_LAST, OP_C1_DIV,
+        OP_C1_FLOOR_L, OP_C1_FLOOR_W, OP_C1_MOV, OP_C1_MUL, OP_C1_NEG, OP_C1_ROUND_L,
+        OP_C1_ROUND_W, OP_C1_SQRT, OP_C1_SUB, OP_C1_TRUNC_L, OP_C1_TRUNC_W,
+    },
+};
+
+use super::{Exception, SysAd, Vr4300};
+
+pub struct Cop1 {
+    fpr: [u64; 32],
+    pub fcr31: Fcr31,
+}
+impl Cop1 {
+    pub fn init() -> Self {
+        Self {
+            fpr: [0; 32],
+            fcr31: Fcr31::new(),
+        }
+    }
+
+    /// Reads the 32 bit view of an FPR.
+    /// With Status.FR clear, odd registers alias the upper half of the even register below them.
+    pub fn get_s(&self, reg: Reg, fr: bool) -> u32 {
+        let (index, shift) = Self::single_location(reg, fr);
+        (self.fpr[index] >> shift) as u32
+    }
+    pub fn set_s(&mut self, reg: Reg, value: u32, fr: bool) {
+        let (index, shift) = Self::single_location(reg, fr);
+        let mask = 0xFFFF_FFFF << shift;
+        self.fpr[index] = (self.fpr[index] & !mask) | ((value as u64) << shift);
+    }
+    /// Reads the 64 bit view of an FPR.
+    /// With Status.FR clear, only the 16 even registers are addressable this way.
+    pub fn get_d(&self, reg: Reg, fr: bool) -> u64 {
+        self.fpr[Self::double_location(reg, fr)]
+    }
+    pub fn set_d(&mut self, reg: Reg, value: u64, fr: bool) {
+        self.fpr[Self::double_location(reg, fr)] = value;
+    }
+
+    fn single_location(reg: Reg, fr: bool) -> (usize, u32) {
+        let reg = reg.0 as usize;
+        if fr || reg.is_multiple_of(2) {
+            (reg, 0)
+        }
+        else {
+            (reg - 1, 32)
+        }
+    }
+    fn double_location(reg: Reg, fr: bool) -> usize {
+        let reg = reg.0 as usize;
+        if fr {
+            reg
+        }
+        else {
+            reg & !1
+        }
+    }
+}
+
+#[bitfield(u32)]
+pub struct Fcr31 {
+    #[bits(2)]
+    pub rm: u8,
+    #[bits(5)]
+    pub flags: u8,
+    #[bits(5)]
+    pub enables: u8,
+    #[bits(6)]
+    pub cause: u8,
+    #[bits(5)]
+    _rfu: u8,
+    pub c: bool,
+    pub fs: bool,
+    #[bits(7)]
+    _rfu: u8,
+}
+impl Fcr31 {
+    fn rounding_mode(self) -> RoundingMode {
+        match self.rm() {
+            0 => RoundingMode::Nearest,
+            1 => RoundingMode::Zero,
+            2 => RoundingMode::Up,
+            _ => RoundingMode::Down,
+        }
+    }
+}
+
+impl Vr4300 {
+    fn fr(&self) -> bool {
+        self.cop0.status.fr()
+    }
+
+    pub(super) fn do_mfc1(&mut self, instr: Instr) -> MipsResult<()> {
+        self.require_cop(1)?;
+        let value = self.cop1.get_s(instr.fs(), self.fr());
+        self.set_reg_u64(instr.rt(), sext_32(value))
+    }
+    pub(super) fn do_dmfc1(&mut self, instr: Instr) -> MipsResult<()> {
+        self.require_cop(1)?;
+        let value = self.cop1.get_d(instr.fs(), self.fr());
+        self.set_reg_u64(instr.rt(), value)
+    }
+    pub(super) fn do_mtc1(&mut self, instr: Instr) -> MipsResult<()> {
+        self.require_cop(1)?;
+        let value = self.get_reg_u32(instr.rt())?;
+        let fr = self.fr();
+        self.cop1.set_s(instr.fs(), value, fr);
+        Ok(())
+    }
+    pub(super) fn do_dmtc1(&mut self, instr: Instr) -> MipsResult<()> {
+        self.require_cop(1)?;
+        let value = self.get_reg_u64(instr.rt())?;
+        let fr = self.fr();
+        self.cop1.set_d(instr.fs(), value, fr);
+        Ok(())
+    }
+    pub(super) fn do_cfc1(&mut self, instr: Instr) -> MipsResult<()> {
+        self.require_cop(1)?;
+        let value = match instr.fs().0 {
+            0 => FCR0,
+            31 => self.cop1.fcr31.into_bits(),
+            _ => 0,
+        };
+        self.set_reg_u64(instr.rt(), sext_32(value))
+    }
+    pub(super) fn do_ctc1(&mut self, instr: Instr) -> MipsResult<()> {
+        self.require_cop(1)?;
+        if instr.fs().0 != 31 {
+            return Ok(());
+        }
+
+        let value = self.get_reg_u32(instr.rt())? & FCR31_WRITE_MASK;
+        self.cop1.fcr31 = Fcr31::from_bits(value);
+
+        // Writing a cause bit whose exception is enabled traps immediately
+        let fcr31 = self.cop1.fcr31;
+        if fcr31.cause() & (fcr31.enables() | CAUSE_UNIMPLEMENTED) != 0 {
+            return self.take_exception(Exception::FloatingPoint);
+        }
+        Ok(())
+    }
+    pub(super) fn do_bc1(&mut self, instr: Instr, likely: bool, on: bool) -> MipsResult<()> {
+        self.require_cop(1)?;
+        let taken = self.cop1.fcr31.c() == on;
+        self.do_branch(instr, taken, likely)
+    }
+
+    pub(super) fn do_lwc1(&mut self, instr: Instr, bus: &mut impl SysAd) -> MipsResult<()> {
+        self.require_cop(1)?;
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.read_data_word(vaddr, bus)?;
+        let fr = self.fr();
+        self.cop1.set_s(instr.ft(), value, fr);
+        Ok(())
+    }
+    pub(super) fn do_ldc1(&mut self, instr: Instr, bus: &mut impl SysAd) -> MipsResult<()> {
+        self.require_cop(1)?;
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.read_data_dword(vaddr, bus)?;
+        let fr = self.fr();
+        self.cop1.set_d(instr.ft(), value, fr);
+        Ok(())
+    }
+    pub(super) fn do_swc1(&mut self, instr: Instr, bus: &mut impl SysAd) -> MipsResult<()> {
+        self.require_cop(1)?;
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.cop1.get_s(instr.ft(), self.fr());
+        self.write_data_word(vaddr, value, bus)
+    }
+    pub(super) fn do_sdc1(&mut self, instr: Instr, bus: &mut impl SysAd) -> MipsResult<()> {
+        self.require_cop(1)?;
+        let vaddr = self.mem_vaddr(instr)?;
+        let value = self.cop1.get_d(instr.ft(), self.fr());
+        self.write_data_dword(vaddr, value, bus)
+    }
+
+    pub(super) fn do_cop1_op(&mut self, instr: Instr) -> MipsResult<()> {
+        self.require_cop(1)?;
+        match instr.funct() {
+            OP_C1_ADD => self.fpu_binary(instr, add),
+            OP_C1_SUB => self.fpu_binary(instr, sub),
+            OP_C1_MUL => self.fpu_binary(instr, mul),
+            OP_C1_DIV => self.fpu_binary(instr, div),
+            OP_C1_SQRT => self.fpu_unary(instr, sqrt),
+            OP_C1_ABS => self.fpu_sign(instr, f64::abs),
+            OP_C1_MOV => self.fpu_mov(instr),
+            OP_C1_NEG => self.fpu_sign(instr, |v| -v),
+            OP_C1_ROUND_L => self.fpu_to_integer(instr, Some(RoundingMode::Nearest), true),
+            OP_C1_TRUNC_L => self.fpu_to_integer(instr, Some(RoundingMode::Zero), true),
+            OP_C1_CEIL_L => self.fpu_to_integer(instr, Some(RoundingMode::Up), true),
+            OP_C1_FLOOR_L => self.fpu_to_integer(instr, Some(RoundingMode::Down), true),
+            OP_C1_ROUND_W => self.fpu_to_integer(instr, Some(RoundingMode::Nearest), false),
+            OP_C1_TRUNC_W => self.fpu_to_integer(instr, Some(RoundingMode::Zero), false),
+            OP_C1_CEIL_W => self.fpu_to_integer(instr, Some(RoundingMode::Up), false),
+            OP_C1_FLOOR_W => self.fpu_to_integer(instr, Some(RoundingMode::Down), false),
+            OP_C1_CVT_S => self.fpu_to_float(instr, Precision::Single),
+            OP_C1_CVT_D => self.fpu_to_float(instr, Precision::Double),
+            OP_C1_CVT_W => self.fpu_to_integer(instr, None, false),
+            OP_C1_CVT_L => self.fpu_to_integer(instr, None, true),
+            OP_C1_C_FIRST..=OP_C1_C_LAST => self.fpu_compare(instr),
+            _ => self.fpu_finish(CAUSE_UNIMPLEMENTED),
+        }
+    }
+
+    fn fpu_binary(&mut self, instr: Instr, op: fn(f64, f64) -> Outcome) -> MipsResult<()> {
+        let Some(precision) = Precision::of(instr) else { return self.fpu_finish(CAUSE_UNIMPLEMENTED) };
+        let a = self.read_operand(instr.fs(), precision);
+        let b = self.read_operand(instr.ft(), precision);
+
+        let cause = a.input_cause() | b.input_cause();
+        if cause != 0 {
+            self.fpu_finish(cause)?;
+            return self.write_float(instr.fd(), precision, precision.default_nan());
+        }
+
+        let outcome = op(a.value, b.value);
+        self.fpu_complete(instr.fd(), precision, outcome)
+    }
+    fn fpu_unary(&mut self, instr: Instr, op: fn(f64) -> Outcome) -> MipsResult<()> {
+        let Some(precision) = Precision::of(instr) else { return self.fpu_finish(CAUSE_UNIMPLEMENTED) };
+        let a = self.read_operand(instr.fs(), precision);
+
+        let cause = a.input_cause();
+        if cause != 0 {
+            self.fpu_finish(cause)?;
+            return self.write_float(instr.fd(), precision, precision.default_nan());
+        }
+
+        let outcome = op(a.value);
+        self.fpu_complete(instr.fd(), precision, outcome)
+    }
+    fn fpu_sign(&mut self, instr: Instr, op: fn(f64) -> f64) -> MipsResult<()> {
+        let Some(precision) = Precision::of(instr) else { return self.fpu_finish(CAUSE_UNIMPLEMENTED) };
+        let a = self.read_operand(instr.fs(), precision);
+
+        let cause = a.input_cause();
+        if cause != 0 {
+            self.fpu_finish(cause)?;
+            return self.write_float(instr.fd(), precision, precision.default_nan());
+        }
+
+        self.fpu_finish(0)?;
+        let bits = precision.to_bits(op(a.value));
+        self.write_float(instr.fd(), precision, bits)
+    }
+    fn fpu_mov(&mut self, instr: Instr) -> MipsResult<()> {
+        let Some(precision) = Precision::of(instr) else { return self.fpu_finish(CAUSE_UNIMPLEMENTED) };
+        let fr = self.fr();
+        match precision {
+            Precision::Single => {
+                let value = self.cop1.get_s(instr.fs(), fr);
+                self.cop1.set_s(instr.fd(), value, fr);
+            }
+            Precision::Double => {
+                let value = self.cop1.get_d(instr.fs(), fr);
+                self.cop1.set_d(instr.fd(), value, fr);
+            }
+        }
+        Ok(())
+    }
+    fn fpu_compare(&mut self, instr: Instr) -> MipsResult<()> {
+        let Some(precision) = Precision::of(instr) else { return self.fpu_finish(CAUSE_UNIMPLEMENTED) };
+        let a = self.read_operand(instr.fs(), precision);
+        let b = self.read_operand(instr.ft(), precision);
+        let cond = instr.funct() & 0xF;
+
+        let unordered = a.value.is_nan() || b.value.is_nan();
+        let signals = cond & COND_SIGNAL != 0 || a.signaling || b.signaling;
+        let cause = if unordered && signals { CAUSE_INVALID } else { 0 };
+
+        let result = (cond & COND_UNORDERED != 0 && unordered)
+            || (cond & COND_EQUAL != 0 && a.value == b.value)
+            || (cond & COND_LESS != 0 && a.value < b.value);
+
+        self.fpu_finish(cause)?;
+        self.cop1.fcr31.set_c(result);
+        Ok(())
+    }
+    fn fpu_to_integer(&mut self, instr: Instr, mode: Option<RoundingMode>, long: bool) -> MipsResult<()> {
+        let Some(precision) = Precision::of(instr) else { return self.fpu_finish(CAUSE_UNIMPLEMENTED) };
+        let a = self.read_operand(instr.fs(), precision);
+        let mode = mode.unwrap_or(self.cop1.fcr31.rounding_mode());
+
+        // Unlike IEEE invalid operations, the VR4300 leaves out-of-range conversions to software
+        if a.subnormal || !a.value.is_finite() {
+            return self.fpu_finish(CAUSE_UNIMPLEMENTED);
+        }
+        let rounded = mode.round_to_integral(a.value);
+        let limit = if long { LONG_CONVERSION_LIMIT } else { WORD_CONVERSION_LIMIT };
+        if rounded >= limit || rounded < -limit {
+            return self.fpu_finish(CAUSE_UNIMPLEMENTED);
+        }
+
+        let cause = if rounded != a.value { CAUSE_INEXACT } else { 0 };
+        self.fpu_finish(cause)?;
+
+        let fr = self.fr();
+        if long {
+            self.cop1.set_d(instr.fd(), rounded as i64 as u64, fr);
+        }
+        else {
+            self.cop1.set_s(instr.fd(), rounded as i32 as u32, fr);
+        }
+        Ok(())
+    }
+    fn fpu_to_float(&mut self, instr: Instr, to: Precision) -> MipsResult<()> {
+        let fr = self.fr();
+        let outcome = match instr.fmt() {
+            FMT_W => {
+                let value = self.cop1.get_s(instr.fs(), fr) as i32;
+                integer_to_float(value as i64)
+            }
+            FMT_L => {
+                let value = self.cop1.get_d(instr.fs(), fr) as i64;
+                if !(-LONG_TO_FLOAT_LIMIT..LONG_TO_FLOAT_LIMIT).contains(&value) {
+                    return self.fpu_finish(CAUSE_UNIMPLEMENTED);
+                }
+                integer_to_float(value)
+            }
+            FMT_S | FMT_D => {
+                let Some(from) = Precision::of(instr) else { unreachable!() };
+                if from == to {
+                    return self.fpu_finish(CAUSE_UNIMPLEMENTED);
+                }
+                let a = self.read_operand(instr.fs(), from);
+                let cause = a.input_cause();
+                if cause != 0 {
+                    self.fpu_finish(cause)?;
+                    return self.write_float(instr.fd(), to, to.default_nan());
+                }
+                Outcome::Value(a.value, 0.0)
+            }
+            _ => return self.fpu_finish(CAUSE_UNIMPLEMENTED),
+        };
+
+        self.fpu_complete(instr.fd(), to, outcome)
+    }
+
+    /// Rounds the outcome of an operation, raises any resulting exceptions and writes the result back.
+    fn fpu_complete(&mut self, fd: Reg, precision: Precision, outcome: Outcome) -> MipsResult<()> {
+        let fcr31 = self.cop1.fcr31;
+        let (bits, cause) = match outcome {
+            Outcome::Invalid => (precision.default_nan(), CAUSE_INVALID),
+            Outcome::DivideByZero(value) => (precision.to_bits(value), CAUSE_DIVIDE_BY_ZERO),
+            Outcome::Infinite(value) => (precision.to_bits(value), 0),
+            Outcome::Value(value, error) => round(value, error, precision, fcr31),
+        };
+
+        self.fpu_finish(cause)?;
+        self.write_float(fd, precision, bits)
+    }
+    /// Latches the cause bits of an FPU operation, trapping if any of them are enabled.
+    fn fpu_finish(&mut self, cause: u8) -> MipsResult<()> {
+        let fcr31 = &mut self.cop1.fcr31;
+        fcr31.set_cause(cause);
+        if cause & (fcr31.enables() | CAUSE_UNIMPLEMENTED) != 0 {
+            return self.take_exception(Exception::FloatingPoint);
+        }
+
+        fcr31.set_flags(fcr31.flags() | cause);
+        Ok(())
+    }
+
+    fn read_operand(&self, reg: Reg, precision: Precision) -> Operand {
+        let fr = self.fr();
+        match precision {
+            Precision::Single => {
+                let bits = self.cop1.get_s(reg, fr);
+                let value = f32::from_bits(bits);
+                Operand {
+                    value: value as f64,
+                    subnormal: value.is_subnormal(),
+                    signaling: value.is_nan() && bits & SINGLE_SIGNAL_BIT != 0,
+                }
+            }
+            Precision::Double => {
+                let bits = self.cop1.get_d(reg, fr);
+                let value = f64::from_bits(bits);
+                Operand {
+                    value,
+                    subnormal: value.is_subnormal(),
+                    signaling: value.is_nan() && bits & DOUBLE_SIGNAL_BIT != 0,
+                }
+            }
+        }
+    }
+    fn write_float(&mut self, reg: Reg, precision: Precision, bits: u64) -> MipsResult<()> {
+        let fr = self.fr();
+        match precision {
+            Precision::Single => self.cop1.set_s(reg, bits as u32, fr),
+            Precision::Double => self.cop1.set_d(reg, bits, fr),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Precision {
+    Single,
+    Double,
+}
+impl Precision {
+    fn of(instr: Instr) -> Option<Self> {
+        match instr.fmt() {
+            FMT_S => Some(Self::Single),
+            FMT_D => Some(Self::Double),
+            _ => None,
+        }
+    }
+
+    fn default_nan(self) -> u64 {
+        match self {
+            Self::Single => SINGLE_DEFAULT_NAN as u64,
+            Self::Double => DOUBLE_DEFAULT_NAN,
+        }
+    }
+    fn to_bits(self, value: f64) -> u64 {
+        match self {
+            Self::Single => (value as f32).to_bits() as u64,
+            Self::Double => value.to_bits(),
+        }
+    }
+    fn min_normal(self) -> f64 {
+        match self {
+            Self::Single => f32::MIN_POSITIVE as f64,
+            Self::Double => f64::MIN_POSITIVE,
+        }
+    }
+    fn max(self) -> f64 {
+        match self {
+            Self::Single => f32::MAX as f64,
+            Self::Double => f64::MAX,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum RoundingMode {
+    Nearest,
+    Zero,
+    Up,
+    Down,
+}
+impl RoundingMode {
+    fn round_to_integral(self, value: f64) -> f64 {
+        match self {
+            Self::Nearest => value.round_ties_even(),
+            Self::Zero => value.trunc(),
+            Self::Up => value.ceil(),
+            Self::Down => value.floor(),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Operand {
+    value: f64,
+    subnormal: bool,
+    signaling: bool,
+}
+impl Operand {
+    /// The VR4300 cannot compute on denormals or quiet NaNs in hardware and leaves those to software.
+    fn input_cause(self) -> u8 {
+        if self.signaling {
+            CAUSE_INVALID
+        }
+        else if self.subnormal || self.value.is_nan() {
+            CAUSE_UNIMPLEMENTED
+        }
+        else {
+            0
+        }
+    }
+}
+
+/// The unrounded result of an operation.
+/// `Value(v, e)` carries the round-to-nearest double result `v`, with the sign of `e` telling
+/// on which side of `v` the infinitely precise result lies (zero if `v` is exact).
+enum Outcome {
+    Value(f64, f64),
+    Infinite(f64),
+    DivideByZero(f64),
+    Invalid,
+}
+
+fn add(a: f64, b: f64) -> Outcome {
+    if a.is_infinite() || b.is_infinite() {
+        if a.is_infinite() && b.is_infinite() && a.signum() != b.signum() {
+            return Outcome::Invalid;
+        }
+        return Outcome::Infinite(a + b);
+    }
+
+    let sum = a + b;
+    if sum.is_infinite() {
+        return Outcome::Value(sum, 0.0);
+    }
+    let b_virtual = sum - a;
+    let a_virtual = sum - b_virtual;
+    let error = (a - a_virtual) + (b - b_virtual);
+    Outcome::Value(sum, error)
+}
+fn sub(a: f64, b: f64) -> Outcome {
+    add(a, -b)
+}
+fn mul(a: f64, b: f64) -> Outcome {
+    if a.is_infinite() || b.is_infinite() {
+        if a == 0.0 || b == 0.0 {
+            return Outcome::Invalid;
+        }
+        return Outcome::Infinite(a * b);
+    }
+
+    let product = a * b;
+    if product.is_infinite() {
+        return Outcome::Value(product, 0.0);
+    }
+    let error = a.mul_add(b, -product);
+    Outcome::Value(product, error)
+}
+fn div(a: f64, b: f64) -> Outcome {
+    if (a == 0.0 && b == 0.0) || (a.is_infinite() && b.is_infinite()) {
+        return Outcome::Invalid;
+    }
+    if b == 0.0 {
+        return Outcome::DivideByZero(a.signum() * b.signum() * f64::INFINITY);
+    }
+    if a.is_infinite() || b.is_infinite() {
+        return Outcome::Infinite(a / b);
+    }
+
+    let quotient = a / b;
+    if quotient.is_infinite() {
+        return Outcome::Value(quotient, 0.0);
+    }
+    let remainder = (-quotient).mul_add(b, a);
+    Outcome::Value(quotient, remainder * b.signum())
+}
+fn sqrt(a: f64) -> Outcome {
+    if a < 0.0 {
+        return Outcome::Invalid;
+    }
+    if a.is_infinite() {
+        return Outcome::Infinite(a);
+    }
+
+    let root = a.sqrt();
+    let remainder = (-root).mul_add(root, a);
+    Outcome::Value(root, remainder)
+}
+fn integer_to_float(value: i64) -> Outcome {
+    let float = value as f64;
+    let error = (value as i128 - float as i128) as f64;
+    Outcome::Value(float, error)
+}
+
+/// Rounds an unrounded result to the target precision and returns its bits together with the cause bits.
+fn round(value: f64, error: f64, precision: Precision, fcr31: Fcr31) -> (u64, u8) {
+    let mode = fcr31.rounding_mode();
+    let (rounded, inexact) = match precision {
+        Precision::Single => {
+            let (rounded, inexact) = round_single(value, error, mode);
+            (rounded as f64, inexact)
+        }
+        Precision::Double => round_double(value, error, mode),
+    };
+
+    if rounded.is_infinite() {
+        let rounded = overflow_result(value.is_sign_negative(), precision, mode);
+        return (precision.to_bits(rounded), CAUSE_OVERFLOW | CAUSE_INEXACT);
+    }
+
+    let exact_zero = value == 0.0 && error == 0.0;
+    if !exact_zero && value.abs() < precision.min_normal() {
+        // Denormal results can only be produced by flushing, and only if the software asked for it
+        let flush = fcr31.fs() && fcr31.enables() & (CAUSE_UNDERFLOW | CAUSE_INEXACT) == 0;
+        if !flush {
+            return (precision.to_bits(value), CAUSE_UNIMPLEMENTED);
+        }
+        let rounded = flush_result(value.is_sign_negative(), precision, mode);
+        return (precision.to_bits(rounded), CAUSE_UNDERFLOW | CAUSE_INEXACT);
+    }
+
+    let cause = if inexact { CAUSE_INEXACT } else { 0 };
+    (precision.to_bits(rounded), cause)
+}
+fn round_double(value: f64, error: f64, mode: RoundingMode) -> (f64, bool) {
+    (nudge_double(value, error, mode), error != 0.0)
+}
+fn round_single(value: f64, error: f64, mode: RoundingMode) -> (f32, bool) {
+    let nearest = value as f32;
+    if nearest.is_infinite() {
+        return (nearest, true);
+    }
+
+    let difference = value - nearest as f64;
+    if difference == 0.0 {
+        return (nudge_single(nearest, error, mode), error != 0.0);
+    }
+
+    let (low, high) = if difference > 0.0 {
+        (nearest, nearest.next_up())
+    }
+    else {
+        (nearest.next_down(), nearest)
+    };
+    let rounded = match mode {
+        RoundingMode::Nearest => {
+            // Double rounding can only go wrong if the double result sits exactly on a tie
+            let other = if difference > 0.0 { high } else { low };
+            let midpoint = (nearest as f64 + other as f64) / 2.0;
+            let beyond = error != 0.0 && (error > 0.0) == (difference > 0.0);
+            if value == midpoint && beyond { other } else { nearest }
+        }
+        RoundingMode::Zero => if value > 0.0 { low } else { high },
+        RoundingMode::Up => high,
+        RoundingMode::Down => low,
+    };
+    (rounded, true)
+}
+fn nudge_double(value: f64, error: f64, mode: RoundingMode) -> f64 {
+    match mode {
+        RoundingMode::Nearest => value,
+        RoundingMode::Zero if value > 0.0 && error < 0.0 => value.next_down(),
+        RoundingMode::Zero if value < 0.0 && error > 0.0 => value.next_up(),
+        RoundingMode::Zero => value,
+        RoundingMode::Up if error > 0.0 => value.next_up(),
+        RoundingMode::Down if error < 0.0 => value.next_down(),
+        RoundingMode::Up | RoundingMode::Down => value,
+    }
+}
+fn nudge_single(value: f32, error: f64, mode: RoundingMode) -> f32 {
+    match mode {
+        RoundingMode::Nearest => value,
+        RoundingMode::Zero if value > 0.0 && error < 0.0 => value.next_down(),
+        RoundingMode::Zero if value < 0.0 && error > 0.0 => value.next_up(),
+        RoundingMode::Zero => value,
+        RoundingMode::Up if error > 0.0 => value.next_up(),
+        RoundingMode::Down if error < 0.0 => value.next_down(),
+        RoundingMode::Up | RoundingMode::Down => value,
+    }
+}
+fn overflow_result(negative: bool, precision: Precision, mode: RoundingMode) -> f64 {
+    let to_infinity = match mode {
+        RoundingMode::Nearest => true,
+        RoundingMode::Zero => false,
+        RoundingMode::Up => !negative,
+        RoundingMode::Down => negative,
+    };
+    let magnitude = if to_infinity { f64::INFINITY } else { precision.max() };
+    if negative { -magnitude } else { magnitude }
+}
+fn flush_result(negative: bool, precision: Precision, mode: RoundingMode) -> f64 {
+    let to_normal = match mode {
+        RoundingMode::Nearest | RoundingMode::Zero => false,
+        RoundingMode::Up => !negative,
+        RoundingMode::Down => negative,
+    };
+    let magnitude = if to_normal { precision.min_normal() } else { 0.0 };
+    if negative { -magnitude } else { magnitude }
+}
+
+const FCR0: u32 = 0x0000_0A00;
+const FCR31_WRITE_MASK: u32 = 0x0183_FFFF;
+
+const CAUSE_INEXACT: u8 = 1 << 0;
+const CAUSE_UNDERFLOW: u8 = 1 << 1;
+const CAUSE_OVERFLOW: u8 = 1 << 2;
+const CAUSE_DIVIDE_BY_ZERO: u8 = 1 << 3;
+const CAUSE_INVALID: u8 = 1 << 4;
+const CAUSE_UNIMPLEMENTED: u8 = 1 << 5;
+
+const COND_UNORDERED: u8 = 1 << 0;
+const COND_EQUAL: u8 = 1 << 1;
+const COND_LESS: u8 = 1 << 2;
+const COND_SIGNAL: u8 = 1 << 3;
+
+const SINGLE_DEFAULT_NAN: u32 = 0x7FBF_FFFF;
+const DOUBLE_DEFAULT_NAN: u64 = 0x7FF7_FFFF_FFFF_FFFF;
+const SINGLE_SIGNAL_BIT: u32 = 0x0040_0000;
+const DOUBLE_SIGNAL_BIT: u64 = 0x0008_0000_0000_0000;
+
+const WORD_CONVERSION_LIMIT: f64 = 2147483648.0;
+const LONG_CONVERSION_LIMIT: f64 = 9007199254740992.0;
+const LONG_TO_FLOAT_LIMIT: i64 = 1 << 55;
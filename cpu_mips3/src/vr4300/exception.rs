@@ -0,0 +1,134 @@
+use crate::core::{MipsResult, RawCore};
+
+use super::Vr4300;
+
+/// The exceptions the VR4300 can raise, as far as they are relevant for choosing
+/// the exception code, the vector and the registers that get updated on entry.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Exception {
+    Interrupt,
+    TlbModification,
+    TlbRefillLoad,
+    TlbRefillStore,
+    TlbInvalidLoad,
+    TlbInvalidStore,
+    AddressErrorLoad,
+    AddressErrorStore,
+    InstructionBusError,
+    DataBusError,
+    Syscall,
+    Breakpoint,
+    ReservedInstruction,
+    CoprocessorUnusable(u8),
+    IntegerOverflow,
+    Trap,
+    FloatingPoint,
+    Watch,
+    CacheError,
+}
+impl Exception {
+    pub fn exec_code(self) -> u8 {
+        match self {
+            Self::Interrupt => 0,
+            Self::TlbModification => 1,
+            Self::TlbRefillLoad | Self::TlbInvalidLoad => 2,
+            Self::TlbRefillStore | Self::TlbInvalidStore => 3,
+            Self::AddressErrorLoad => 4,
+            Self::AddressErrorStore => 5,
+            Self::InstructionBusError => 6,
+            Self::DataBusError => 7,
+            Self::Syscall => 8,
+            Self::Breakpoint => 9,
+            Self::ReservedInstruction => 10,
+            Self::CoprocessorUnusable(_) => 11,
+            Self::IntegerOverflow => 12,
+            Self::Trap => 13,
+            Self::FloatingPoint => 15,
+            Self::Watch => 23,
+            // The cache error exception does not touch Cause at all
+            Self::CacheError => 0,
+        }
+    }
+
+    pub fn is_tlb_refill(self) -> bool {
+        matches!(self, Self::TlbRefillLoad | Self::TlbRefillStore)
+    }
+}
+
+impl Vr4300 {
+    /// Enters the exception handler for the given exception.
+    /// Always returns Err(None), so the currently executing instruction can be aborted with `?`.
+    pub(super) fn take_exception<R>(&mut self, exception: Exception) -> MipsResult<R> {
+        let vector = self.exception_vector(exception);
+        let pc = if self.delay_slot { self.pc.wrapping_sub(4) } else { self.pc };
+
+        if exception == Exception::CacheError {
+            self.cop0.error_epc.0 = pc;
+            self.cop0.status.set_erl(true);
+        }
+        else {
+            let cause = &mut self.cop0.cause;
+            cause.set_exec_code(exception.exec_code());
+            cause.set_ce(match exception {
+                Exception::CoprocessorUnusable(cop) => cop,
+                _ => 0,
+            });
+
+            if !self.cop0.status.exl() {
+                self.cop0.epc.0 = pc;
+                self.cop0.cause.set_bd(self.delay_slot);
+                self.cop0.status.set_exl(true);
+            }
+        }
+
+        self.pc = vector;
+        self.branch = None;
+        self.delay_slot = false;
+        Err(None)
+    }
+    /// Enters the exception handler for an exception caused by accessing the given virtual address.
+    pub(super) fn take_address_exception<R>(&mut self, exception: Exception, vaddr: u64) -> MipsResult<R> {
+        self.cop0.bad_v_addr.0 = vaddr;
+        self.take_exception(exception)
+    }
+
+    /// Enters the exception handler for a TLB exception, filling in the registers a refill handler relies on.
+    pub(super) fn take_tlb_exception<R>(&mut self, exception: Exception, vaddr: u64) -> MipsResult<R> {
+        let vpn2 = (vaddr >> 13) as u32;
+        let region = (vaddr >> 62) as u8;
+        self.cop0.context.set_bad_vpn2(vpn2 & 0x7_FFFF);
+        self.cop0.x_context.set_bad_vpn2(vpn2 & 0x7FF_FFFF);
+        self.cop0.x_context.set_r(region);
+        self.cop0.entry_hi.set_vpn2(vpn2 & 0x7FF_FFFF);
+        self.cop0.entry_hi.set_r(region);
+        self.take_address_exception(exception, vaddr)
+    }
+
+    fn exception_vector(&self, exception: Exception) -> u64 {
+        let bev = self.cop0.status.bev();
+        let base = if bev { EXCEPTION_BASE_BEV } else { EXCEPTION_BASE };
+
+        match exception {
+            Exception::CacheError if bev => EXCEPTION_BASE_BEV + CACHE_ERROR_OFFSET,
+            Exception::CacheError => CACHE_ERROR_BASE + CACHE_ERROR_OFFSET,
+            e if e.is_tlb_refill() && !self.cop0.status.exl() => {
+                if self.is_64_bit_mode() {
+                    base + XTLB_REFILL_OFFSET
+                }
+                else {
+                    base + TLB_REFILL_OFFSET
+                }
+            }
+            _ => base + GENERAL_OFFSET,
+        }
+    }
+}
+
+const EXCEPTION_BASE: u64 = 0xFFFF_FFFF_8000_0000;
+const EXCEPTION_BASE_BEV: u64 = 0xFFFF_FFFF_BFC0_0200;
+const CACHE_ERROR_BASE: u64 = 0xFFFF_FFFF_A000_0000;
+
+const TLB_REFILL_OFFSET: u64 = 0x000;
+const XTLB_REFILL_OFFSET: u64 = 0x080;
+const CACHE_ERROR_OFFSET: u64 = 0x100;
+const GENERAL_OFFSET: u64 = 0x180;
@@ -0,0 +1,97 @@
+use super::cop0::{EntryHi, EntryLo, PageMask};
+
+pub struct Tlb {
+    entries: [TlbEntry; TLB_ENTRIES],
+}
+impl Tlb {
+    pub fn init() -> Self {
+        Self {
+            entries: [TlbEntry::default(); TLB_ENTRIES],
+        }
+    }
+
+    pub fn read(&self, index: usize) -> TlbEntry {
+        self.entries[index % TLB_ENTRIES]
+    }
+    pub fn write(&mut self, index: usize, entry: TlbEntry) {
+        self.entries[index % TLB_ENTRIES] = entry;
+    }
+
+    /// Returns the index of the first entry matching the given virtual address and ASID.
+    pub fn probe(&self, vaddr: u64, asid: u8) -> Option<usize> {
+        self.entries.iter().position(|e| e.matches(vaddr, asid))
+    }
+
+    pub fn translate(&self, vaddr: u64, asid: u8) -> TlbLookup {
+        let Some(index) = self.probe(vaddr, asid) else { return TlbLookup::Miss };
+        let entry = &self.entries[index];
+
+        let offset_mask = entry.offset_mask();
+        let odd = vaddr & (offset_mask + 1) != 0;
+        let lo = if odd { entry.entry_lo1 } else { entry.entry_lo0 };
+        if !lo.v() {
+            return TlbLookup::Invalid;
+        }
+
+        let frame = (lo.pfn() as u64) << 12;
+        let addr = (frame & !offset_mask) | (vaddr & offset_mask);
+        TlbLookup::Hit {
+            addr: addr as u32,
+            cached: lo.c() != UNCACHED,
+            dirty: lo.d(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TlbEntry {
+    pub page_mask: PageMask,
+    pub entry_hi: EntryHi,
+    pub entry_lo0: EntryLo,
+    pub entry_lo1: EntryLo,
+    pub global: bool,
+}
+impl TlbEntry {
+    /// Builds an entry the way TLBWI/TLBWR do from the current CP0 contents.
+    pub fn from_cop0(page_mask: PageMask, entry_hi: EntryHi, entry_lo0: EntryLo, entry_lo1: EntryLo) -> Self {
+        let entry_hi = entry_hi.with_vpn2(entry_hi.vpn2() & !(page_mask.mask() as u32));
+        let global = entry_lo0.g() && entry_lo1.g();
+
+        Self {
+            page_mask,
+            entry_hi,
+            entry_lo0: entry_lo0.with_g(global),
+            entry_lo1: entry_lo1.with_g(global),
+            global,
+        }
+    }
+
+    pub fn matches(&self, vaddr: u64, asid: u8) -> bool {
+        let compare_mask = (VPN2_BITS & !(self.page_mask.into_bits() as u64)) | REGION_BITS;
+        let hi = self.entry_hi.into_bits();
+        let vpn_match = (vaddr ^ hi) & compare_mask == 0;
+        let asid_match = self.global || self.entry_hi.asid() == asid;
+        vpn_match && asid_match
+    }
+
+    /// The mask of virtual address bits that pass through untranslated, i.e. the page offset.
+    fn offset_mask(&self) -> u64 {
+        ((self.page_mask.into_bits() as u64) >> 1) | 0xFFF
+    }
+}
+
+pub enum TlbLookup {
+    Hit {
+        addr: u32,
+        cached: bool,
+        dirty: bool,
+    },
+    Invalid,
+    Miss,
+}
+
+pub const TLB_ENTRIES: usize = 32;
+
+const VPN2_BITS: u64 = 0x0000_00FF_FFFF_E000;
+const REGION_BITS: u64 = 0xC000_0000_0000_0000;
+const UNCACHED: u8 = 0b010;
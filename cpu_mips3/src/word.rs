@@ -42,6 +42,8 @@ impl Word {
             (1, 3) => [false, false, false, true],
             (2, 0) => [true, true, false, false],
             (2, 2) => [false, false, true, true],
+            (3, 0) => [true, true, true, false],
+            (3, 1) => [false, true, true, true],
             (4, 0) => [true, true, true, true],
             (_, _) => unreachable!(),
         };
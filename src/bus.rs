@@ -0,0 +1,538 @@
+use std::{collections::VecDeque, ops::RangeInclusive};
+
+use cpu_mips3::{
+    core::MipsErr,
+    rsp::RspBus,
+    vr4300::{SysAd, WriteSize},
+    word::Word,
+};
+
+use crate::{
+    cartridge::Cartridge,
+    dp::{self, Dp},
+    mi::{self, Interrupt, Mi},
+    pi::{self, Pi, PiDirection, PiDma},
+    pif_nus::{self, PifNus},
+    rdram::{self, Rdram},
+    ri::{self, Ri},
+    rsp::{self, Rsp, SpDirection, SpDma},
+    save::Backup,
+    si::{self, Si, SiDirection, SiDma},
+    sp_mem::SpMem,
+};
+
+
+/// All devices reachable through the physical address space, together with the decoder that routes accesses to them.
+/// The CPU, the debugger and the DMA engines all go through here.
+pub struct Bus {
+    pub rsp: Rsp,
+    pub dp: Dp,
+    pub dmem: SpMem,
+    pub imem: SpMem,
+    pub mi: Mi,
+    pub pi: Pi,
+    pub pif_nus: PifNus,
+    pub rdram: Rdram,
+    pub ri: Ri,
+    pub si: Si,
+    pub cartridge: Option<Cartridge>,
+
+    pub unmapped_policy: UnmappedPolicy,
+    /// Unmapped accesses recorded under [UnmappedPolicy::Log], oldest first.
+    /// Only the last [LOG_CAPACITY] are kept, so nobody has to drain it.
+    pub log: VecDeque<String>,
+}
+impl Bus {
+    pub fn init(rdram: Rdram) -> Self {
+        Self {
+            rsp: Rsp::init(),
+            dp: Dp::init(),
+            dmem: SpMem::dmem(),
+            imem: SpMem::imem(),
+            mi: Mi::init(),
+            pi: Pi::init(),
+            pif_nus: PifNus::init(),
+            rdram,
+            ri: Ri::init(),
+            si: Si::init(),
+            cartridge: None,
+
+            unmapped_policy: UnmappedPolicy::Error,
+            log: VecDeque::new(),
+        }
+    }
+
+    /// Advances the devices that work in the background by one CPU instruction.
+    pub fn tick(&mut self) -> Result<(), MipsErr> {
+        if let Some(dma) = self.rsp.tick() {
+            self.run_sp_dma(dma)?;
+        }
+        if let Some(dma) = self.pi.tick() {
+            self.run_pi_dma(dma)?;
+            self.mi.set(Interrupt::Pi, self.pi.interrupt());
+        }
+        if let Some(dma) = self.si.tick() {
+            self.run_si_dma(dma)?;
+            self.mi.set(Interrupt::Si, self.si.interrupt());
+        }
+        self.dp.tick();
+        Ok(())
+    }
+
+    /// Reads a word without side effects and without consulting the unmapped access policy.
+    pub fn read_debug(&self, addr: u32) -> Option<Word> {
+        self.read_device(addr).ok().flatten()
+    }
+
+    fn read_device(&self, addr: u32) -> Result<Option<Word>, MipsErr> {
+        let Some(device) = Device::decode(addr) else { return Ok(None) };
+        match device {
+            Device::Rdram => self.rdram.read_word_for_cpu(addr),
+            Device::DMem => self.dmem.read_word_for_cpu(addr),
+            Device::IMem => self.imem.read_word_for_cpu(addr),
+            Device::Mi => self.mi.read_word_for_cpu(addr),
+            Device::Pi => self.pi.read_word_for_cpu(addr),
+            Device::Rsp => self.rsp.read_word_for_cpu(addr),
+            Device::Dp => self.dp.read_word_for_cpu(addr),
+            Device::Ri => self.ri.read_word_for_cpu(addr),
+            Device::Si => self.si.read_word_for_cpu(addr),
+            Device::PifNus => self.pif_nus.read_word_for_cpu(addr),
+            Device::CartRom => Ok(Some(self.cart_rom_word(addr))),
+            Device::CartSave => Ok(Some(self.cart_save_word(addr))),
+        }
+    }
+    fn write_device(&mut self, addr: u32, size: WriteSize, data: Word) -> Result<bool, MipsErr> {
+        let Some(device) = Device::decode(addr) else { return Ok(false) };
+        match device {
+            Device::Rdram => self.rdram.write_word_for_cpu(addr, size, data),
+            Device::DMem => self.dmem.write_word_for_cpu(addr, size, data),
+            Device::IMem => self.imem.write_word_for_cpu(addr, size, data),
+            Device::Mi => self.mi.write_word_for_cpu(addr, size, data),
+            Device::Pi => {
+                self.pi.write_word_for_cpu(addr, size, data)?;
+                self.mi.set(Interrupt::Pi, self.pi.interrupt());
+                Ok(true)
+            }
+            Device::Rsp => {
+                self.rsp.write_word_for_cpu(addr, size, data)?;
+                self.mi.set(Interrupt::Sp, self.rsp.interrupt());
+                Ok(true)
+            }
+            Device::Dp => {
+                self.dp.write_word_for_cpu(addr, size, data)?;
+                self.run_dp()?;
+                Ok(true)
+            }
+            Device::Ri => self.ri.write_word_for_cpu(addr, size, data),
+            Device::Si => {
+                self.si.write_word_for_cpu(addr, size, data)?;
+                self.mi.set(Interrupt::Si, self.si.interrupt());
+                Ok(true)
+            }
+            Device::PifNus => self.pif_nus.write_word_for_cpu(addr, size, data),
+            // The cartridge ROM ignores writes
+            Device::CartRom => Ok(true),
+            Device::CartSave => {
+                if let Some(backup) = self.backup_mut() {
+                    backup.write_word(addr, size, data);
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// Copies data between RDRAM and the cartridge domains.
+    fn run_pi_dma(&mut self, dma: PiDma) -> Result<(), MipsErr> {
+        let (from, to) = match dma.direction {
+            PiDirection::ToDram => (dma.cart_addr, dma.dram_addr),
+            PiDirection::ToCart => (dma.dram_addr, dma.cart_addr),
+        };
+        let mut data = vec![0; dma.len as usize];
+        self.dma_read(from, &mut data)?;
+        self.dma_write(to, &data)?;
+        self.pi.complete_dma(dma);
+        Ok(())
+    }
+    /// Copies the rows of an RSP DMA between RDRAM and DMEM or IMEM.
+    fn run_sp_dma(&mut self, dma: SpDma) -> Result<(), MipsErr> {
+        for (mem_addr, dram_addr) in dma.addresses() {
+            let mem_addr = rsp::MEM_FIRST | mem_addr;
+            let (from, to) = match dma.direction {
+                SpDirection::ToSp => (dram_addr, mem_addr),
+                SpDirection::ToDram => (mem_addr, dram_addr),
+            };
+            let mut byte = [0];
+            self.dma_read(from, &mut byte)?;
+            self.dma_write(to, &byte)?;
+        }
+        self.rsp.complete_dma(dma);
+        Ok(())
+    }
+    /// Copies all of PIF RAM to or from RDRAM.
+    /// Reading PIF RAM runs the joybus commands the last write requested.
+    fn run_si_dma(&mut self, dma: SiDma) -> Result<(), MipsErr> {
+        let (from, to) = match dma.direction {
+            SiDirection::ToDram => (pif_nus::PIF_RAM_FIRST, dma.dram_addr),
+            SiDirection::ToPif => (dma.dram_addr, pif_nus::PIF_RAM_FIRST),
+        };
+        let mut ram = [0; pif_nus::PIF_RAM_BYTES];
+        self.dma_read(from, &mut ram)?;
+        self.dma_write(to, &ram)?;
+        self.si.complete_dma();
+        Ok(())
+    }
+
+    /// Reads a block of memory for a DMA engine, through the same decoder as the CPU.
+    /// The first address picks the device, since no transfer reaches from one device into the next.
+    fn dma_read(&mut self, addr: u32, data: &mut [u8]) -> Result<(), MipsErr> {
+        let addrs = (0..data.len() as u32).map(|i| addr.wrapping_add(i)).zip(data.iter_mut());
+        match Device::decode(addr) {
+            Some(Device::Rdram) => addrs.for_each(|(addr, byte)| *byte = self.rdram.read_byte(addr)),
+            Some(Device::DMem) => addrs.for_each(|(addr, byte)| *byte = self.dmem.read_byte(addr)),
+            Some(Device::IMem) => addrs.for_each(|(addr, byte)| *byte = self.imem.read_byte(addr)),
+            Some(Device::CartRom) => addrs.for_each(|(addr, byte)| *byte = self.cart_rom_word(addr & !3).0[addr as usize % 4]),
+            Some(Device::CartSave) => match self.backup() {
+                Some(backup) => backup.read_dma(addr, data),
+                None => data.fill(0),
+            },
+            Some(Device::PifNus) => self.pif_nus.read_dma(addr, data),
+            _ => {
+                self.unmapped_read(addr)?;
+                addrs.for_each(|(addr, byte)| *byte = open_bus(addr & !3).0[addr as usize % 4]);
+            }
+        }
+        Ok(())
+    }
+    /// Writes a block of memory for a DMA engine, through the same decoder as the CPU.
+    fn dma_write(&mut self, addr: u32, data: &[u8]) -> Result<(), MipsErr> {
+        let addrs = (0..data.len() as u32).map(|i| addr.wrapping_add(i)).zip(data.iter().copied());
+        match Device::decode(addr) {
+            Some(Device::Rdram) => addrs.for_each(|(addr, byte)| self.rdram.write_byte(addr, byte)),
+            Some(Device::DMem) => addrs.for_each(|(addr, byte)| self.dmem.write_byte(addr, byte)),
+            Some(Device::IMem) => addrs.for_each(|(addr, byte)| self.imem.write_byte(addr, byte)),
+            // The cartridge ROM ignores writes
+            Some(Device::CartRom) => (),
+            Some(Device::CartSave) => {
+                if let Some(backup) = self.backup_mut() {
+                    backup.write_dma(addr, data);
+                }
+            }
+            Some(Device::PifNus) => self.pif_nus.write_dma(addr, data),
+            _ => self.unmapped_write(addr)?,
+        }
+        Ok(())
+    }
+
+    /// Reads from beyond the end of the ROM, or without a cartridge, return the open bus value.
+    fn cart_rom_word(&self, addr: u32) -> Word {
+        let offset = addr - pi::CART_ROM_FIRST;
+        let word = self.cartridge.as_ref().and_then(|cart| cart.read_word(offset));
+        word.unwrap_or_else(|| open_bus(addr))
+    }
+
+    /// Without save memory, domain 2 reads as open bus.
+    fn cart_save_word(&self, addr: u32) -> Word {
+        match self.backup() {
+            Some(backup) => backup.read_word(addr),
+            None => open_bus(addr),
+        }
+    }
+    fn backup(&self) -> Option<&Backup> {
+        self.cartridge.as_ref()?.backup.as_ref()
+    }
+    fn backup_mut(&mut self) -> Option<&mut Backup> {
+        self.cartridge.as_mut()?.backup.as_mut()
+    }
+
+    fn unmapped_read(&mut self, addr: u32) -> Result<Word, MipsErr> {
+        match self.unmapped_policy {
+            UnmappedPolicy::Error => Err(MipsErr::new(format!("read from unmapped address {addr:0>8x}"))),
+            UnmappedPolicy::Log => {
+                self.log_unmapped(format!("read from unmapped address {addr:0>8x}"));
+                Ok(open_bus(addr))
+            }
+            UnmappedPolicy::OpenBus => Ok(open_bus(addr)),
+        }
+    }
+    fn unmapped_write(&mut self, addr: u32) -> Result<(), MipsErr> {
+        match self.unmapped_policy {
+            UnmappedPolicy::Error => Err(MipsErr::new(format!("write to unmapped address {addr:0>8x}"))),
+            UnmappedPolicy::Log => {
+                self.log_unmapped(format!("write to unmapped address {addr:0>8x}"));
+                Ok(())
+            }
+            UnmappedPolicy::OpenBus => Ok(()),
+        }
+    }
+    /// Records an unmapped access, dropping the oldest one once the log is full.
+    fn log_unmapped(&mut self, message: String) {
+        if self.log.len() == LOG_CAPACITY {
+            self.log.pop_front();
+        }
+        self.log.push_back(message);
+    }
+}
+impl SysAd for Bus {
+    fn read_word(&mut self, addr: u32) -> Result<Word, MipsErr> {
+        match self.read_device(addr)? {
+            Some(word) => {
+                if Device::decode(addr) == Some(Device::Rsp) {
+                    self.rsp.after_cpu_read(addr);
+                }
+                Ok(word)
+            }
+            None => self.unmapped_read(addr),
+        }
+    }
+
+    fn write_word(&mut self, addr: u32, size: WriteSize, data: Word) -> Result<(), MipsErr> {
+        if self.write_device(addr, size, data)? {
+            Ok(())
+        }
+        else {
+            self.unmapped_write(addr)
+        }
+    }
+
+    fn write_dword(&mut self, addr: u32, data: [Word; 2]) -> Result<(), MipsErr> {
+        self.write_word(addr, WriteSize::Four, data[0])?;
+        self.write_word(addr + 4, WriteSize::Four, data[1])
+    }
+
+    fn read_cached_data(&mut self, addr: u32) -> Result<[Word; 4], MipsErr> {
+        self.read_line(addr)
+    }
+
+    fn read_cached_inst(&mut self, addr: u32) -> Result<[Word; 8], MipsErr> {
+        self.read_line(addr)
+    }
+
+    fn write_cached_data(&mut self, addr: u32, data: [Word; 4]) -> Result<(), MipsErr> {
+        for (i, word) in data.into_iter().enumerate() {
+            self.write_word(addr + i as u32 * 4, WriteSize::Four, word)?;
+        }
+        Ok(())
+    }
+}
+impl RspBus for Bus {
+    fn pc(&self) -> u32 {
+        self.rsp.pc()
+    }
+    fn set_pc(&mut self, pc: u32) {
+        self.rsp.set_pc(pc);
+    }
+
+    fn read_imem(&mut self, addr: u32) -> u32 {
+        self.imem.read_word(addr)
+    }
+    fn read_dmem(&mut self, addr: u32) -> u8 {
+        self.dmem.read_byte(addr)
+    }
+    fn write_dmem(&mut self, addr: u32, value: u8) {
+        self.dmem.write_byte(addr, value);
+    }
+
+    fn read_cop0(&mut self, reg: u8) -> Result<u32, MipsErr> {
+        match reg {
+            0..=7 => Ok(self.rsp.read_reg(reg as u32)),
+            _ => Ok(self.dp.read_reg(reg as u32 - 8)),
+        }
+    }
+    fn write_cop0(&mut self, reg: u8, value: u32) -> Result<(), MipsErr> {
+        match reg {
+            0..=7 => self.rsp.write_reg(reg as u32, value),
+            _ => {
+                self.dp.write_reg(reg as u32 - 8, value);
+                self.run_dp()?;
+            }
+        }
+        self.mi.set(Interrupt::Sp, self.rsp.interrupt());
+        Ok(())
+    }
+
+    fn on_break(&mut self) {
+        self.rsp.on_break();
+        self.mi.set(Interrupt::Sp, self.rsp.interrupt());
+    }
+}
+impl Bus {
+    /// Feeds the RDP every command word between DPC_CURRENT and DPC_END, from DMEM if the XBUS is selected.
+    fn run_dp(&mut self) -> Result<(), MipsErr> {
+        while let Some(addr) = self.dp.next_fetch() {
+            let addr = if self.dp.xbus() { rsp::MEM_FIRST | addr & 0xFFF } else { addr };
+            let mut word = [0; 8];
+            self.dma_read(addr, &mut word)?;
+            self.dp.feed(u64::from_be_bytes(word), &mut self.rdram);
+        }
+        if self.dp.take_interrupt() {
+            self.mi.raise(Interrupt::Dp);
+        }
+        Ok(())
+    }
+
+    /// Reads a cache line as a burst of consecutive words.
+    fn read_line<const N: usize>(&mut self, addr: u32) -> Result<[Word; N], MipsErr> {
+        let mut line = [Word::zero(); N];
+        for (i, word) in line.iter_mut().enumerate() {
+            *word = self.read_word(addr + i as u32 * 4)?;
+        }
+        Ok(line)
+    }
+}
+
+/// What happens on an access to a physical address no device responds to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UnmappedPolicy {
+    /// Stop emulation with an error.
+    Error,
+    /// Record the access in [Bus::log] and otherwise behave like [UnmappedPolicy::OpenBus].
+    Log,
+    /// Ignore writes and let reads return the open bus value.
+    OpenBus,
+}
+
+/// How many unmapped accesses [Bus::log] holds at most.
+pub const LOG_CAPACITY: usize = 1024;
+
+/// An undriven bus returns the lower half of the address in both halves of the word.
+fn open_bus(addr: u32) -> Word {
+    let low = addr & 0xFFFF;
+    Word::from_u32_be(low << 16 | low)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Device {
+    Rdram,
+    DMem,
+    IMem,
+    Mi,
+    Pi,
+    Rsp,
+    Dp,
+    Ri,
+    Si,
+    PifNus,
+    CartRom,
+    CartSave,
+}
+impl Device {
+    fn decode(addr: u32) -> Option<Self> {
+        let device = MEMORY_MAP.iter().find(|(range, _)| range.contains(&addr)).map(|(_, device)| *device)?;
+        if device == Self::DMem && addr & rsp::MEM_SELECT_IMEM != 0 {
+            Some(Self::IMem)
+        }
+        else {
+            Some(device)
+        }
+    }
+}
+
+/// The physical address ranges each device responds to.
+const MEMORY_MAP: &[(RangeInclusive<u32>, Device)] = &[
+    (rdram::RDRAM_FIRST..=rdram::RDRAM_LAST, Device::Rdram),
+    (rdram::REGS_FIRST..=rdram::REGS_LAST, Device::Rdram),
+    // Split between DMEM and IMEM by Device::decode
+    (rsp::MEM_FIRST..=rsp::MEM_LAST, Device::DMem),
+    (rsp::REGS_FIRST..=rsp::REGS_LAST, Device::Rsp),
+    (dp::REGS_FIRST..=dp::REGS_LAST, Device::Dp),
+    (mi::REGS_FIRST..=mi::REGS_LAST, Device::Mi),
+    (pi::REGS_FIRST..=pi::REGS_LAST, Device::Pi),
+    (ri::REGS_FIRST..=ri::REGS_LAST, Device::Ri),
+    (si::REGS_FIRST..=si::REGS_LAST, Device::Si),
+    (pi::CART_DOM2_ADDR2_FIRST..=pi::CART_DOM2_ADDR2_LAST, Device::CartSave),
+    (pi::CART_ROM_FIRST..=pi::CART_ROM_LAST, Device::CartRom),
+    (pif_nus::PIF_FIRST..=pif_nus::PIF_LAST, Device::PifNus),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(bus: &mut Bus, addr: u32, value: u32) -> Result<(), MipsErr> {
+        bus.write_word(addr, WriteSize::Four, Word::from_u32_be(value))
+    }
+    fn pi_status(bus: &mut Bus) -> u32 {
+        bus.read_word(pi::PI_STATUS).unwrap().to_u32_be()
+    }
+    /// Runs a PI DMA from the cartridge bus into RDRAM to completion.
+    fn pi_dma(bus: &mut Bus, cart_addr: u32, dram_addr: u32, len: u32) -> Result<(), MipsErr> {
+        write(bus, pi::PI_DRAM_ADDR, dram_addr)?;
+        write(bus, pi::PI_CART_ADDR, cart_addr)?;
+        write(bus, pi::PI_WR_LEN, len - 1)?;
+        while pi_status(bus) & 1 != 0 {
+            bus.tick()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn dma_from_unmapped_addresses_follows_the_policy() {
+        let mut bus = Bus::init(Rdram::init(false));
+        bus.unmapped_policy = UnmappedPolicy::Error;
+        assert!(pi_dma(&mut bus, 0x0600_1234, 0x1000, 8).is_err());
+
+        bus.unmapped_policy = UnmappedPolicy::Log;
+        pi_dma(&mut bus, 0x0600_1234, 0x1000, 8).unwrap();
+        assert_eq!(bus.log.pop_front().unwrap(), "read from unmapped address 06001234");
+        assert_eq!(bus.read_debug(0x1000).unwrap().to_u32_be(), 0x1234_1234);
+
+        // The addresses wrap around past the top of the address space
+        pi_dma(&mut bus, 0xFFFF_FFFE, 0x1000, 8).unwrap();
+        assert_eq!(bus.log.pop_front().unwrap(), "read from unmapped address fffffffe");
+        assert_eq!(bus.read_debug(0x1000).unwrap().to_u32_be(), 0xFFFC_0000);
+        assert_eq!(bus.read_debug(0x1004).unwrap().to_u32_be(), 0x0000_0004);
+    }
+
+    #[test]
+    fn pi_dma_takes_time() {
+        let mut bus = Bus::init(Rdram::init(false));
+        let mut rom: Vec<u8> = (0..0x1000).map(|i| i as u8).collect();
+        rom[..4].copy_from_slice(&[0x80, 0x37, 0x12, 0x40]);
+        bus.cartridge = Some(Cartridge::from_bytes(rom).unwrap());
+        write(&mut bus, pi::PI_BSD_DOM1_LAT, 0x40).unwrap();
+        write(&mut bus, pi::PI_BSD_DOM1_PWD, 0x12).unwrap();
+        write(&mut bus, pi::PI_BSD_DOM1_PGS, 0x07).unwrap();
+        write(&mut bus, pi::PI_BSD_DOM1_RLS, 0x03).unwrap();
+        write(&mut bus, pi::PI_DRAM_ADDR, 0x1000).unwrap();
+        write(&mut bus, pi::PI_CART_ADDR, pi::CART_ROM_FIRST + 0x40).unwrap();
+        write(&mut bus, pi::PI_WR_LEN, 0x7F).unwrap();
+
+        let cycles = bus.pi.domain_timing(1).transfer_cycles(0x80);
+        for _ in 1..cycles {
+            assert_eq!(pi_status(&mut bus), 0b0011);
+            bus.tick().unwrap();
+        }
+        assert_eq!(bus.read_debug(0x1000).unwrap().to_u32_be(), 0);
+        bus.tick().unwrap();
+        assert_eq!(pi_status(&mut bus), 0b1000);
+        assert_eq!(bus.read_debug(0x1000).unwrap().to_u32_be(), 0x4041_4243);
+        assert_eq!(bus.read_debug(0x107C).unwrap().to_u32_be(), 0xBCBD_BEBF);
+    }
+
+    #[test]
+    fn sync_full_from_dmem_raises_the_dp_interrupt() {
+        let mut bus = Bus::init(Rdram::init(false));
+        for (i, byte) in (0xE9u64 << 56).to_be_bytes().into_iter().enumerate() {
+            bus.dmem.write_byte(0x100 + i as u32, byte);
+        }
+        // Select the XBUS, then hand the RDP the single command
+        write(&mut bus, dp::DPC_STATUS, 1 << 1).unwrap();
+        write(&mut bus, dp::DPC_START, 0x100).unwrap();
+        write(&mut bus, dp::DPC_END, 0x108).unwrap();
+
+        assert_eq!(bus.read_word(dp::DPC_CURRENT).unwrap().to_u32_be(), 0x108);
+        let interrupts = bus.read_word(mi::MI_INTERRUPT).unwrap().to_u32_be();
+        assert_eq!(interrupts, 1 << Interrupt::Dp as u32);
+    }
+
+    #[test]
+    fn the_log_keeps_the_latest_accesses() {
+        let mut bus = Bus::init(Rdram::init(false));
+        bus.unmapped_policy = UnmappedPolicy::Log;
+        for i in 0..LOG_CAPACITY as u32 + 10 {
+            bus.read_word(0x0600_0000 + i * 4).unwrap();
+        }
+        assert_eq!(bus.log.len(), LOG_CAPACITY);
+        assert_eq!(bus.log.front().unwrap(), "read from unmapped address 06000028");
+    }
+}
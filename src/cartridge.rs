@@ -0,0 +1,162 @@
+use std::{
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+use cpu_mips3::word::Word;
+
+use crate::save::{Backup, SaveType};
+
+
+/// A game cartridge, with its ROM normalized to big endian byte order.
+pub struct Cartridge {
+    rom: Vec<Word>,
+    header: Header,
+    path: Option<PathBuf>,
+    /// Detected from the game code on creation, and can be overridden before the cartridge is inserted.
+    pub save_type: SaveType,
+    /// The SRAM or FlashRAM in domain 2, created when the cartridge is inserted.
+    pub backup: Option<Backup>,
+}
+impl Cartridge {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let bytes = fs::read(&path)?;
+        let mut cartridge = Self::from_bytes(bytes)?;
+        cartridge.path = Some(path.as_ref().to_owned());
+        Ok(cartridge)
+    }
+    /// Creates a cartridge from a ROM dump in any of the common byte orders.
+    pub fn from_bytes(mut bytes: Vec<u8>) -> anyhow::Result<Self> {
+        if bytes.len() < HEADER_BYTES {
+            bail!("ROM is too small to contain a header ({} bytes)", bytes.len());
+        }
+        let magic = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        let Some(order) = ByteOrder::detect(magic) else {
+            bail!("ROM has an unrecognized header magic {magic:02x?}");
+        };
+        order.normalize(&mut bytes);
+
+        // Pad to whole words, the remaining bytes are never read by a real console either
+        bytes.resize(bytes.len().next_multiple_of(4), 0);
+        let rom: Vec<Word> = bytes.chunks(4).map(|c| Word([c[0], c[1], c[2], c[3]])).collect();
+        let header = Header::parse(&bytes[..HEADER_BYTES]);
+        let save_type = SaveType::detect(&header);
+
+        Ok(Self {
+            rom,
+            header,
+            path: None,
+            save_type,
+            backup: None,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+    /// Where saves go: next to the ROM file, named after it with the extension of the save type.
+    /// Cartridges not loaded from a file are not saved.
+    pub fn save_path(&self) -> Option<PathBuf> {
+        let path = self.path.as_ref()?;
+        Some(path.with_extension(self.save_type.extension()))
+    }
+    /// The ROM size in bytes.
+    pub fn size(&self) -> usize {
+        self.rom.len() * 4
+    }
+    /// Reads the word at the given offset into the ROM, or None beyond its end.
+    pub fn read_word(&self, offset: u32) -> Option<Word> {
+        self.rom.get(offset as usize / 4).copied()
+    }
+    /// Copies out the bytes in the given range of offsets, stopping early at the end of the ROM.
+    pub fn bytes(&self, range: Range<usize>) -> Vec<u8> {
+        range.map_while(|offset| self.rom.get(offset / 4).map(|word| word.0[offset % 4])).collect()
+    }
+}
+
+/// The byte orders ROM dumps come in, named after their usual file extensions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// `.z64`, the native big endian order.
+    BigEndian,
+    /// `.v64`, with the bytes of each halfword swapped.
+    ByteSwapped,
+    /// `.n64`, with the bytes of each word reversed.
+    LittleEndian,
+}
+impl ByteOrder {
+    pub fn detect(magic: [u8; 4]) -> Option<Self> {
+        match magic {
+            [0x80, 0x37, 0x12, 0x40] => Some(Self::BigEndian),
+            [0x37, 0x80, 0x40, 0x12] => Some(Self::ByteSwapped),
+            [0x40, 0x12, 0x37, 0x80] => Some(Self::LittleEndian),
+            _ => None,
+        }
+    }
+
+    /// Converts the bytes to big endian in place.
+    pub fn normalize(self, bytes: &mut [u8]) {
+        match self {
+            Self::BigEndian => (),
+            Self::ByteSwapped => bytes.chunks_exact_mut(2).for_each(|c| c.swap(0, 1)),
+            Self::LittleEndian => bytes.chunks_exact_mut(4).for_each(|c| c.reverse()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Header {
+    /// The PI domain 1 timing the boot code configures before reading the rest of the ROM.
+    pub pi_config: u32,
+    pub clock_rate: u32,
+    pub entry_point: u32,
+    pub release: u32,
+    pub crc1: u32,
+    pub crc2: u32,
+    pub title: String,
+    /// The four character code of the game, like `NSME` for the american release of Super Mario 64.
+    pub game_code: [u8; 4],
+    pub revision: u8,
+}
+impl Header {
+    fn parse(bytes: &[u8]) -> Self {
+        let word = |offset: usize| u32::from_be_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+        let title = bytes[0x20..0x34].iter().map(|&b| b as char).collect::<String>();
+
+        Self {
+            pi_config: word(0x00),
+            clock_rate: word(0x04),
+            entry_point: word(0x08),
+            release: word(0x0C),
+            crc1: word(0x10),
+            crc2: word(0x14),
+            title: title.trim_end_matches(['\0', ' ']).to_owned(),
+            game_code: [bytes[0x3B], bytes[0x3C], bytes[0x3D], bytes[0x3E]],
+            revision: bytes[0x3F],
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        Region::from_code(self.game_code[3])
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    Unknown,
+}
+impl Region {
+    fn from_code(code: u8) -> Self {
+        match code {
+            b'7' | b'A' | b'B' | b'C' | b'E' | b'J' | b'K' | b'N' | b'G' => Self::Ntsc,
+            b'D' | b'F' | b'H' | b'I' | b'L' | b'P' | b'S' | b'U' | b'W' | b'X' | b'Y' | b'Z' => Self::Pal,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+const HEADER_BYTES: usize = 0x40;
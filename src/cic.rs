@@ -0,0 +1,148 @@
+use crate::cartridge::{Cartridge, Region};
+
+
+/// The lockout chips found in cartridges. Each one pairs with its own IPL3 boot code,
+/// and hands the PIF the seed that boot code uses to checksum the game.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cic {
+    Nus6101,
+    Nus6102,
+    Nus6103,
+    Nus6105,
+    Nus6106,
+    Nus7101,
+    Nus7102,
+    Nus7103,
+    Nus7105,
+    Nus7106,
+}
+impl Cic {
+    /// Identifies the CIC a cartridge was made for from a hash of its IPL3 boot code.
+    pub fn detect(cartridge: &Cartridge) -> Option<Self> {
+        let ipl3 = cartridge.bytes(IPL3_FIRST..IPL3_FIRST + IPL3_HASHED_BYTES);
+        let pal = cartridge.header().region() == Region::Pal;
+
+        let cic = match (crc32(&ipl3), pal) {
+            (IPL3_6101, _) => Self::Nus6101,
+            (IPL3_7102, _) => Self::Nus7102,
+            (IPL3_6102, false) => Self::Nus6102,
+            (IPL3_6102, true) => Self::Nus7101,
+            (IPL3_6103, false) => Self::Nus6103,
+            (IPL3_6103, true) => Self::Nus7103,
+            (IPL3_6105, false) => Self::Nus6105,
+            (IPL3_6105, true) => Self::Nus7105,
+            (IPL3_6106, false) => Self::Nus6106,
+            (IPL3_6106, true) => Self::Nus7106,
+            _ => return None,
+        };
+        Some(cic)
+    }
+
+    /// The word the PIF places at offset 0x24 of its RAM for the boot code.
+    /// Bits 8 to 15 are the checksum seed, bit 18 marks the 6101 and 7102, whose IPL3 predates the others.
+    pub fn pif_ram_seed(self) -> u32 {
+        match self {
+            Self::Nus6101 | Self::Nus7102 => 0x0004_3F3F,
+            Self::Nus6102 | Self::Nus7101 => 0x0000_3F3F,
+            Self::Nus6103 | Self::Nus7103 => 0x0000_783F,
+            Self::Nus6105 | Self::Nus7105 => 0x0000_913F,
+            Self::Nus6106 | Self::Nus7106 => 0x0000_853F,
+        }
+    }
+}
+
+/// Computes the response of the 6105 and 7105 to the challenge their IPL3 sends through the PIF.
+/// Both are given as one nibble per byte.
+pub fn cic_6105_response(challenge: &[u8]) -> Vec<u8> {
+    const LUT0: [u8; 16] = [0x4, 0x7, 0xA, 0x7, 0xE, 0x5, 0xE, 0x1, 0xC, 0xF, 0x8, 0xF, 0x6, 0x3, 0x6, 0x9];
+    const LUT1: [u8; 16] = [0x4, 0x1, 0xA, 0x7, 0xE, 0x5, 0xE, 0x1, 0xC, 0x9, 0x8, 0x5, 0x6, 0x3, 0xC, 0x9];
+
+    let mut key: u8 = 0xB;
+    let mut use_lut1 = false;
+    let mut response = Vec::with_capacity(challenge.len());
+    for &c in challenge {
+        let r = key.wrapping_add(c.wrapping_mul(5)) & 0xF;
+        response.push(r);
+        key = if use_lut1 { LUT1[r as usize] } else { LUT0[r as usize] };
+
+        let sign = (r >> 3) & 1;
+        let magnitude = if sign == 1 { !r } else { r } & 0x7;
+        let mut modifier = if magnitude % 3 == 1 { sign } else { 1 - sign };
+        if use_lut1 && (r == 0x1 || r == 0x9) {
+            modifier = 1;
+        }
+        if use_lut1 && (r == 0xB || r == 0xE) {
+            modifier = 0;
+        }
+        use_lut1 = modifier == 1;
+    }
+    response
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// IPL3 fills the rest of the first 4 KB after the header, and the CRCs below are the commonly published ones of all of it.
+const IPL3_FIRST: usize = 0x40;
+const IPL3_HASHED_BYTES: usize = 0x1000 - IPL3_FIRST;
+
+const IPL3_6101: u32 = 0x6170_A4A1;
+const IPL3_7102: u32 = 0x009E_9EA3;
+const IPL3_6102: u32 = 0x90BB_6CB5;
+const IPL3_6103: u32 = 0x0B05_0EE0;
+const IPL3_6105: u32 = 0x98BC_2C86;
+const IPL3_6106: u32 = 0xACC8_580A;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a ROM whose IPL3 hashes to `crc`, by choosing its last four bytes.
+    fn rom_with_ipl3_crc(crc: u32, region: u8) -> Cartridge {
+        let mut rom = vec![0; 0x1000];
+        rom[..4].copy_from_slice(&[0x80, 0x37, 0x12, 0x40]);
+        rom[0x3E] = region;
+
+        // Run the CRC backwards from the wanted result over the four bytes to choose
+        let mut wanted = !crc;
+        for _ in 0..32 {
+            wanted = if wanted & 0x8000_0000 != 0 { (wanted ^ 0xEDB8_8320) << 1 | 1 } else { wanted << 1 };
+        }
+        let before = !crc32(&rom[IPL3_FIRST..0x1000 - 4]);
+        rom[0x1000 - 4..].copy_from_slice(&(wanted ^ before).to_le_bytes());
+
+        Cartridge::from_bytes(rom).unwrap()
+    }
+
+    #[test]
+    fn crc32_is_the_standard_one() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn detects_each_ipl3() {
+        let cases = [
+            (IPL3_6101, b'E', Cic::Nus6101),
+            (IPL3_7102, b'P', Cic::Nus7102),
+            (IPL3_6102, b'E', Cic::Nus6102),
+            (IPL3_6102, b'P', Cic::Nus7101),
+            (IPL3_6103, b'E', Cic::Nus6103),
+            (IPL3_6105, b'J', Cic::Nus6105),
+            (IPL3_6105, b'P', Cic::Nus7105),
+            (IPL3_6106, b'E', Cic::Nus6106),
+        ];
+        for (crc, region, cic) in cases {
+            assert_eq!(Cic::detect(&rom_with_ipl3_crc(crc, region)), Some(cic));
+        }
+        assert_eq!(Cic::detect(&rom_with_ipl3_crc(0x1234_5678, b'E')), None);
+    }
+}
@@ -1,106 +1,114 @@
-use cpu_mips3::vr4300::{SysAd, Vr4300};
+use std::io;
 
-use crate::{dmem::DMem, imem::IMem, pif_nus::PifNus, rsp::Rsp};
 use cpu_mips3::core::MipsErr;
+use cpu_mips3::rsp::RspCore;
+use cpu_mips3::vr4300::Vr4300;
 use cpu_mips3::word::Word;
-use cpu_mips3::vr4300::WriteSize;
+
+use crate::{
+    bus::Bus,
+    cartridge::Cartridge,
+    cic::Cic,
+    controller::Controller,
+    eeprom::Eeprom,
+    flashram::FlashRam,
+    joybus::CARTRIDGE_CHANNEL,
+    rdp::Renderer,
+    rdram::Rdram,
+    save::{Backup, SaveType},
+    sram::Sram,
+};
 
 pub struct Console {
     pub cpu: Vr4300,
-    pub rsp: Rsp,
-    pub dmem: DMem,
-    pub imem: IMem,
-    pub pif_nus: PifNus,
+    pub rsp: RspCore,
+    pub bus: Bus,
 }
 impl Console {
     pub fn init() -> Self {
+        Self::init_with_rdram(Rdram::init(false))
+    }
+    pub fn with_expansion_pak() -> Self {
+        Self::init_with_rdram(Rdram::init(true))
+    }
+    fn init_with_rdram(rdram: Rdram) -> Self {
         Self {
             cpu: Vr4300::init(),
-            rsp: Rsp::init(),
-            dmem: DMem::init(),
-            imem: IMem::init(),
-            pif_nus: PifNus::init(),
+            rsp: RspCore::init(),
+            bus: Bus::init(rdram),
         }
     }
 
-    pub fn read_debug(&self, addr: u32) -> Option<Word> {
-        if let Ok(Some(word)) = self.pif_nus.read_word_for_cpu(addr) {
-            Some(word)
-        }
-        else if let Ok(Some(word)) = self.dmem.read_word_for_cpu(addr) {
-            Some(word)
-        }
-        else if let Ok(Some(word)) = self.imem.read_word_for_cpu(addr) {
-            Some(word)
-        }
-        else if let Ok(Some(word)) = self.rsp.read_word_for_cpu(addr) {
-            Some(word)
-        }
-        else {
-            None
-        }
-    }
+    /// Inserts the cartridge, seeds PIF RAM for its CIC and loads its save memory.
+    /// Cartridges with an unrecognized IPL3 are assumed to use the 6102, like most games do.
+    pub fn insert_cartridge(&mut self, mut cartridge: Cartridge) -> io::Result<()> {
+        let cic = Cic::detect(&cartridge).unwrap_or(Cic::Nus6102);
+        self.bus.pif_nus.set_cic(cic);
 
-    pub fn cpu_and_bus(&mut self) -> (&mut Vr4300, CpuBus) {
-        let bus = CpuBus {
-            rsp: &mut self.rsp,
-            dmem: &mut self.dmem,
-            imem: &mut self.imem,
-            pif_nus: &mut self.pif_nus,
+        let path = cartridge.save_path();
+        self.bus.pif_nus.detach(CARTRIDGE_CHANNEL);
+        cartridge.backup = match cartridge.save_type {
+            SaveType::None => None,
+            save @ (SaveType::Eeprom4K | SaveType::Eeprom16K) => {
+                self.bus.pif_nus.attach(CARTRIDGE_CHANNEL, Box::new(Eeprom::open(save, path)?));
+                None
+            }
+            save @ (SaveType::Sram32K | SaveType::Sram96K) => Some(Backup::Sram(Sram::open(save, path)?)),
+            SaveType::FlashRam => Some(Backup::FlashRam(FlashRam::open(path)?)),
         };
-        (&mut self.cpu, bus)
-    }
-}
-
 
-
-pub struct CpuBus<'a> {
-    rsp: &'a mut Rsp,
-    dmem: &'a mut DMem,
-    imem: &'a mut IMem,
-    pif_nus: &'a mut PifNus,
-}
-impl<'a> SysAd for CpuBus<'a> {
-    fn read_word(&mut self, addr: u32) -> Result<Word, MipsErr> {
-        if let Some(word) = self.pif_nus.read_word_for_cpu(addr)? {
-            Ok(word)
-        }
-        else if let Some(word) = self.dmem.read_word_for_cpu(addr)? {
-            Ok(word)
-        }
-        else if let Some(word) = self.imem.read_word_for_cpu(addr)? {
-            Ok(word)
-        }
-        else if let Some(word) = self.rsp.read_word_for_cpu(addr)? {
-            Ok(word)
-        }
-        else {
-            Ok(Word::zero())
+        self.bus.cartridge = Some(cartridge);
+        Ok(())
+    }
+    /// Writes all save memory that changed to its files.
+    pub fn flush_saves(&mut self) -> io::Result<()> {
+        self.bus.pif_nus.flush()?;
+        if let Some(backup) = self.bus.cartridge.as_mut().and_then(|cart| cart.backup.as_mut()) {
+            backup.flush()?;
         }
+        Ok(())
     }
 
-    fn write_word(
-        &mut self,
-        addr: u32,
-        size: WriteSize,
-        data: Word,
-    ) -> Result<(), MipsErr> {
-        return Err(MipsErr::new("unimplemented"))
+    /// Plugs a controller into one of the four ports, counted from zero.
+    pub fn plug_controller(&mut self, port: usize, controller: Controller) {
+        assert!(port < CONTROLLER_PORTS, "there is no controller port {port}");
+        self.bus.pif_nus.attach(port, Box::new(controller));
     }
-
-    fn write_dword(&mut self, addr: u32, data: [Word; 2]) -> Result<(), MipsErr> {
-        return Err(MipsErr::new("unimplemented"))
+    /// Hands every command the RDP processes from now on to `renderer`.
+    pub fn set_renderer(&mut self, renderer: Box<dyn Renderer>) {
+        self.bus.dp.set_renderer(renderer);
     }
 
-    fn read_cached_data(&mut self, addr: u32) -> Result<[Word; 4], MipsErr> {
-        return Err(MipsErr::new("unimplemented"))
+    /// Advances the CPU by one instruction, after updating its interrupt lines from the RCP,
+    /// and lets the rest of the system catch up.
+    pub fn step(&mut self) -> Result<(), MipsErr> {
+        self.cpu.set_interrupt(RCP_INTERRUPT_LINE, self.bus.mi.interrupt_pending());
+        self.cpu.step_forward(&mut self.bus)?;
+        self.step_rsp()?;
+        self.bus.tick()?;
+        Ok(())
+    }
+    /// Runs one RSP instruction unless it is halted. In single-step mode, it halts again right after.
+    fn step_rsp(&mut self) -> Result<(), MipsErr> {
+        if self.bus.rsp.halted() {
+            return Ok(());
+        }
+        self.rsp.step_forward(&mut self.bus)?;
+        if self.bus.rsp.single_step() {
+            self.bus.rsp.halt();
+        }
+        Ok(())
     }
 
-    fn read_cached_inst(&mut self, addr: u32) -> Result<[Word; 8], MipsErr> {
-        return Err(MipsErr::new("unimplemented"))
+    pub fn read_debug(&self, addr: u32) -> Option<Word> {
+        self.bus.read_debug(addr)
     }
 
-    fn write_cached_data(&mut self, addr: u32, data: [Word; 4]) -> Result<(), MipsErr> {
-        return Err(MipsErr::new("unimplemented")) 
+    pub fn cpu_and_bus(&mut self) -> (&mut Vr4300, &mut Bus) {
+        (&mut self.cpu, &mut self.bus)
     }
 }
+
+/// The MI's interrupt output is wired to the CPU's IP2.
+const RCP_INTERRUPT_LINE: u8 = 2;
+const CONTROLLER_PORTS: usize = 4;
@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+
+use crate::joybus::{JoybusDevice, ACCESSORY_BLOCK};
+
+
+/// A standard controller, which reads its inputs from an [InputSource] whenever the game polls it.
+pub struct Controller {
+    source: Box<dyn InputSource>,
+    pub stick: StickConfig,
+    pub pak: Option<ControllerPak>,
+}
+impl Controller {
+    pub fn new(source: Box<dyn InputSource>) -> Self {
+        Self {
+            source,
+            stick: StickConfig::default(),
+            pak: None,
+        }
+    }
+}
+impl JoybusDevice for Controller {
+    fn status(&self) -> [u8; 3] {
+        let pak = if self.pak.is_some() { PAK_PRESENT } else { PAK_ABSENT };
+        let [hi, lo] = DEVICE_TYPE.to_be_bytes();
+        [hi, lo, pak]
+    }
+
+    fn read_buttons(&mut self) -> Option<[u8; 4]> {
+        let state = self.source.poll();
+        let [hi, lo] = state.buttons.0.to_be_bytes();
+        let x = self.stick.axis(state.stick_x);
+        let y = self.stick.axis(state.stick_y);
+        Some([hi, lo, x as u8, y as u8])
+    }
+
+    fn read_accessory(&mut self, addr: u16, data: &mut [u8; ACCESSORY_BLOCK]) -> bool {
+        let Some(pak) = &self.pak else { return false };
+        pak.read(addr, data);
+        true
+    }
+    fn write_accessory(&mut self, addr: u16, data: &[u8; ACCESSORY_BLOCK]) -> bool {
+        let Some(pak) = &mut self.pak else { return false };
+        pak.write(addr, data);
+        true
+    }
+}
+
+/// Where a controller gets its inputs from, implemented by the frontend.
+pub trait InputSource {
+    /// Returns the current inputs. Called once for every time the game reads the controller.
+    fn poll(&mut self) -> ControllerState;
+}
+
+/// Replays a fixed sequence of inputs, each held for a number of polls, and then releases everything.
+pub struct ScriptedInput {
+    script: VecDeque<(u32, ControllerState)>,
+}
+impl ScriptedInput {
+    pub fn new(script: impl IntoIterator<Item = (u32, ControllerState)>) -> Self {
+        Self {
+            script: script.into_iter().collect(),
+        }
+    }
+}
+impl InputSource for ScriptedInput {
+    fn poll(&mut self) -> ControllerState {
+        while let Some((polls, state)) = self.script.front_mut() {
+            if *polls != 0 {
+                *polls -= 1;
+                return *state;
+            }
+            self.script.pop_front();
+        }
+        ControllerState::default()
+    }
+}
+
+/// The inputs of a controller at one moment.
+/// The stick position is given from -1.0 to 1.0 on each axis, with up and right being positive.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ControllerState {
+    pub buttons: Buttons,
+    pub stick_x: f32,
+    pub stick_y: f32,
+}
+
+/// The button bits, in the order the controller reports them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Buttons(pub u16);
+impl Buttons {
+    pub const A: Self = Self(1 << 15);
+    pub const B: Self = Self(1 << 14);
+    pub const Z: Self = Self(1 << 13);
+    pub const START: Self = Self(1 << 12);
+    pub const D_UP: Self = Self(1 << 11);
+    pub const D_DOWN: Self = Self(1 << 10);
+    pub const D_LEFT: Self = Self(1 << 9);
+    pub const D_RIGHT: Self = Self(1 << 8);
+    pub const L: Self = Self(1 << 5);
+    pub const R: Self = Self(1 << 4);
+    pub const C_UP: Self = Self(1 << 3);
+    pub const C_DOWN: Self = Self(1 << 2);
+    pub const C_LEFT: Self = Self(1 << 1);
+    pub const C_RIGHT: Self = Self(1 << 0);
+
+    pub fn contains(self, buttons: Self) -> bool {
+        self.0 & buttons.0 == buttons.0
+    }
+    pub fn set(&mut self, buttons: Self, pressed: bool) {
+        if pressed {
+            self.0 |= buttons.0;
+        }
+        else {
+            self.0 &= !buttons.0;
+        }
+    }
+}
+
+/// How stick positions are turned into the values the controller reports.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StickConfig {
+    /// The value reported at full deflection. Original controllers reach about 85.
+    pub range: u8,
+    /// Positions closer to the center than this, as a fraction of full deflection, read as centered.
+    pub deadzone: f32,
+}
+impl StickConfig {
+    fn axis(&self, position: f32) -> i8 {
+        let magnitude = position.abs().min(1.0);
+        if magnitude <= self.deadzone {
+            return 0;
+        }
+        let scaled = (magnitude - self.deadzone) / (1.0 - self.deadzone) * self.range.min(i8::MAX as u8) as f32;
+        (scaled.round() as i8) * position.signum() as i8
+    }
+}
+impl Default for StickConfig {
+    fn default() -> Self {
+        Self { range: 85, deadzone: 0.0 }
+    }
+}
+
+/// The 32 KB memory card that plugs into the accessory port.
+pub struct ControllerPak {
+    pub memory: Box<[u8; PAK_BYTES]>,
+}
+impl ControllerPak {
+    pub fn new() -> Self {
+        Self {
+            memory: Box::new([0; PAK_BYTES]),
+        }
+    }
+
+    /// Addresses beyond the memory read as zero, like on a real pak.
+    fn read(&self, addr: u16, data: &mut [u8; ACCESSORY_BLOCK]) {
+        let addr = addr as usize;
+        match self.memory.get(addr..addr + ACCESSORY_BLOCK) {
+            Some(block) => data.copy_from_slice(block),
+            None => data.fill(0),
+        }
+    }
+    fn write(&mut self, addr: u16, data: &[u8; ACCESSORY_BLOCK]) {
+        let addr = addr as usize;
+        if let Some(block) = self.memory.get_mut(addr..addr + ACCESSORY_BLOCK) {
+            block.copy_from_slice(data);
+        }
+    }
+}
+impl Default for ControllerPak {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const DEVICE_TYPE: u16 = 0x0500;
+const PAK_PRESENT: u8 = 0x01;
+const PAK_ABSENT: u8 = 0x02;
+
+const PAK_BYTES: usize = 0x8000;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::joybus::{run_commands, CHANNELS};
+
+    type Channels = [Option<Box<dyn JoybusDevice>>; CHANNELS];
+
+    /// Sends one command to the device on the first channel and returns its answer.
+    fn command(channels: &mut Channels, request: &[u8], rx_len: usize) -> Vec<u8> {
+        let mut buffer = vec![request.len() as u8, rx_len as u8];
+        buffer.extend_from_slice(request);
+        buffer.resize(buffer.len() + rx_len, 0);
+        buffer.push(0xFE);
+        run_commands(&mut buffer, channels);
+        assert_eq!(buffer[1], rx_len as u8, "the controller did not answer {request:02x?}");
+        buffer[2 + request.len()..][..rx_len].to_vec()
+    }
+    fn status(channels: &mut Channels) -> Vec<u8> {
+        command(channels, &[0x00], 3)
+    }
+    fn read_buttons(channels: &mut Channels) -> Vec<u8> {
+        command(channels, &[0x01], 4)
+    }
+
+    fn controller(script: impl IntoIterator<Item = (u32, ControllerState)>) -> Channels {
+        let mut channels = Channels::default();
+        channels[0] = Some(Box::new(Controller::new(Box::new(ScriptedInput::new(script)))));
+        channels
+    }
+
+    #[test]
+    fn status_reports_the_controller_pak() {
+        let mut channels = controller([]);
+        assert_eq!(status(&mut channels), [0x05, 0x00, 0x02]);
+
+        let mut pak = Controller::new(Box::new(ScriptedInput::new([])));
+        pak.pak = Some(ControllerPak::new());
+        channels[0] = Some(Box::new(pak));
+        assert_eq!(status(&mut channels), [0x05, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn reads_replay_the_script() {
+        let mut start = Buttons::default();
+        start.set(Buttons::START, true);
+        start.set(Buttons::C_LEFT, true);
+        let mut channels = controller([
+            (2, ControllerState { buttons: Buttons::A, stick_x: 1.0, stick_y: 0.0 }),
+            (1, ControllerState { buttons: start, stick_x: 0.0, stick_y: -0.5 }),
+        ]);
+
+        assert_eq!(read_buttons(&mut channels), [0x80, 0x00, 85, 0]);
+        // Status commands do not poll the inputs
+        status(&mut channels);
+        assert_eq!(read_buttons(&mut channels), [0x80, 0x00, 85, 0]);
+        assert_eq!(read_buttons(&mut channels), [0x10, 0x02, 0, -43i8 as u8]);
+        // Once the script runs out everything is released
+        assert_eq!(read_buttons(&mut channels), [0, 0, 0, 0]);
+    }
+}
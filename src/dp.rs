@@ -0,0 +1,262 @@
+use cpu_mips3::{core::MipsErr, vr4300::WriteSize, word::Word};
+
+use crate::{
+    mi::apply_set_clear,
+    rdp::{Command, NullRenderer, Renderer},
+    rdram::Rdram,
+};
+
+/// The RDP's command registers, which point it at a buffer of commands in RDRAM or, over the XBUS, in DMEM.
+/// Commands are decoded as soon as they are fetched and handed to the [Renderer], so the RDP never appears busy.
+pub struct Dp {
+    start: u32,
+    end: u32,
+    current: u32,
+    /// Whether a START write is waiting for the END write that begins the transfer.
+    start_valid: bool,
+    /// The XBUS, freeze and flush flags, in their DPC_STATUS bits.
+    flags: u32,
+    clock: u32,
+    buf_busy: u32,
+    pipe_busy: u32,
+    tmem: u32,
+    /// The words fetched so far of a command that continues past END.
+    command: Vec<u64>,
+    interrupt: bool,
+    renderer: Box<dyn Renderer>,
+}
+impl Dp {
+    pub fn init() -> Self {
+        Self {
+            start: 0,
+            end: 0,
+            current: 0,
+            start_valid: false,
+            flags: 0,
+            clock: 0,
+            buf_busy: 0,
+            pipe_busy: 0,
+            tmem: 0,
+            command: Vec::new(),
+            interrupt: false,
+            renderer: Box::new(NullRenderer),
+        }
+    }
+
+    pub fn set_renderer(&mut self, renderer: Box<dyn Renderer>) {
+        self.renderer = renderer;
+    }
+    /// Whether commands are fetched from DMEM rather than RDRAM.
+    pub fn xbus(&self) -> bool {
+        self.flags & STATUS_XBUS != 0
+    }
+    /// Returns whether a SYNC_FULL completed since the last call.
+    /// The interrupt is acknowledged through MI_MODE, so the bus raises it in the MI once per sync.
+    pub fn take_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.interrupt)
+    }
+
+    /// Advances the clock counter by one cycle.
+    pub fn tick(&mut self) {
+        self.clock = (self.clock + 1) & COUNTER_MASK;
+    }
+
+    /// The address of the next command word to fetch, unless the buffer is used up or the RDP is frozen.
+    /// The caller reads the word and passes it to [Dp::feed].
+    pub fn next_fetch(&self) -> Option<u32> {
+        (self.flags & STATUS_FREEZE == 0 && self.current < self.end).then_some(self.current)
+    }
+    /// Takes the word at DPC_CURRENT, and executes the command once all of its words are there.
+    pub fn feed(&mut self, word: u64, rdram: &mut Rdram) {
+        self.current += 8;
+        self.command.push(word);
+        if self.command.len() < Command::words(self.command[0]) {
+            return;
+        }
+
+        let command = Command::parse(&self.command);
+        self.command.clear();
+        self.renderer.execute(&command, rdram);
+        if command == Command::SyncFull {
+            self.interrupt = true;
+        }
+    }
+
+    /// Reads a register by its index, as seen from the RSP's COP0, where they follow the SP registers.
+    pub fn read_reg(&self, reg: u32) -> u32 {
+        match reg {
+            REG_START => self.start,
+            REG_END => self.end,
+            REG_CURRENT => self.current,
+            REG_STATUS => self.read_status(),
+            REG_CLOCK => self.clock,
+            REG_BUFBUSY => self.buf_busy,
+            REG_PIPEBUSY => self.pipe_busy,
+            REG_TMEM => self.tmem,
+            _ => 0,
+        }
+    }
+    /// Writes a register by its index. Only START, END and STATUS are writable.
+    /// The caller fetches the commands an END or STATUS write makes available.
+    pub fn write_reg(&mut self, reg: u32, value: u32) {
+        match reg {
+            // A second START write before END is ignored
+            REG_START if !self.start_valid => {
+                self.start = value & ADDR_MASK;
+                self.start_valid = true;
+            }
+            REG_END => {
+                self.end = value & ADDR_MASK;
+                if self.start_valid {
+                    self.current = self.start;
+                    self.start_valid = false;
+                    self.command.clear();
+                }
+            }
+            REG_STATUS => self.write_status(value),
+            _ => (),
+        }
+    }
+
+    pub fn read_word_for_cpu(&self, addr: u32) -> Result<Option<Word>, MipsErr> {
+        if !(REGS_FIRST..=REGS_LAST).contains(&addr) { return Ok(None) }
+        Ok(Some(Word::from_u32_be(self.read_reg((addr & REGS_MASK) / 4 % 8))))
+    }
+    pub fn write_word_for_cpu(&mut self, addr: u32, _size: WriteSize, data: Word) -> Result<bool, MipsErr> {
+        if !(REGS_FIRST..=REGS_LAST).contains(&addr) { return Ok(false) }
+        self.write_reg((addr & REGS_MASK) / 4 % 8, data.to_u32_be());
+        Ok(true)
+    }
+
+    fn read_status(&self) -> u32 {
+        let mut status = self.flags | STATUS_CBUF_READY;
+        if self.start_valid {
+            status |= STATUS_START_VALID;
+        }
+        status
+    }
+    /// The flags are driven by pairs of clear and set bits, followed by bits that clear the counters.
+    fn write_status(&mut self, value: u32) {
+        self.flags = apply_set_clear(self.flags, value, 3);
+
+        if value & WRITE_CLEAR_TMEM != 0 {
+            self.tmem = 0;
+        }
+        if value & WRITE_CLEAR_PIPE != 0 {
+            self.pipe_busy = 0;
+        }
+        if value & WRITE_CLEAR_BUFFER != 0 {
+            self.buf_busy = 0;
+        }
+        if value & WRITE_CLEAR_CLOCK != 0 {
+            self.clock = 0;
+        }
+    }
+}
+
+pub const REGS_FIRST: u32 = 0x04100000;
+pub const REGS_LAST: u32 = 0x041FFFFF;
+const REGS_MASK: u32 = 0x0410_001F;
+
+pub const DPC_START: u32 = 0x0410_0000;
+pub const DPC_END: u32 = 0x0410_0004;
+pub const DPC_CURRENT: u32 = 0x0410_0008;
+pub const DPC_STATUS: u32 = 0x0410_000C;
+pub const DPC_CLOCK: u32 = 0x0410_0010;
+pub const DPC_BUFBUSY: u32 = 0x0410_0014;
+pub const DPC_PIPEBUSY: u32 = 0x0410_0018;
+pub const DPC_TMEM: u32 = 0x0410_001C;
+
+/// Register indices, shared by the CPU's view and the RSP's COP0 registers 8 to 15.
+pub const REG_START: u32 = 0;
+pub const REG_END: u32 = 1;
+pub const REG_CURRENT: u32 = 2;
+pub const REG_STATUS: u32 = 3;
+pub const REG_CLOCK: u32 = 4;
+pub const REG_BUFBUSY: u32 = 5;
+pub const REG_PIPEBUSY: u32 = 6;
+pub const REG_TMEM: u32 = 7;
+
+const STATUS_XBUS: u32 = 1 << 0;
+const STATUS_FREEZE: u32 = 1 << 1;
+const STATUS_CBUF_READY: u32 = 1 << 7;
+const STATUS_START_VALID: u32 = 1 << 10;
+const WRITE_CLEAR_TMEM: u32 = 1 << 6;
+const WRITE_CLEAR_PIPE: u32 = 1 << 7;
+const WRITE_CLEAR_BUFFER: u32 = 1 << 8;
+const WRITE_CLEAR_CLOCK: u32 = 1 << 9;
+
+const ADDR_MASK: u32 = 0x00FF_FFF8;
+const COUNTER_MASK: u32 = 0x00FF_FFFF;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{self, Receiver, Sender};
+
+    use super::*;
+
+    struct Recorder(Sender<Command>);
+    impl Renderer for Recorder {
+        fn execute(&mut self, command: &Command, _rdram: &mut Rdram) {
+            self.0.send(command.clone()).unwrap();
+        }
+    }
+
+    fn recording_dp() -> (Dp, Receiver<Command>) {
+        let (sender, receiver) = mpsc::channel();
+        let mut dp = Dp::init();
+        dp.set_renderer(Box::new(Recorder(sender)));
+        (dp, receiver)
+    }
+    /// Feeds the RDP every word up to DPC_END, the way the bus does from RDRAM.
+    fn fetch(dp: &mut Dp, rdram: &mut Rdram) {
+        while let Some(addr) = dp.next_fetch() {
+            let word = (0..8).fold(0, |word, i| word << 8 | rdram.read_byte(addr + i) as u64);
+            dp.feed(word, rdram);
+        }
+    }
+    fn store(rdram: &mut Rdram, addr: u32, words: &[u64]) {
+        for (i, byte) in words.iter().flat_map(|word| word.to_be_bytes()).enumerate() {
+            rdram.write_byte(addr + i as u32, byte);
+        }
+    }
+
+    #[test]
+    fn start_waits_for_end() {
+        let mut dp = Dp::init();
+        dp.write_reg(REG_START, 0x100);
+        // A second START before END is ignored
+        dp.write_reg(REG_START, 0x200);
+        assert_ne!(dp.read_reg(REG_STATUS) & STATUS_START_VALID, 0);
+        assert_eq!(dp.read_reg(REG_CURRENT), 0);
+
+        dp.write_reg(REG_END, 0x108);
+        assert_eq!(dp.read_reg(REG_STATUS) & STATUS_START_VALID, 0);
+        assert_eq!(dp.read_reg(REG_CURRENT), 0x100);
+        assert_eq!(dp.next_fetch(), Some(0x100));
+    }
+
+    #[test]
+    fn commands_continue_past_end() {
+        let (mut dp, commands) = recording_dp();
+        let mut rdram = Rdram::init(false);
+        let mut triangle = vec![0x0C << 56];
+        triangle.extend(1..12);
+        store(&mut rdram, 0x1000, &triangle);
+        store(&mut rdram, 0x1060, &[0x29 << 56]);
+
+        dp.write_reg(REG_START, 0x1000);
+        dp.write_reg(REG_END, 0x1010);
+        fetch(&mut dp, &mut rdram);
+        assert!(commands.try_recv().is_err());
+
+        // Moving END on without a new START appends to the same buffer
+        dp.write_reg(REG_END, 0x1068);
+        fetch(&mut dp, &mut rdram);
+        let Ok(Command::Triangle(triangle)) = commands.try_recv() else { panic!("the triangle was not assembled") };
+        assert_eq!(triangle.shade.unwrap().dy[3], 9 << 16 | 11);
+        assert_eq!(commands.try_recv(), Ok(Command::SyncFull));
+        assert!(commands.try_recv().is_err());
+        assert!(dp.take_interrupt());
+    }
+}
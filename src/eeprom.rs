@@ -0,0 +1,60 @@
+use std::{
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+
+use crate::{
+    joybus::{JoybusDevice, EEPROM_BLOCK},
+    save::{SaveFile, SaveType},
+};
+
+
+/// The serial EEPROM some cartridges save to, reached through the PIF's cartridge channel.
+pub struct Eeprom {
+    save: SaveFile,
+    device_type: u16,
+}
+impl Eeprom {
+    /// Opens an EEPROM of the given type. Save types other than EEPROM are rejected.
+    pub fn open(save_type: SaveType, path: Option<PathBuf>) -> io::Result<Self> {
+        let device_type = match save_type {
+            SaveType::Eeprom4K => DEVICE_TYPE_4K,
+            SaveType::Eeprom16K => DEVICE_TYPE_16K,
+            other => return Err(io::Error::new(ErrorKind::InvalidInput, format!("{other:?} is not an EEPROM"))),
+        };
+        Ok(Self {
+            save: SaveFile::open(path, save_type.size(), ERASED)?,
+            device_type,
+        })
+    }
+
+    /// The 4K chip only decodes the low address bits, so larger block numbers wrap around.
+    fn offset(&self, block: u8) -> usize {
+        block as usize * EEPROM_BLOCK % self.save.data().len()
+    }
+}
+impl JoybusDevice for Eeprom {
+    fn status(&self) -> [u8; 3] {
+        let [hi, lo] = self.device_type.to_be_bytes();
+        [hi, lo, 0]
+    }
+
+    fn read_eeprom(&mut self, block: u8, data: &mut [u8; EEPROM_BLOCK]) -> bool {
+        let offset = self.offset(block);
+        data.copy_from_slice(&self.save.data()[offset..offset + EEPROM_BLOCK]);
+        true
+    }
+    fn write_eeprom(&mut self, block: u8, data: &[u8; EEPROM_BLOCK]) -> bool {
+        let offset = self.offset(block);
+        self.save.data_mut()[offset..offset + EEPROM_BLOCK].copy_from_slice(data);
+        true
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.save.flush()
+    }
+}
+
+const DEVICE_TYPE_4K: u16 = 0x0080;
+const DEVICE_TYPE_16K: u16 = 0x00C0;
+const ERASED: u8 = 0xFF;
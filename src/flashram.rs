@@ -0,0 +1,143 @@
+use std::{io, path::PathBuf};
+
+use cpu_mips3::word::Word;
+
+use crate::save::{SaveFile, SaveType};
+
+
+/// The 128K FlashRAM some cartridges have in domain 2 instead of SRAM.
+///
+/// It is driven through a command register: games select a mode, then read data or the status
+/// through PI DMAs, or fill the page buffer and have it programmed, or erase sectors or the whole chip.
+pub struct FlashRam {
+    save: SaveFile,
+    mode: FlashMode,
+    status: u64,
+    /// The byte offset of the page or sector the next erase or program command applies to.
+    offset: usize,
+    page_buffer: [u8; PAGE_BYTES],
+}
+impl FlashRam {
+    pub fn open(path: Option<PathBuf>) -> io::Result<Self> {
+        Ok(Self {
+            save: SaveFile::open(path, SaveType::FlashRam.size(), ERASED)?,
+            mode: FlashMode::Read,
+            status: 0,
+            offset: 0,
+            page_buffer: [0; PAGE_BYTES],
+        })
+    }
+
+    /// The CPU can only read the upper half of the status register.
+    pub fn read_word(&self, addr: u32) -> Word {
+        match addr {
+            FLASH_STATUS => Word::from_u32_be((self.status >> 32) as u32),
+            _ => Word::zero(),
+        }
+    }
+    pub fn write_word(&mut self, addr: u32, data: Word) {
+        if addr == FLASH_COMMAND {
+            self.command(data.to_u32_be());
+        }
+    }
+    pub fn read_dma(&self, addr: u32, data: &mut [u8]) {
+        match self.mode {
+            // In read mode, DMA addresses count 16-bit units of the flash array
+            FlashMode::Read => {
+                let flash = self.save.data();
+                let first = (addr as usize & 0xFFFF) * 2;
+                for (i, byte) in data.iter_mut().enumerate() {
+                    *byte = flash[(first + i) % flash.len()];
+                }
+            }
+            FlashMode::Status => {
+                let status = self.status.to_be_bytes();
+                for (i, byte) in data.iter_mut().enumerate() {
+                    *byte = status[(addr as usize + i) % 8];
+                }
+            }
+            _ => data.fill(0),
+        }
+    }
+    pub fn write_dma(&mut self, addr: u32, data: &[u8]) {
+        if self.mode != FlashMode::Write {
+            return;
+        }
+        for (i, &byte) in data.iter().enumerate() {
+            self.page_buffer[(addr as usize + i) % PAGE_BYTES] = byte;
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.save.flush()
+    }
+
+    fn command(&mut self, command: u32) {
+        let page = (command & 0xFFFF) as usize;
+        match command >> 24 {
+            CMD_SECTOR_ERASE => {
+                self.mode = FlashMode::SectorErase;
+                self.offset = (page * PAGE_BYTES) & !(SECTOR_BYTES - 1);
+            }
+            CMD_CHIP_ERASE => self.mode = FlashMode::ChipErase,
+            CMD_ERASE_MODE => self.status = STATUS_ERASE,
+            CMD_PROGRAM_PAGE => {
+                self.offset = page * PAGE_BYTES;
+                self.status = STATUS_PROGRAM;
+            }
+            CMD_WRITE_MODE => self.mode = FlashMode::Write,
+            CMD_EXECUTE => self.execute(),
+            CMD_STATUS_MODE => {
+                self.mode = FlashMode::Status;
+                self.status = STATUS_IDENTIFY;
+            }
+            CMD_READ_MODE => {
+                self.mode = FlashMode::Read;
+                self.status = STATUS_READ;
+            }
+            _ => (),
+        }
+    }
+    /// Carries out the erase or program operation that was set up, which completes instantly.
+    fn execute(&mut self) {
+        let len = self.save.data().len();
+        let offset = self.offset % len;
+        match self.mode {
+            FlashMode::SectorErase => self.save.data_mut()[offset..offset + SECTOR_BYTES].fill(ERASED),
+            FlashMode::ChipErase => self.save.data_mut().fill(ERASED),
+            FlashMode::Write => self.save.data_mut()[offset..offset + PAGE_BYTES].copy_from_slice(&self.page_buffer),
+            _ => (),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum FlashMode {
+    Read,
+    Status,
+    SectorErase,
+    ChipErase,
+    Write,
+}
+
+pub const FLASH_STATUS: u32 = 0x0800_0000;
+pub const FLASH_COMMAND: u32 = 0x0801_0000;
+
+const PAGE_BYTES: usize = 128;
+const SECTOR_BYTES: usize = 128 * PAGE_BYTES;
+const ERASED: u8 = 0xFF;
+
+const CMD_CHIP_ERASE: u32 = 0x3C;
+const CMD_SECTOR_ERASE: u32 = 0x4B;
+const CMD_ERASE_MODE: u32 = 0x78;
+const CMD_PROGRAM_PAGE: u32 = 0xA5;
+const CMD_WRITE_MODE: u32 = 0xB4;
+const CMD_EXECUTE: u32 = 0xD2;
+const CMD_STATUS_MODE: u32 = 0xE1;
+const CMD_READ_MODE: u32 = 0xF0;
+
+/// The silicon ID of the Macronix chip, with the low byte reporting the state of the last operation.
+const STATUS_IDENTIFY: u64 = 0x1111_8001_00C2_001E;
+const STATUS_ERASE: u64 = 0x1111_8008_00C2_001E;
+const STATUS_PROGRAM: u64 = 0x1111_8004_00C2_001E;
+const STATUS_READ: u64 = 0x1111_8004_F000_0000;
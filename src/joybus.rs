@@ -0,0 +1,196 @@
+use std::io;
+
+/// A device that can be plugged into one of the PIF's joybus channels, like a controller or an EEPROM.
+/// The PIF decodes the commands and their checksums, devices only need to implement the ones they understand.
+pub trait JoybusDevice {
+    /// The three byte answer to the status and reset commands:
+    /// a big endian device type followed by a device specific status byte.
+    fn status(&self) -> [u8; 3];
+    fn reset(&mut self) {}
+
+    /// The button and stick state of a controller, or None if the device is no controller.
+    fn read_buttons(&mut self) -> Option<[u8; 4]> {
+        None
+    }
+
+    /// Reads 32 bytes from the accessory port of a controller, at an address with the checksum bits cleared.
+    /// Returns false if no accessory is inserted.
+    fn read_accessory(&mut self, _addr: u16, _data: &mut [u8; ACCESSORY_BLOCK]) -> bool {
+        false
+    }
+    /// Writes 32 bytes to the accessory port of a controller. Returns false if no accessory is inserted.
+    fn write_accessory(&mut self, _addr: u16, _data: &[u8; ACCESSORY_BLOCK]) -> bool {
+        false
+    }
+
+    /// Reads an 8 byte block of EEPROM, or returns false if the device has none.
+    fn read_eeprom(&mut self, _block: u8, _data: &mut [u8; EEPROM_BLOCK]) -> bool {
+        false
+    }
+    /// Writes an 8 byte block of EEPROM, or returns false if the device has none.
+    fn write_eeprom(&mut self, _block: u8, _data: &[u8; EEPROM_BLOCK]) -> bool {
+        false
+    }
+
+    /// Writes any save data the device holds to its backing file.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The joybus channels of the PIF: the four controller ports, then the cartridge.
+pub const CHANNELS: usize = 5;
+pub const CARTRIDGE_CHANNEL: usize = 4;
+
+pub const ACCESSORY_BLOCK: usize = 32;
+pub const EEPROM_BLOCK: usize = 8;
+
+/// Walks the command buffer in PIF RAM, passing each command to the device on its channel
+/// and writing the answers back in place.
+///
+/// Every command is a transmit length, a receive length, the bytes to transmit and room for the bytes received.
+/// A zero in place of the transmit length skips a channel, 0xFF is padding and 0xFE ends the buffer.
+pub fn run_commands(buffer: &mut [u8], channels: &mut [Option<Box<dyn JoybusDevice>>; CHANNELS]) {
+    let mut channel = 0;
+    let mut i = 0;
+
+    while i < buffer.len() && channel < CHANNELS {
+        let tx = buffer[i];
+        match tx {
+            TX_END => break,
+            TX_SKIP_CHANNEL => {
+                channel += 1;
+                i += 1;
+                continue;
+            }
+            tx if tx & TX_PADDING != 0 => {
+                i += 1;
+                continue;
+            }
+            _ => (),
+        }
+
+        let Some(&rx) = buffer.get(i + 1) else { break };
+        if rx == TX_END {
+            break;
+        }
+        let tx_len = (tx & LENGTH_MASK) as usize;
+        let rx_len = (rx & LENGTH_MASK) as usize;
+        let tx_first = i + 2;
+        let rx_first = tx_first + tx_len;
+        let end = rx_first + rx_len;
+        if end > buffer.len() {
+            break;
+        }
+
+        let (request, rest) = buffer[tx_first..end].split_at_mut(tx_len);
+        let response = match channels[channel].as_deref_mut() {
+            Some(device) => execute(device, request),
+            None => None,
+        };
+        let flags = match response {
+            Some(response) => {
+                let len = response.len().min(rx_len);
+                rest[..len].copy_from_slice(&response[..len]);
+                if response.len() == rx_len { 0 } else { RX_SIZE_ERROR }
+            }
+            None => RX_NO_DEVICE,
+        };
+        buffer[i + 1] = rx | flags;
+
+        channel += 1;
+        i = end;
+    }
+}
+
+/// Runs a single command on a device, returning its answer, or None if the device did not respond.
+fn execute(device: &mut dyn JoybusDevice, request: &[u8]) -> Option<Vec<u8>> {
+    let (&command, args) = request.split_first()?;
+    match command {
+        CMD_STATUS => Some(device.status().to_vec()),
+        CMD_RESET => {
+            device.reset();
+            Some(device.status().to_vec())
+        }
+        CMD_READ_BUTTONS => device.read_buttons().map(|buttons| buttons.to_vec()),
+        CMD_READ_ACCESSORY => {
+            let addr = accessory_addr(args)?;
+            let mut data = [0; ACCESSORY_BLOCK];
+            let present = device.read_accessory(addr, &mut data);
+            let mut response = data.to_vec();
+            response.push(accessory_data_crc(&data, present));
+            Some(response)
+        }
+        CMD_WRITE_ACCESSORY => {
+            let addr = accessory_addr(args)?;
+            let data: &[u8; ACCESSORY_BLOCK] = args.get(2..2 + ACCESSORY_BLOCK)?.try_into().ok()?;
+            let present = device.write_accessory(addr, data);
+            Some(vec![accessory_data_crc(data, present)])
+        }
+        CMD_READ_EEPROM => {
+            let &block = args.first()?;
+            let mut data = [0; EEPROM_BLOCK];
+            device.read_eeprom(block, &mut data).then(|| data.to_vec())
+        }
+        CMD_WRITE_EEPROM => {
+            let &block = args.first()?;
+            let data: &[u8; EEPROM_BLOCK] = args.get(1..1 + EEPROM_BLOCK)?.try_into().ok()?;
+            // The only status bit is "busy", which we never are
+            device.write_eeprom(block, data).then(|| vec![0])
+        }
+        _ => None,
+    }
+}
+
+/// Extracts the address of an accessory command, or None if its checksum does not match.
+fn accessory_addr(args: &[u8]) -> Option<u16> {
+    let addr = u16::from_be_bytes([*args.first()?, *args.get(1)?]);
+    let addr_bits = addr & !ACCESSORY_ADDR_CRC_MASK;
+    (addr_crc(addr_bits) == addr & ACCESSORY_ADDR_CRC_MASK).then_some(addr_bits)
+}
+/// The five bit checksum protecting the upper eleven bits of an accessory address.
+pub fn addr_crc(addr: u16) -> u16 {
+    const XOR_TABLE: [u16; 11] = [0x15, 0x1F, 0x0B, 0x16, 0x19, 0x07, 0x0E, 0x1C, 0x0D, 0x1A, 0x01];
+
+    (5..16)
+        .filter(|bit| addr & (1 << bit) != 0)
+        .fold(0, |crc, bit| crc ^ XOR_TABLE[bit - 5])
+}
+/// The checksum a controller answers accessory reads and writes with.
+/// Without an accessory it is inverted, which is how games tell that the port is empty.
+fn accessory_data_crc(data: &[u8; ACCESSORY_BLOCK], present: bool) -> u8 {
+    let crc = data_crc(data);
+    if present { crc } else { !crc }
+}
+/// The CRC-8 with polynomial 0x85 over a block of accessory data.
+pub fn data_crc(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    // The trailing zero byte flushes the data through the shift register
+    for &byte in data.iter().chain(&[0]) {
+        for bit in (0..8).rev() {
+            let xor = if crc & 0x80 != 0 { 0x85 } else { 0 };
+            crc = (crc << 1 | (byte >> bit) & 1) ^ xor;
+        }
+    }
+    crc
+}
+
+const TX_SKIP_CHANNEL: u8 = 0x00;
+const TX_END: u8 = 0xFE;
+/// Set in both 0xFF, the usual padding byte, and 0xFD, which resets the channel on a real PIF.
+const TX_PADDING: u8 = 0x80;
+const LENGTH_MASK: u8 = 0x3F;
+
+/// Error flags the PIF sets in the receive length of a command.
+const RX_NO_DEVICE: u8 = 0x80;
+const RX_SIZE_ERROR: u8 = 0x40;
+
+const ACCESSORY_ADDR_CRC_MASK: u16 = 0x1F;
+
+pub const CMD_STATUS: u8 = 0x00;
+pub const CMD_READ_BUTTONS: u8 = 0x01;
+pub const CMD_READ_ACCESSORY: u8 = 0x02;
+pub const CMD_WRITE_ACCESSORY: u8 = 0x03;
+pub const CMD_READ_EEPROM: u8 = 0x04;
+pub const CMD_WRITE_EEPROM: u8 = 0x05;
+pub const CMD_RESET: u8 = 0xFF;
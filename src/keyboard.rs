@@ -0,0 +1,81 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use no64::controller::{Buttons, ControllerState, InputSource};
+
+
+/// Feeds key events from the terminal to a controller.
+/// Clones share their state, so one can be handed to the controller while the app keeps the other.
+///
+/// Most terminals only report key presses, so every press holds its input for a few polls,
+/// and key repeat keeps it held for as long as the key is down.
+#[derive(Clone, Default)]
+pub struct KeyboardInput {
+    held: Rc<RefCell<Vec<(KeyCode, u32)>>>,
+}
+impl KeyboardInput {
+    /// Records a key event, returning false if the key is not bound to any input.
+    pub fn handle_key(&self, key: KeyEvent) -> bool {
+        if binding(key.code).is_none() {
+            return false;
+        }
+        let mut held = self.held.borrow_mut();
+        held.retain(|&(code, _)| code != key.code);
+        if key.kind != KeyEventKind::Release {
+            held.push((key.code, HOLD_POLLS));
+        }
+        true
+    }
+}
+impl InputSource for KeyboardInput {
+    fn poll(&mut self) -> ControllerState {
+        let mut state = ControllerState::default();
+        let mut held = self.held.borrow_mut();
+        for (code, polls) in held.iter_mut() {
+            match binding(*code) {
+                Some(Binding::Button(button)) => state.buttons.set(button, true),
+                Some(Binding::Stick(x, y)) => {
+                    state.stick_x = (state.stick_x + x).clamp(-1.0, 1.0);
+                    state.stick_y = (state.stick_y + y).clamp(-1.0, 1.0);
+                }
+                None => (),
+            }
+            *polls -= 1;
+        }
+        held.retain(|&(_, polls)| polls != 0);
+        state
+    }
+}
+
+enum Binding {
+    Button(Buttons),
+    Stick(f32, f32),
+}
+
+fn binding(code: KeyCode) -> Option<Binding> {
+    let binding = match code {
+        KeyCode::Up => Binding::Stick(0.0, 1.0),
+        KeyCode::Down => Binding::Stick(0.0, -1.0),
+        KeyCode::Left => Binding::Stick(-1.0, 0.0),
+        KeyCode::Right => Binding::Stick(1.0, 0.0),
+        KeyCode::Char('x') => Binding::Button(Buttons::A),
+        KeyCode::Char('c') => Binding::Button(Buttons::B),
+        KeyCode::Char('z') => Binding::Button(Buttons::Z),
+        KeyCode::Char('s') => Binding::Button(Buttons::START),
+        KeyCode::Char('a') => Binding::Button(Buttons::L),
+        KeyCode::Char('d') => Binding::Button(Buttons::R),
+        KeyCode::Char('i') => Binding::Button(Buttons::C_UP),
+        KeyCode::Char('k') => Binding::Button(Buttons::C_DOWN),
+        KeyCode::Char('j') => Binding::Button(Buttons::C_LEFT),
+        KeyCode::Char('l') => Binding::Button(Buttons::C_RIGHT),
+        KeyCode::Char('t') => Binding::Button(Buttons::D_UP),
+        KeyCode::Char('g') => Binding::Button(Buttons::D_DOWN),
+        KeyCode::Char('f') => Binding::Button(Buttons::D_LEFT),
+        KeyCode::Char('h') => Binding::Button(Buttons::D_RIGHT),
+        _ => return None,
+    };
+    Some(binding)
+}
+
+/// Games poll once per frame, so this holds a press for a little longer than the usual key repeat delay.
+const HOLD_POLLS: u32 = 30;
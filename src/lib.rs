@@ -1,5 +1,20 @@
 pub mod pif_nus;
 pub mod rsp;
-pub mod dmem;
-pub mod imem;
+pub mod dp;
+pub mod rdp;
+pub mod sp_mem;
+pub mod mi;
+pub mod pi;
+pub mod rdram;
+pub mod ri;
+pub mod si;
+pub mod bus;
+pub mod cartridge;
+pub mod save;
+pub mod eeprom;
+pub mod sram;
+pub mod flashram;
+pub mod cic;
+pub mod joybus;
+pub mod controller;
 pub mod console;
@@ -13,9 +13,11 @@ use cpu_mips3::{
     word::Word,
 };
 use crossterm::event::{poll, Event, KeyCode, KeyEvent, KeyEventKind};
-use no64::{console::Console, pif_nus::PifNus, rsp::Rsp};
+use keyboard::KeyboardInput;
+use no64::{cartridge::Cartridge, console::Console, controller::Controller, pif_nus::PifNus, rsp::Rsp};
 use terminal::Terminal;
 
+mod keyboard;
 mod terminal;
 
 fn main() -> anyhow::Result<()> {
@@ -26,6 +28,7 @@ fn main() -> anyhow::Result<()> {
 
 struct App {
     console: Console,
+    keyboard: KeyboardInput,
     running: bool,
     term: Terminal<Stdout>,
     line: String,
@@ -35,10 +38,21 @@ struct App {
 }
 impl App {
     fn new() -> anyhow::Result<Self> {
+        let mut console = Console::init();
+        if let Some(path) = std::env::args().nth(1) {
+            let mut cartridge = Cartridge::load(path)?;
+            if let Some(save_type) = std::env::args().nth(2) {
+                cartridge.save_type = save_type.parse()?;
+            }
+            console.insert_cartridge(cartridge)?;
+        }
+        let keyboard = KeyboardInput::default();
+        console.plug_controller(0, Controller::new(Box::new(keyboard.clone())));
         let term = Terminal::init(stdout())?;
 
         Ok(Self {
-            console: Console::init(),
+            console,
+            keyboard,
             running: true,
             term,
             line: String::new(),
@@ -56,6 +70,7 @@ impl App {
             sleep(Duration::from_secs_f64(1.0 / 60.0));
         }
 
+        self.console.flush_saves()?;
         Ok(())
     }
     fn handle_events(&mut self) -> anyhow::Result<()> {
@@ -73,6 +88,10 @@ impl App {
         Ok(())
     }
     fn handle_key(&mut self, key: KeyEvent) -> anyhow::Result<()> {
+        // While the emulator runs, bound keys go to the controller instead of the command line
+        if self.state != State::Idle && self.keyboard.handle_key(key) {
+            return Ok(());
+        }
         if key.kind != KeyEventKind::Press {
             return Ok(());
         };
@@ -171,8 +190,8 @@ impl App {
     }
 
     fn step_emulator(&mut self) -> anyhow::Result<()> {
-        let (cpu, mut bus) = self.console.cpu_and_bus();
-        let res = cpu.step_forward(&mut bus);
+        let res = self.console.step();
+        self.errors.extend(self.console.bus.log.drain(..));
 
         if let Err(err) = res {
             self.render()?;
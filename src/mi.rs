@@ -0,0 +1,124 @@
+use cpu_mips3::{core::MipsErr, vr4300::WriteSize, word::Word};
+
+
+/// The MIPS Interface, which collects the interrupts of all RCP devices into the single line wired to the CPU's IP2.
+pub struct Mi {
+    mode: u32,
+    interrupt: u32,
+    mask: u32,
+}
+impl Mi {
+    pub fn init() -> Self {
+        Self {
+            mode: 0,
+            interrupt: 0,
+            mask: 0,
+        }
+    }
+
+    pub fn raise(&mut self, interrupt: Interrupt) {
+        self.interrupt |= interrupt.bit();
+    }
+    pub fn clear(&mut self, interrupt: Interrupt) {
+        self.interrupt &= !interrupt.bit();
+    }
+    pub fn set(&mut self, interrupt: Interrupt, raised: bool) {
+        if raised {
+            self.raise(interrupt);
+        }
+        else {
+            self.clear(interrupt);
+        }
+    }
+    /// Whether any unmasked interrupt is raised, which is the level of the CPU's IP2 line.
+    pub fn interrupt_pending(&self) -> bool {
+        self.interrupt & self.mask != 0
+    }
+
+    pub fn read_word_for_cpu(&self, addr: u32) -> Result<Option<Word>, MipsErr> {
+        if !(REGS_FIRST..=REGS_LAST).contains(&addr) { return Ok(None) }
+        let value = match addr & REGS_MASK {
+            MI_MODE => self.mode,
+            MI_VERSION => VERSION,
+            MI_INTERRUPT => self.interrupt,
+            MI_MASK => self.mask,
+            _ => 0,
+        };
+        Ok(Some(Word::from_u32_be(value)))
+    }
+    pub fn write_word_for_cpu(&mut self, addr: u32, _size: WriteSize, data: Word) -> Result<bool, MipsErr> {
+        if !(REGS_FIRST..=REGS_LAST).contains(&addr) { return Ok(false) }
+        // The registers are written by command bits, so partial writes do not merge with the old value
+        let value = data.to_u32_be();
+        match addr & REGS_MASK {
+            MI_MODE => self.write_mode(value),
+            MI_MASK => self.mask = apply_set_clear(self.mask, value, 6),
+            _ => (),
+        }
+        Ok(true)
+    }
+
+    fn write_mode(&mut self, value: u32) {
+        let length = value & MODE_INIT_LENGTH;
+        // Init mode and EBus test mode each have a clear and a set bit, starting at bit 7
+        let flags = apply_set_clear((self.mode >> 7) & 0b11, value >> 7, 2);
+        let mut mode = length | flags << 7 | (self.mode & MODE_RDRAM_REG);
+
+        if value & MODE_CLEAR_RDRAM_REG != 0 {
+            mode &= !MODE_RDRAM_REG;
+        }
+        if value & MODE_SET_RDRAM_REG != 0 {
+            mode |= MODE_RDRAM_REG;
+        }
+        if value & MODE_CLEAR_DP_INTERRUPT != 0 {
+            self.clear(Interrupt::Dp);
+        }
+        self.mode = mode;
+    }
+}
+
+/// The sources of RCP interrupts, in the bit order of MI_INTERRUPT and MI_MASK.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    Sp,
+    Si,
+    Ai,
+    Vi,
+    Pi,
+    Dp,
+}
+impl Interrupt {
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// Applies a command word that has a clear bit followed by a set bit for each of `count` flags.
+pub(crate) fn apply_set_clear(mut flags: u32, command: u32, count: u32) -> u32 {
+    for i in 0..count {
+        if command & (1 << (2 * i)) != 0 {
+            flags &= !(1 << i);
+        }
+        if command & (1 << (2 * i + 1)) != 0 {
+            flags |= 1 << i;
+        }
+    }
+    flags
+}
+
+pub const REGS_FIRST: u32 = 0x0430_0000;
+pub const REGS_LAST: u32 = 0x043F_FFFF;
+const REGS_MASK: u32 = 0x0430_000F;
+
+pub const MI_MODE: u32 = 0x0430_0000;
+pub const MI_VERSION: u32 = 0x0430_0004;
+pub const MI_INTERRUPT: u32 = 0x0430_0008;
+pub const MI_MASK: u32 = 0x0430_000C;
+
+const VERSION: u32 = 0x0202_0102;
+
+const MODE_INIT_LENGTH: u32 = 0x7F;
+const MODE_RDRAM_REG: u32 = 1 << 9;
+const MODE_CLEAR_DP_INTERRUPT: u32 = 1 << 11;
+const MODE_CLEAR_RDRAM_REG: u32 = 1 << 12;
+const MODE_SET_RDRAM_REG: u32 = 1 << 13;
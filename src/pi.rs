@@ -0,0 +1,237 @@
+use cpu_mips3::{core::MipsErr, vr4300::WriteSize, word::Word};
+
+
+/// The Peripheral Interface, which connects the cartridge bus domains and DMAs between them and RDRAM.
+/// Transfers take as long as the domain timing says, the data only moves once [Pi::tick] reports them done.
+pub struct Pi {
+    dram_addr: u32,
+    cart_addr: u32,
+    interrupt: bool,
+    domains: [DomainTiming; 2],
+    dma: Option<PiDma>,
+    /// Cycles until the running DMA finishes.
+    busy_cycles: u32,
+}
+impl Pi {
+    pub fn init() -> Self {
+        Self {
+            dram_addr: 0,
+            cart_addr: 0,
+            interrupt: false,
+            domains: [DomainTiming::default(); 2],
+            dma: None,
+            busy_cycles: 0,
+        }
+    }
+
+    pub fn interrupt(&self) -> bool {
+        self.interrupt
+    }
+    pub fn domain_timing(&self, domain: usize) -> DomainTiming {
+        self.domains[domain - 1]
+    }
+
+    /// Advances the running DMA by one cycle, and returns it once its time is up.
+    pub fn tick(&mut self) -> Option<PiDma> {
+        self.dma?;
+        self.busy_cycles = self.busy_cycles.saturating_sub(1);
+        if self.busy_cycles == 0 { self.dma.take() } else { None }
+    }
+    /// Finishes a DMA: the address registers point past the transferred data and the interrupt is raised.
+    pub fn complete_dma(&mut self, dma: PiDma) {
+        self.dram_addr = dma.dram_addr.wrapping_add(dma.len) & DRAM_ADDR_MASK;
+        self.cart_addr = dma.cart_addr.wrapping_add(dma.len) & CART_ADDR_MASK;
+        self.interrupt = true;
+    }
+
+    pub fn read_word_for_cpu(&self, addr: u32) -> Result<Option<Word>, MipsErr> {
+        if !(REGS_FIRST..=REGS_LAST).contains(&addr) { return Ok(None) }
+        let value = match addr & REGS_MASK {
+            PI_DRAM_ADDR => self.dram_addr,
+            PI_CART_ADDR => self.cart_addr,
+            PI_STATUS => self.status(),
+            reg @ PI_BSD_DOM1_LAT..=PI_BSD_DOM2_RLS => {
+                let index = (reg - PI_BSD_DOM1_LAT) / 4;
+                self.domains[index as usize / 4].read(index % 4)
+            }
+            // The length registers read back as 0x7F once the transfer is done
+            PI_RD_LEN | PI_WR_LEN => 0x7F,
+            _ => 0,
+        };
+        Ok(Some(Word::from_u32_be(value)))
+    }
+    pub fn write_word_for_cpu(&mut self, addr: u32, _size: WriteSize, data: Word) -> Result<bool, MipsErr> {
+        if !(REGS_FIRST..=REGS_LAST).contains(&addr) { return Ok(false) }
+        let value = data.to_u32_be();
+        match addr & REGS_MASK {
+            PI_DRAM_ADDR => self.dram_addr = value & DRAM_ADDR_MASK,
+            PI_CART_ADDR => self.cart_addr = value & CART_ADDR_MASK,
+            PI_RD_LEN => self.start_dma(PiDirection::ToCart, value),
+            PI_WR_LEN => self.start_dma(PiDirection::ToDram, value),
+            PI_STATUS => {
+                // Resetting the controller abandons the running DMA
+                if value & STATUS_RESET != 0 {
+                    self.dma = None;
+                }
+                if value & (STATUS_RESET | STATUS_CLEAR_INTERRUPT) != 0 {
+                    self.interrupt = false;
+                }
+            }
+            reg @ PI_BSD_DOM1_LAT..=PI_BSD_DOM2_RLS => {
+                let index = (reg - PI_BSD_DOM1_LAT) / 4;
+                self.domains[index as usize / 4].write(index % 4, value);
+            }
+            _ => (),
+        }
+        Ok(true)
+    }
+
+    fn status(&self) -> u32 {
+        let mut status = 0;
+        if self.dma.is_some() {
+            status |= STATUS_DMA_BUSY | STATUS_IO_BUSY;
+        }
+        if self.interrupt {
+            status |= STATUS_INTERRUPT;
+        }
+        status
+    }
+    /// Starting a DMA while another one runs is ignored, like on the SI.
+    fn start_dma(&mut self, direction: PiDirection, len: u32) {
+        if self.dma.is_some() {
+            return;
+        }
+        let len = (len & LEN_MASK) + 1;
+        self.busy_cycles = self.domain_timing(domain(self.cart_addr)).transfer_cycles(len);
+        self.dma = Some(PiDma {
+            direction,
+            dram_addr: self.dram_addr,
+            cart_addr: self.cart_addr,
+            len,
+        })
+    }
+}
+
+/// Which cartridge domain a cartridge bus address belongs to.
+/// Domain 2 covers the 64DD registers and the save memory, domain 1 everything else.
+fn domain(cart_addr: u32) -> usize {
+    match cart_addr {
+        CART_DOM2_ADDR1_FIRST..=CART_DOM2_ADDR1_LAST | CART_DOM2_ADDR2_FIRST..=CART_DOM2_ADDR2_LAST => 2,
+        _ => 1,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PiDirection {
+    ToDram,
+    ToCart,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PiDma {
+    pub direction: PiDirection,
+    pub dram_addr: u32,
+    pub cart_addr: u32,
+    pub len: u32,
+}
+
+/// The bus timing the boot code configures for a cartridge domain.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DomainTiming {
+    pub latency: u8,
+    pub pulse_width: u8,
+    pub page_size: u8,
+    pub release: u8,
+}
+impl DomainTiming {
+    fn read(&self, reg: u32) -> u32 {
+        match reg {
+            0 => self.latency as u32,
+            1 => self.pulse_width as u32,
+            2 => self.page_size as u32,
+            _ => self.release as u32,
+        }
+    }
+    fn write(&mut self, reg: u32, value: u32) {
+        match reg {
+            0 => self.latency = value as u8,
+            1 => self.pulse_width = value as u8,
+            2 => self.page_size = value as u8 & 0xF,
+            _ => self.release = value as u8 & 0x3,
+        }
+    }
+
+    /// How many cycles a transfer of `len` bytes takes.
+    /// Every page starts with the latency, and every halfword costs a pulse and a release.
+    pub fn transfer_cycles(&self, len: u32) -> u32 {
+        let page_bytes = 1 << (self.page_size + 2);
+        let pages = len.div_ceil(page_bytes);
+        let halfwords = len.div_ceil(2);
+        pages * (self.latency as u32 + 1) + halfwords * (self.pulse_width as u32 + 1 + self.release as u32 + 1)
+    }
+}
+
+pub const REGS_FIRST: u32 = 0x0460_0000;
+pub const REGS_LAST: u32 = 0x046F_FFFF;
+const REGS_MASK: u32 = 0x0460_003F;
+
+pub const PI_DRAM_ADDR: u32 = 0x0460_0000;
+pub const PI_CART_ADDR: u32 = 0x0460_0004;
+pub const PI_RD_LEN: u32 = 0x0460_0008;
+pub const PI_WR_LEN: u32 = 0x0460_000C;
+pub const PI_STATUS: u32 = 0x0460_0010;
+pub const PI_BSD_DOM1_LAT: u32 = 0x0460_0014;
+pub const PI_BSD_DOM1_PWD: u32 = 0x0460_0018;
+pub const PI_BSD_DOM1_PGS: u32 = 0x0460_001C;
+pub const PI_BSD_DOM1_RLS: u32 = 0x0460_0020;
+pub const PI_BSD_DOM2_LAT: u32 = 0x0460_0024;
+pub const PI_BSD_DOM2_PWD: u32 = 0x0460_0028;
+pub const PI_BSD_DOM2_PGS: u32 = 0x0460_002C;
+pub const PI_BSD_DOM2_RLS: u32 = 0x0460_0030;
+
+/// The 64DD registers, in domain 2.
+pub const CART_DOM2_ADDR1_FIRST: u32 = 0x0500_0000;
+pub const CART_DOM2_ADDR1_LAST: u32 = 0x05FF_FFFF;
+
+/// The cartridge save memory, in domain 2.
+pub const CART_DOM2_ADDR2_FIRST: u32 = 0x0800_0000;
+pub const CART_DOM2_ADDR2_LAST: u32 = 0x0FFF_FFFF;
+
+/// The cartridge ROM, in domain 1.
+pub const CART_ROM_FIRST: u32 = 0x1000_0000;
+pub const CART_ROM_LAST: u32 = 0x1FBF_FFFF;
+
+const DRAM_ADDR_MASK: u32 = 0x00FF_FFFE;
+const CART_ADDR_MASK: u32 = 0xFFFF_FFFE;
+const LEN_MASK: u32 = 0x00FF_FFFF;
+const STATUS_RESET: u32 = 1 << 0;
+const STATUS_CLEAR_INTERRUPT: u32 = 1 << 1;
+const STATUS_DMA_BUSY: u32 = 1 << 0;
+const STATUS_IO_BUSY: u32 = 1 << 1;
+const STATUS_INTERRUPT: u32 = 1 << 3;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(pi: &mut Pi, addr: u32, value: u32) {
+        pi.write_word_for_cpu(addr, WriteSize::Four, Word::from_u32_be(value)).unwrap();
+    }
+    fn read(pi: &Pi, addr: u32) -> u32 {
+        pi.read_word_for_cpu(addr).unwrap().unwrap().to_u32_be()
+    }
+
+    #[test]
+    fn addresses_wrap_at_the_top_of_the_address_space() {
+        let mut pi = Pi::init();
+        write(&mut pi, PI_DRAM_ADDR, 0x00FF_FFFE);
+        write(&mut pi, PI_CART_ADDR, 0xFFFF_FFFE);
+        write(&mut pi, PI_RD_LEN, 7);
+        let dma = std::iter::repeat_with(|| pi.tick()).find_map(|dma| dma).unwrap();
+        assert_eq!((dma.dram_addr, dma.cart_addr, dma.len), (0x00FF_FFFE, 0xFFFF_FFFE, 8));
+
+        pi.complete_dma(dma);
+        assert_eq!(read(&pi, PI_DRAM_ADDR), 0x0000_0006);
+        assert_eq!(read(&pi, PI_CART_ADDR), 0x0000_0006);
+    }
+}
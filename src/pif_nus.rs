@@ -1,12 +1,32 @@
-use cpu_mips3::{core::MipsErr, word::Word};
+use std::io;
+
+use cpu_mips3::{core::MipsErr, vr4300::WriteSize, word::Word};
+
+use crate::{
+    cic::{self, Cic},
+    joybus::{self, JoybusDevice, CHANNELS},
+};
 
 pub struct PifNus {
     rom: Vec<Word>,
+    ram: [u8; PIF_RAM_BYTES],
+    channels: [Option<Box<dyn JoybusDevice>>; CHANNELS],
+    /// Set once the CPU has asked for the joybus commands in RAM to be run on the next read.
+    joybus_pending: bool,
+    /// Set by the boot code once it has copied itself to IMEM, after which the ROM reads as zero.
+    rom_locked: bool,
 }
 impl PifNus {
+    /// The PIF holds the boot code in a loop until it has read the seed from the CIC, so without a cartridge it never boots.
     pub fn init() -> Self {
+        let mut ram = [0; PIF_RAM_BYTES];
+        ram[COMMAND] = STATUS_BUSY;
         Self {
             rom: Self::preprocess_rom(),
+            ram,
+            channels: Default::default(),
+            joybus_pending: false,
+            rom_locked: false,
         }
     }
     fn preprocess_rom() -> Vec<Word> {
@@ -20,21 +40,209 @@ impl PifNus {
 
     pub fn read_word_for_cpu(&self, addr: u32) -> Result<Option<Word>, MipsErr> {
         match addr {
+            PIF_ROM_FIRST..=PIF_ROM_LAST if self.rom_locked => Ok(Some(Word::zero())),
             PIF_ROM_FIRST..=PIF_ROM_LAST => Ok(Some(self.rom[(addr - PIF_ROM_FIRST) as usize / 4])),
-            PIF_RAM_FIRST..=PIF_RAM_LAST => Err(MipsErr::new("reading from PIFNUS ram is not implemented")),
+            PIF_RAM_FIRST..=PIF_RAM_LAST => {
+                let offset = (addr - PIF_RAM_FIRST) as usize & !3;
+                let bytes = &self.ram[offset..offset + 4];
+                Ok(Some(Word([bytes[0], bytes[1], bytes[2], bytes[3]])))
+            }
             _ => Ok(None)
         }
     }
+    pub fn write_word_for_cpu(&mut self, addr: u32, size: WriteSize, data: Word) -> Result<bool, MipsErr> {
+        match addr {
+            // The ROM simply ignores writes
+            PIF_ROM_FIRST..=PIF_ROM_LAST => Ok(true),
+            PIF_RAM_FIRST..=PIF_RAM_LAST => {
+                let offset = (addr - PIF_RAM_FIRST) as usize;
+                let lane = offset % 4;
+                for i in 0..size.bytes() as usize {
+                    self.ram[offset + i] = data.0[lane + i];
+                }
+                self.process_commands();
+                Ok(true)
+            }
+            _ => Ok(false)
+        }
+    }
+
+    /// Plugs a device into a joybus channel, returning the one that was there before.
+    pub fn attach(&mut self, channel: usize, device: Box<dyn JoybusDevice>) -> Option<Box<dyn JoybusDevice>> {
+        self.channels[channel].replace(device)
+    }
+    pub fn detach(&mut self, channel: usize) -> Option<Box<dyn JoybusDevice>> {
+        self.channels[channel].take()
+    }
+
+    /// Writes the save data of all attached devices to their files.
+    pub fn flush(&mut self) -> io::Result<()> {
+        for device in self.channels.iter_mut().flatten() {
+            device.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Fills `data` with the bytes a DMA from `addr` transfers.
+    /// Reading PIF RAM runs any pending joybus commands first, so the results are part of the transfer.
+    pub fn read_dma(&mut self, addr: u32, data: &mut [u8]) {
+        if (PIF_RAM_FIRST..=PIF_RAM_LAST).contains(&addr) && self.joybus_pending {
+            self.joybus_pending = false;
+            joybus::run_commands(&mut self.ram[..COMMAND], &mut self.channels);
+        }
+        for (i, byte) in data.iter_mut().enumerate() {
+            let addr = addr + i as u32;
+            *byte = self.read_word_for_cpu(addr).ok().flatten().map_or(0, |word| word.0[addr as usize % 4]);
+        }
+    }
+    /// Writes the bytes of a DMA to PIF RAM, and carries out the commands they request.
+    pub fn write_dma(&mut self, addr: u32, data: &[u8]) {
+        for (i, &byte) in data.iter().enumerate() {
+            let addr = addr + i as u32;
+            if (PIF_RAM_FIRST..=PIF_RAM_LAST).contains(&addr) {
+                self.ram[(addr - PIF_RAM_FIRST) as usize] = byte;
+            }
+        }
+        self.process_commands();
+    }
+
+    /// Places the seed of the cartridge's CIC where the PIF ROM and IPL3 expect it,
+    /// and releases the boot code that waits for it.
+    pub fn set_cic(&mut self, cic: Cic) {
+        self.ram[CIC_SEED..CIC_SEED + 4].copy_from_slice(&cic.pif_ram_seed().to_be_bytes());
+        self.ram[COMMAND] &= !STATUS_BUSY;
+    }
+
+    /// Carries out the commands the CPU requested through the last byte of PIF RAM.
+    fn process_commands(&mut self) {
+        if self.ram[COMMAND] & COMMAND_JOYBUS != 0 {
+            self.ram[COMMAND] &= !COMMAND_JOYBUS;
+            self.joybus_pending = true;
+        }
+        if self.ram[COMMAND] & COMMAND_CIC_CHALLENGE != 0 {
+            self.cic_challenge();
+        }
+        // Without it, the real PIF would halt the console a few seconds after boot
+        if self.ram[COMMAND] & COMMAND_TERMINATE_BOOT != 0 {
+            self.ram[COMMAND] &= !COMMAND_TERMINATE_BOOT;
+        }
+        if self.ram[COMMAND] & COMMAND_LOCK_ROM != 0 {
+            self.ram[COMMAND] &= !COMMAND_LOCK_ROM;
+            self.rom_locked = true;
+        }
+        // The CIC would verify the checksum IPL3 left at 0x30, but a mismatch only makes it halt the console
+        if self.ram[COMMAND] & COMMAND_CHECKSUM != 0 {
+            self.ram[COMMAND] &= !COMMAND_CHECKSUM;
+            self.ram[COMMAND] |= STATUS_CHECKSUM_OK;
+        }
+        if self.ram[COMMAND] & COMMAND_CLEAR_RAM != 0 {
+            self.ram = [0; PIF_RAM_BYTES];
+        }
+    }
+    /// Answers the challenge the IPL3 of the 6105 and 7105 sends to the CIC.
+    /// The challenge and the response both take up the 15 bytes before the command byte, two nibbles per byte.
+    fn cic_challenge(&mut self) {
+        let challenge: Vec<u8> = self.ram[CIC_CHALLENGE..COMMAND].iter().flat_map(|&b| [b >> 4, b & 0xF]).collect();
+        let response = cic::cic_6105_response(&challenge);
+
+        self.ram[CIC_CHALLENGE - 2] = 0;
+        self.ram[CIC_CHALLENGE - 1] = 0;
+        for (byte, nibbles) in self.ram[CIC_CHALLENGE..COMMAND].iter_mut().zip(response.chunks(2)) {
+            *byte = nibbles[0] << 4 | nibbles[1];
+        }
+        self.ram[COMMAND] = 0;
+    }
 }
 
+pub const PIF_FIRST: u32 = PIF_ROM_FIRST;
+pub const PIF_LAST: u32 = PIF_RAM_LAST;
+
 const PIF_ROM_FIRST: u32 = 0x1FC00000;
 const PIF_ROM_LAST: u32 = 0x1FC007BF;
 
-const PIF_RAM_FIRST: u32 = 0x1FC007C0;
+pub const PIF_RAM_FIRST: u32 = 0x1FC007C0;
 const PIF_RAM_LAST: u32 = 0x1FC007FF;
 
-const PIF_RAM_BYTES: usize = 64;
+pub const PIF_RAM_BYTES: usize = 64;
+
+/// Offsets into PIF RAM.
+const CIC_SEED: usize = 0x24;
+const CIC_CHALLENGE: usize = 0x30;
+const COMMAND: usize = 0x3F;
+
+const COMMAND_JOYBUS: u8 = 0x01;
+const COMMAND_CIC_CHALLENGE: u8 = 0x02;
+const COMMAND_TERMINATE_BOOT: u8 = 0x08;
+const COMMAND_LOCK_ROM: u8 = 0x10;
+const COMMAND_CHECKSUM: u8 = 0x20;
+const COMMAND_CLEAR_RAM: u8 = 0x40;
+/// Set by the PIF until the CIC seed is in place, and again once the boot checksum has been accepted.
+const STATUS_BUSY: u8 = 0x80;
+const STATUS_CHECKSUM_OK: u8 = 0x80;
 
 const PIF_ROM_BYTES: usize = 1984;
 const PIF_ROM: &[u8; PIF_ROM_BYTES] = include_bytes!("pifrom.NTSC.bin");
 const PIF_ROM_WORDS: usize = PIF_ROM_BYTES / 4;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bus::UnmappedPolicy, cartridge::Cartridge, console::Console};
+
+    /// The PIF ROM copies the rest of itself to IMEM, so its boot loop runs from there.
+    const SEED_LOOP_END: u64 = 0xFFFF_FFFF_A400_1010;
+    const IPL3_ENTRY: u64 = 0xFFFF_FFFF_A400_0040;
+
+    fn console_with_cartridge() -> Console {
+        let mut rom = vec![0; 0x1000];
+        rom[..4].copy_from_slice(&[0x80, 0x37, 0x12, 0x40]);
+        let mut console = Console::init();
+        console.insert_cartridge(Cartridge::from_bytes(rom).unwrap()).unwrap();
+        // The video interface is not emulated yet
+        console.bus.unmapped_policy = UnmappedPolicy::OpenBus;
+        console
+    }
+    /// Steps until the CPU reaches `pc`, and returns whether it did within `steps` instructions.
+    fn run_until(console: &mut Console, pc: u64, steps: usize) -> bool {
+        for _ in 0..steps {
+            if console.cpu.program_counter() == pc {
+                return true;
+            }
+            console.step().unwrap();
+        }
+        false
+    }
+
+    #[test]
+    fn pif_rom_boots_into_ipl3() {
+        let mut console = console_with_cartridge();
+        assert!(run_until(&mut console, SEED_LOOP_END, 10_000));
+        assert!(run_until(&mut console, IPL3_ENTRY, 1_000_000));
+        assert!(console.bus.pif_nus.rom_locked);
+        assert_eq!(console.bus.pif_nus.ram, [0; PIF_RAM_BYTES]);
+    }
+
+    #[test]
+    fn pif_rom_waits_for_a_cartridge() {
+        let mut console = Console::init();
+        console.bus.unmapped_policy = UnmappedPolicy::OpenBus;
+        assert!(!run_until(&mut console, SEED_LOOP_END + 8, 10_000));
+    }
+
+    #[test]
+    fn boot_commands_are_acknowledged() {
+        let mut pif = PifNus::init();
+        pif.set_cic(Cic::Nus6102);
+        assert_eq!(pif.ram[CIC_SEED..CIC_SEED + 4], [0x00, 0x00, 0x3F, 0x3F]);
+        assert_eq!(pif.ram[COMMAND], 0);
+
+        pif.ram[COMMAND] = COMMAND_LOCK_ROM | COMMAND_CHECKSUM;
+        pif.process_commands();
+        assert_eq!(pif.ram[COMMAND], STATUS_CHECKSUM_OK);
+        assert_eq!(pif.read_word_for_cpu(PIF_ROM_FIRST).unwrap(), Some(Word::zero()));
+
+        pif.ram[COMMAND] = COMMAND_TERMINATE_BOOT;
+        pif.process_commands();
+        assert_eq!(pif.ram[COMMAND], 0);
+    }
+}
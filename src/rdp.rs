@@ -0,0 +1,694 @@
+use crate::rdram::Rdram;
+
+
+/// Receives the commands the RDP reads from its command buffer, in order.
+/// The command processor only decodes them; drawing is entirely up to the renderer.
+pub trait Renderer {
+    /// Executes one command. Texture loads and the color and depth images all live in `rdram`.
+    fn execute(&mut self, command: &Command, rdram: &mut Rdram);
+}
+
+/// A renderer that ignores every command, for running without graphics output.
+pub struct NullRenderer;
+impl Renderer for NullRenderer {
+    fn execute(&mut self, _command: &Command, _rdram: &mut Rdram) {}
+}
+
+/// A decoded RDP command.
+/// Coordinates keep the fixed point formats of the command words; the field docs name them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    NoOp,
+    Triangle(Triangle),
+    TextureRectangle(TextureRectangle),
+    SyncLoad,
+    SyncPipe,
+    SyncTile,
+    /// Waits for all drawing to finish and raises the DP interrupt.
+    SyncFull,
+    SetKeyGb(KeyGb),
+    SetKeyR(KeyR),
+    /// The six coefficients of the YUV to RGB conversion, 9-bit signed.
+    SetConvert([i16; 6]),
+    SetScissor(Scissor),
+    /// The depth and delta depth used when the z source is the primitive.
+    SetPrimDepth { z: u16, delta_z: u16 },
+    SetOtherModes(OtherModes),
+    LoadTlut(TileRect),
+    SetTileSize(TileRect),
+    LoadBlock(LoadBlock),
+    LoadTile(TileRect),
+    SetTile(Tile),
+    FillRectangle(Rect),
+    /// The raw fill value, which holds two pixels in 16-bit color images.
+    SetFillColor(u32),
+    SetFogColor(Color),
+    SetBlendColor(Color),
+    SetPrimColor { min_level: u8, level_fraction: u8, color: Color },
+    SetEnvColor(Color),
+    SetCombineMode(CombineMode),
+    SetTextureImage(Image),
+    SetZImage { addr: u32 },
+    SetColorImage(Image),
+    /// A command id the RDP does not assign, which it skips like a no-op.
+    Invalid(u8),
+}
+impl Command {
+    /// The number of 64-bit words the command starting with `first` occupies.
+    pub fn words(first: u64) -> usize {
+        match command_id(first) {
+            id @ TRIANGLE_FIRST..=TRIANGLE_LAST => {
+                let mut words = 4;
+                if id & TRIANGLE_SHADE != 0 {
+                    words += 8;
+                }
+                if id & TRIANGLE_TEXTURE != 0 {
+                    words += 8;
+                }
+                if id & TRIANGLE_Z != 0 {
+                    words += 2;
+                }
+                words
+            }
+            ID_TEXTURE_RECTANGLE | ID_TEXTURE_RECTANGLE_FLIP => 2,
+            _ => 1,
+        }
+    }
+
+    /// Decodes a command from all of its words, as counted by [Command::words].
+    pub fn parse(words: &[u64]) -> Self {
+        let w = words[0];
+        match command_id(w) {
+            ID_NO_OP => Self::NoOp,
+            TRIANGLE_FIRST..=TRIANGLE_LAST => Self::Triangle(Triangle::parse(words)),
+            ID_TEXTURE_RECTANGLE => Self::TextureRectangle(TextureRectangle::parse(words, false)),
+            ID_TEXTURE_RECTANGLE_FLIP => Self::TextureRectangle(TextureRectangle::parse(words, true)),
+            ID_SYNC_LOAD => Self::SyncLoad,
+            ID_SYNC_PIPE => Self::SyncPipe,
+            ID_SYNC_TILE => Self::SyncTile,
+            ID_SYNC_FULL => Self::SyncFull,
+            ID_SET_KEY_GB => Self::SetKeyGb(KeyGb {
+                width_g: bits(w, 44, 12) as u16,
+                width_b: bits(w, 32, 12) as u16,
+                center_g: bits(w, 24, 8) as u8,
+                scale_g: bits(w, 16, 8) as u8,
+                center_b: bits(w, 8, 8) as u8,
+                scale_b: bits(w, 0, 8) as u8,
+            }),
+            ID_SET_KEY_R => Self::SetKeyR(KeyR {
+                width_r: bits(w, 16, 12) as u16,
+                center_r: bits(w, 8, 8) as u8,
+                scale_r: bits(w, 0, 8) as u8,
+            }),
+            ID_SET_CONVERT => Self::SetConvert([45, 36, 27, 18, 9, 0].map(|shift| signed(w, shift, 9) as i16)),
+            ID_SET_SCISSOR => Self::SetScissor(Scissor {
+                xh: bits(w, 44, 12) as u16,
+                yh: bits(w, 32, 12) as u16,
+                field: bits(w, 25, 1) != 0,
+                odd: bits(w, 24, 1) != 0,
+                xl: bits(w, 12, 12) as u16,
+                yl: bits(w, 0, 12) as u16,
+            }),
+            ID_SET_PRIM_DEPTH => Self::SetPrimDepth {
+                z: bits(w, 16, 16) as u16,
+                delta_z: bits(w, 0, 16) as u16,
+            },
+            ID_SET_OTHER_MODES => Self::SetOtherModes(OtherModes::parse(w)),
+            ID_LOAD_TLUT => Self::LoadTlut(TileRect::parse(w)),
+            ID_SET_TILE_SIZE => Self::SetTileSize(TileRect::parse(w)),
+            ID_LOAD_BLOCK => Self::LoadBlock(LoadBlock {
+                sl: bits(w, 44, 12) as u16,
+                tl: bits(w, 32, 12) as u16,
+                tile: bits(w, 24, 3) as u8,
+                sh: bits(w, 12, 12) as u16,
+                dxt: bits(w, 0, 12) as u16,
+            }),
+            ID_LOAD_TILE => Self::LoadTile(TileRect::parse(w)),
+            ID_SET_TILE => Self::SetTile(Tile::parse(w)),
+            ID_FILL_RECTANGLE => Self::FillRectangle(Rect::parse(w)),
+            ID_SET_FILL_COLOR => Self::SetFillColor(w as u32),
+            ID_SET_FOG_COLOR => Self::SetFogColor(Color::parse(w)),
+            ID_SET_BLEND_COLOR => Self::SetBlendColor(Color::parse(w)),
+            ID_SET_PRIM_COLOR => Self::SetPrimColor {
+                min_level: bits(w, 40, 5) as u8,
+                level_fraction: bits(w, 32, 8) as u8,
+                color: Color::parse(w),
+            },
+            ID_SET_ENV_COLOR => Self::SetEnvColor(Color::parse(w)),
+            ID_SET_COMBINE_MODE => Self::SetCombineMode(CombineMode::parse(w)),
+            ID_SET_TEXTURE_IMAGE => Self::SetTextureImage(Image::parse(w)),
+            ID_SET_Z_IMAGE => Self::SetZImage { addr: bits(w, 0, 26) as u32 },
+            ID_SET_COLOR_IMAGE => Self::SetColorImage(Image::parse(w)),
+            id => Self::Invalid(id),
+        }
+    }
+}
+
+/// A triangle, described by its three edges and the optional attribute gradients.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Triangle {
+    /// Whether the major edge H is on the right, so the triangle is drawn from right to left.
+    pub right_major: bool,
+    pub level: u8,
+    pub tile: u8,
+    /// The y coordinates of the low, middle and high vertices, signed 11.2 fixed point.
+    pub yl: i16,
+    pub ym: i16,
+    pub yh: i16,
+    /// The x coordinates where the edges start and their slopes, signed 15.16 fixed point.
+    /// Edge H runs from the high to the low vertex, edge M from the high to the middle one and edge L on from there.
+    pub xl: i32,
+    pub dxldy: i32,
+    pub xh: i32,
+    pub dxhdy: i32,
+    pub xm: i32,
+    pub dxmdy: i32,
+    /// Red, green, blue and alpha.
+    pub shade: Option<Coefficients>,
+    /// S, T and W, followed by an unused fourth attribute.
+    pub texture: Option<Coefficients>,
+    pub z: Option<ZCoefficients>,
+}
+impl Triangle {
+    fn parse(words: &[u64]) -> Self {
+        let w = words[0];
+        let id = command_id(w);
+        let edge = |word: u64| ((word >> 32) as i32, word as i32);
+        let (xl, dxldy) = edge(words[1]);
+        let (xh, dxhdy) = edge(words[2]);
+        let (xm, dxmdy) = edge(words[3]);
+
+        let mut rest = &words[4..];
+        let mut attributes = |present: bool, words: usize| {
+            present.then(|| {
+                let (taken, remaining) = rest.split_at(words);
+                rest = remaining;
+                taken
+            })
+        };
+        let shade = attributes(id & TRIANGLE_SHADE != 0, 8).map(Coefficients::parse);
+        let texture = attributes(id & TRIANGLE_TEXTURE != 0, 8).map(Coefficients::parse);
+        let z = attributes(id & TRIANGLE_Z != 0, 2).map(ZCoefficients::parse);
+
+        Self {
+            right_major: bits(w, 55, 1) != 0,
+            level: bits(w, 51, 3) as u8,
+            tile: bits(w, 48, 3) as u8,
+            yl: signed(w, 32, 14) as i16,
+            ym: signed(w, 16, 14) as i16,
+            yh: signed(w, 0, 14) as i16,
+            xl,
+            dxldy,
+            xh,
+            dxhdy,
+            xm,
+            dxmdy,
+            shade,
+            texture,
+            z,
+        }
+    }
+}
+
+/// Four attributes with their gradients along x, along the major edge and along y, all signed 15.16 fixed point.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Coefficients {
+    pub value: [i32; 4],
+    pub dx: [i32; 4],
+    pub de: [i32; 4],
+    pub dy: [i32; 4],
+}
+impl Coefficients {
+    /// The integer halves of each group come in one word, the fractions in another.
+    fn parse(words: &[u64]) -> Self {
+        let combine = |integers: u64, fractions: u64| {
+            [48, 32, 16, 0].map(|shift| (bits(integers, shift, 16) << 16 | bits(fractions, shift, 16)) as i32)
+        };
+        Self {
+            value: combine(words[0], words[2]),
+            dx: combine(words[1], words[3]),
+            de: combine(words[4], words[6]),
+            dy: combine(words[5], words[7]),
+        }
+    }
+}
+
+/// The depth at the start of the major edge and its gradients, signed 15.16 fixed point.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZCoefficients {
+    pub z: i32,
+    pub dzdx: i32,
+    pub dzde: i32,
+    pub dzdy: i32,
+}
+impl ZCoefficients {
+    fn parse(words: &[u64]) -> Self {
+        Self {
+            z: (words[0] >> 32) as i32,
+            dzdx: words[0] as i32,
+            dzde: (words[1] >> 32) as i32,
+            dzdy: words[1] as i32,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextureRectangle {
+    /// Whether S and T are swapped, as in Texture Rectangle Flip.
+    pub flip: bool,
+    pub tile: u8,
+    /// The corners, unsigned 10.2 fixed point.
+    pub rect: Rect,
+    /// The texture coordinates at the top left corner, signed 10.5 fixed point.
+    pub s: i16,
+    pub t: i16,
+    /// Their increments per pixel, signed 5.10 fixed point.
+    pub dsdx: i16,
+    pub dtdy: i16,
+}
+impl TextureRectangle {
+    fn parse(words: &[u64], flip: bool) -> Self {
+        let (w, coordinates) = (words[0], words[1]);
+        Self {
+            flip,
+            tile: bits(w, 24, 3) as u8,
+            rect: Rect::parse(w),
+            s: bits(coordinates, 48, 16) as i16,
+            t: bits(coordinates, 32, 16) as i16,
+            dsdx: bits(coordinates, 16, 16) as i16,
+            dtdy: bits(coordinates, 0, 16) as i16,
+        }
+    }
+}
+
+/// A screen rectangle with corners in unsigned 10.2 fixed point; the low corner is the bottom right one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub xl: u16,
+    pub yl: u16,
+    pub xh: u16,
+    pub yh: u16,
+}
+impl Rect {
+    fn parse(w: u64) -> Self {
+        Self {
+            xl: bits(w, 44, 12) as u16,
+            yl: bits(w, 32, 12) as u16,
+            xh: bits(w, 12, 12) as u16,
+            yh: bits(w, 0, 12) as u16,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Scissor {
+    pub xh: u16,
+    pub yh: u16,
+    /// Whether only one field of an interlaced image is drawn, and if so whether it is the odd one.
+    pub field: bool,
+    pub odd: bool,
+    pub xl: u16,
+    pub yl: u16,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KeyGb {
+    pub width_g: u16,
+    pub width_b: u16,
+    pub center_g: u8,
+    pub scale_g: u8,
+    pub center_b: u8,
+    pub scale_b: u8,
+}
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KeyR {
+    pub width_r: u16,
+    pub center_r: u8,
+    pub scale_r: u8,
+}
+
+/// The texture coordinates of a tile, unsigned 10.2 fixed point, as used by Set Tile Size, Load Tile and Load TLUT.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TileRect {
+    pub tile: u8,
+    pub sl: u16,
+    pub tl: u16,
+    pub sh: u16,
+    pub th: u16,
+}
+impl TileRect {
+    fn parse(w: u64) -> Self {
+        Self {
+            tile: bits(w, 24, 3) as u8,
+            sl: bits(w, 44, 12) as u16,
+            tl: bits(w, 32, 12) as u16,
+            sh: bits(w, 12, 12) as u16,
+            th: bits(w, 0, 12) as u16,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LoadBlock {
+    pub tile: u8,
+    pub sl: u16,
+    pub tl: u16,
+    /// The last texel to load.
+    pub sh: u16,
+    /// The increment of T per 64-bit word, unsigned 1.11 fixed point.
+    pub dxt: u16,
+}
+
+/// The description of one of the eight tiles in TMEM.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub format: u8,
+    pub size: u8,
+    /// The length of a row in 64-bit words.
+    pub line: u16,
+    /// The start of the tile in TMEM, in 64-bit words.
+    pub tmem_addr: u16,
+    pub tile: u8,
+    pub palette: u8,
+    pub clamp_t: bool,
+    pub mirror_t: bool,
+    pub mask_t: u8,
+    pub shift_t: u8,
+    pub clamp_s: bool,
+    pub mirror_s: bool,
+    pub mask_s: u8,
+    pub shift_s: u8,
+}
+impl Tile {
+    fn parse(w: u64) -> Self {
+        Self {
+            format: bits(w, 53, 3) as u8,
+            size: bits(w, 51, 2) as u8,
+            line: bits(w, 41, 9) as u16,
+            tmem_addr: bits(w, 32, 9) as u16,
+            tile: bits(w, 24, 3) as u8,
+            palette: bits(w, 20, 4) as u8,
+            clamp_t: bits(w, 19, 1) != 0,
+            mirror_t: bits(w, 18, 1) != 0,
+            mask_t: bits(w, 14, 4) as u8,
+            shift_t: bits(w, 10, 4) as u8,
+            clamp_s: bits(w, 9, 1) != 0,
+            mirror_s: bits(w, 8, 1) != 0,
+            mask_s: bits(w, 4, 4) as u8,
+            shift_s: bits(w, 0, 4) as u8,
+        }
+    }
+}
+
+/// An image in RDRAM, as set by Set Texture Image and Set Color Image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub format: u8,
+    pub size: u8,
+    /// The width in pixels.
+    pub width: u16,
+    pub addr: u32,
+}
+impl Image {
+    fn parse(w: u64) -> Self {
+        Self {
+            format: bits(w, 53, 3) as u8,
+            size: bits(w, 51, 2) as u8,
+            width: bits(w, 32, 10) as u16 + 1,
+            addr: bits(w, 0, 26) as u32,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+impl Color {
+    fn parse(w: u64) -> Self {
+        Self {
+            r: bits(w, 24, 8) as u8,
+            g: bits(w, 16, 8) as u8,
+            b: bits(w, 8, 8) as u8,
+            a: bits(w, 0, 8) as u8,
+        }
+    }
+}
+
+/// The inputs of the color combiner's equation (a - b) * c + d, for each of the two cycles.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CombineMode {
+    pub rgb: [CombinerInputs; 2],
+    pub alpha: [CombinerInputs; 2],
+}
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CombinerInputs {
+    pub sub_a: u8,
+    pub sub_b: u8,
+    pub mul: u8,
+    pub add: u8,
+}
+impl CombineMode {
+    fn parse(w: u64) -> Self {
+        let inputs = |sub_a: (u32, u32), sub_b: (u32, u32), mul: (u32, u32), add: (u32, u32)| CombinerInputs {
+            sub_a: bits(w, sub_a.0, sub_a.1) as u8,
+            sub_b: bits(w, sub_b.0, sub_b.1) as u8,
+            mul: bits(w, mul.0, mul.1) as u8,
+            add: bits(w, add.0, add.1) as u8,
+        };
+        Self {
+            rgb: [
+                inputs((52, 4), (28, 4), (47, 5), (15, 3)),
+                inputs((37, 4), (24, 4), (32, 5), (6, 3)),
+            ],
+            alpha: [
+                inputs((44, 3), (12, 3), (41, 3), (9, 3)),
+                inputs((21, 3), (3, 3), (18, 3), (0, 3)),
+            ],
+        }
+    }
+}
+
+/// The rendering modes of Set Other Modes. The blender inputs are indexed by cycle.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OtherModes {
+    pub atomic_prim: bool,
+    pub cycle_type: u8,
+    pub persp_tex_en: bool,
+    pub detail_tex_en: bool,
+    pub sharpen_tex_en: bool,
+    pub tex_lod_en: bool,
+    pub en_tlut: bool,
+    pub tlut_type: bool,
+    pub sample_type: bool,
+    pub mid_texel: bool,
+    pub bi_lerp: [bool; 2],
+    pub convert_one: bool,
+    pub key_en: bool,
+    pub rgb_dither_sel: u8,
+    pub alpha_dither_sel: u8,
+    pub blend_m1a: [u8; 2],
+    pub blend_m1b: [u8; 2],
+    pub blend_m2a: [u8; 2],
+    pub blend_m2b: [u8; 2],
+    pub force_blend: bool,
+    pub alpha_cvg_select: bool,
+    pub cvg_times_alpha: bool,
+    pub z_mode: u8,
+    pub cvg_dest: u8,
+    pub color_on_cvg: bool,
+    pub image_read_en: bool,
+    pub z_update_en: bool,
+    pub z_compare_en: bool,
+    pub antialias_en: bool,
+    pub z_source_sel: bool,
+    pub dither_alpha_en: bool,
+    pub alpha_compare_en: bool,
+}
+impl OtherModes {
+    fn parse(w: u64) -> Self {
+        let flag = |shift| bits(w, shift, 1) != 0;
+        let field = |shift, width| bits(w, shift, width) as u8;
+        Self {
+            atomic_prim: flag(55),
+            cycle_type: field(52, 2),
+            persp_tex_en: flag(51),
+            detail_tex_en: flag(50),
+            sharpen_tex_en: flag(49),
+            tex_lod_en: flag(48),
+            en_tlut: flag(47),
+            tlut_type: flag(46),
+            sample_type: flag(45),
+            mid_texel: flag(44),
+            bi_lerp: [flag(43), flag(42)],
+            convert_one: flag(41),
+            key_en: flag(40),
+            rgb_dither_sel: field(38, 2),
+            alpha_dither_sel: field(36, 2),
+            blend_m1a: [field(30, 2), field(28, 2)],
+            blend_m1b: [field(26, 2), field(24, 2)],
+            blend_m2a: [field(22, 2), field(20, 2)],
+            blend_m2b: [field(18, 2), field(16, 2)],
+            force_blend: flag(14),
+            alpha_cvg_select: flag(13),
+            cvg_times_alpha: flag(12),
+            z_mode: field(10, 2),
+            cvg_dest: field(8, 2),
+            color_on_cvg: flag(7),
+            image_read_en: flag(6),
+            z_update_en: flag(5),
+            z_compare_en: flag(4),
+            antialias_en: flag(3),
+            z_source_sel: flag(2),
+            dither_alpha_en: flag(1),
+            alpha_compare_en: flag(0),
+        }
+    }
+}
+
+fn command_id(first: u64) -> u8 {
+    bits(first, 56, 6) as u8
+}
+fn bits(word: u64, shift: u32, width: u32) -> u64 {
+    word >> shift & ((1 << width) - 1)
+}
+fn signed(word: u64, shift: u32, width: u32) -> i64 {
+    ((word >> shift) << (64 - width)) as i64 >> (64 - width)
+}
+
+const ID_NO_OP: u8 = 0x00;
+const TRIANGLE_FIRST: u8 = 0x08;
+const TRIANGLE_LAST: u8 = 0x0F;
+const TRIANGLE_SHADE: u8 = 0x04;
+const TRIANGLE_TEXTURE: u8 = 0x02;
+const TRIANGLE_Z: u8 = 0x01;
+const ID_TEXTURE_RECTANGLE: u8 = 0x24;
+const ID_TEXTURE_RECTANGLE_FLIP: u8 = 0x25;
+const ID_SYNC_LOAD: u8 = 0x26;
+const ID_SYNC_PIPE: u8 = 0x27;
+const ID_SYNC_TILE: u8 = 0x28;
+const ID_SYNC_FULL: u8 = 0x29;
+const ID_SET_KEY_GB: u8 = 0x2A;
+const ID_SET_KEY_R: u8 = 0x2B;
+const ID_SET_CONVERT: u8 = 0x2C;
+const ID_SET_SCISSOR: u8 = 0x2D;
+const ID_SET_PRIM_DEPTH: u8 = 0x2E;
+const ID_SET_OTHER_MODES: u8 = 0x2F;
+const ID_LOAD_TLUT: u8 = 0x30;
+const ID_SET_TILE_SIZE: u8 = 0x32;
+const ID_LOAD_BLOCK: u8 = 0x33;
+const ID_LOAD_TILE: u8 = 0x34;
+const ID_SET_TILE: u8 = 0x35;
+const ID_FILL_RECTANGLE: u8 = 0x36;
+const ID_SET_FILL_COLOR: u8 = 0x37;
+const ID_SET_FOG_COLOR: u8 = 0x38;
+const ID_SET_BLEND_COLOR: u8 = 0x39;
+const ID_SET_PRIM_COLOR: u8 = 0x3A;
+const ID_SET_ENV_COLOR: u8 = 0x3B;
+const ID_SET_COMBINE_MODE: u8 = 0x3C;
+const ID_SET_TEXTURE_IMAGE: u8 = 0x3D;
+const ID_SET_Z_IMAGE: u8 = 0x3E;
+const ID_SET_COLOR_IMAGE: u8 = 0x3F;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triangles_count_their_attribute_words() {
+        let words = |id: u64| Command::words(id << 56);
+        assert_eq!(words(0x08), 4);
+        assert_eq!(words(0x09), 6);
+        assert_eq!(words(0x0A), 12);
+        assert_eq!(words(0x0C), 12);
+        assert_eq!(words(0x0E), 20);
+        assert_eq!(words(0x0F), 22);
+        assert_eq!(words(0x24), 2);
+        assert_eq!(words(0x29), 1);
+    }
+
+    #[test]
+    fn triangle_attributes_follow_the_edges_in_order() {
+        let mut words = vec![0x0F << 56 | 1 << 55 | 3 << 48 | 0x3FFF << 32 | 0x0040 << 16 | 0x0020];
+        // Every 16 bits of word i hold i
+        words.extend((1..22).map(|i| i * 0x0001_0001_0001_0001));
+        let Command::Triangle(triangle) = Command::parse(&words) else { panic!("not a triangle") };
+        let both = |integer: i32, fraction: i32| integer << 16 | fraction;
+
+        assert!(triangle.right_major);
+        assert_eq!((triangle.tile, triangle.yl, triangle.ym, triangle.yh), (3, -1, 0x40, 0x20));
+        assert_eq!((triangle.xl, triangle.xh, triangle.dxmdy), (both(1, 1), both(2, 2), both(3, 3)));
+        let shade = triangle.shade.unwrap();
+        assert_eq!(shade.value, [both(4, 6); 4]);
+        assert_eq!(shade.dx, [both(5, 7); 4]);
+        assert_eq!(shade.de, [both(8, 10); 4]);
+        assert_eq!(shade.dy, [both(9, 11); 4]);
+        assert_eq!(triangle.texture.unwrap().value, [both(12, 14); 4]);
+        assert_eq!(triangle.z, Some(ZCoefficients { z: both(20, 20), dzdx: both(20, 20), dzde: both(21, 21), dzdy: both(21, 21) }));
+    }
+
+    #[test]
+    fn decodes_combine_mode() {
+        // G_CC_SHADE in both cycles: (0 - 0) * 0 + shade
+        let shade = |zero| CombinerInputs { sub_a: zero, sub_b: zero, mul: if zero == 15 { 31 } else { 7 }, add: 4 };
+        assert_eq!(
+            Command::parse(&[0xFCFF_FFFF_FFFE_793C]),
+            Command::SetCombineMode(CombineMode {
+                rgb: [shade(15), shade(15)],
+                alpha: [shade(7), shade(7)],
+            })
+        );
+    }
+
+    #[test]
+    fn decodes_other_modes() {
+        // Perspective correct bilinear filtering with G_RM_AA_ZB_OPA_SURF in both cycles
+        let Command::SetOtherModes(modes) = Command::parse(&[0xEF08_2C00_0055_2078]) else { panic!("not Set Other Modes") };
+        assert_eq!(
+            modes,
+            OtherModes {
+                atomic_prim: false,
+                cycle_type: 0,
+                persp_tex_en: true,
+                detail_tex_en: false,
+                sharpen_tex_en: false,
+                tex_lod_en: false,
+                en_tlut: false,
+                tlut_type: false,
+                sample_type: true,
+                mid_texel: false,
+                bi_lerp: [true, true],
+                convert_one: false,
+                key_en: false,
+                rgb_dither_sel: 0,
+                alpha_dither_sel: 0,
+                blend_m1a: [0, 0],
+                blend_m1b: [0, 0],
+                blend_m2a: [1, 1],
+                blend_m2b: [1, 1],
+                force_blend: false,
+                alpha_cvg_select: true,
+                cvg_times_alpha: false,
+                z_mode: 0,
+                cvg_dest: 0,
+                color_on_cvg: false,
+                image_read_en: true,
+                z_update_en: true,
+                z_compare_en: true,
+                antialias_en: true,
+                z_source_sel: false,
+                dither_alpha_en: false,
+                alpha_compare_en: false,
+            }
+        );
+    }
+
+    #[test]
+    fn convert_coefficients_are_signed() {
+        // The default YUV to RGB conversion of libultra
+        assert_eq!(Command::parse(&[0xEC15_FD5D_3B78_E42A]), Command::SetConvert([175, -43, -89, 222, 114, 42]));
+    }
+}
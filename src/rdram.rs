@@ -0,0 +1,194 @@
+use cpu_mips3::{core::MipsErr, vr4300::WriteSize, word::Word};
+
+
+pub struct Rdram {
+    mem: Vec<Word>,
+    modules: Vec<[u32; MODULE_REGS]>,
+}
+impl Rdram {
+    /// Creates the 4 MB of RDRAM the console ships with, or 8 MB if an Expansion Pak is inserted.
+    pub fn init(expansion_pak: bool) -> Self {
+        let bytes = if expansion_pak { RDRAM_BYTES_EXPANDED } else { RDRAM_BYTES };
+        let modules = bytes / MODULE_BYTES;
+        Self {
+            mem: vec![Word::zero(); bytes / 4],
+            modules: vec![Self::module_reset_regs(); modules],
+        }
+    }
+    fn module_reset_regs() -> [u32; MODULE_REGS] {
+        let mut regs = [0; MODULE_REGS];
+        regs[REG_DEVICE_TYPE] = DEVICE_TYPE;
+        regs[REG_DEVICE_MANUF] = DEVICE_MANUF;
+        regs
+    }
+
+    pub fn size(&self) -> usize {
+        self.mem.len() * 4
+    }
+
+    /// Byte access for DMA engines. Bytes beyond the installed memory read as zero and can not be written.
+    pub fn read_byte(&self, addr: u32) -> u8 {
+        self.mem.get(addr as usize / 4).map_or(0, |word| word.0[addr as usize % 4])
+    }
+    pub fn write_byte(&mut self, addr: u32, byte: u8) {
+        if let Some(word) = self.mem.get_mut(addr as usize / 4) {
+            word.0[addr as usize % 4] = byte;
+        }
+    }
+
+    pub fn read_word_for_cpu(&self, addr: u32) -> Result<Option<Word>, MipsErr> {
+        match addr {
+            RDRAM_FIRST..=RDRAM_LAST => Ok(Some(self.mem.get(addr as usize / 4).copied().unwrap_or(Word::zero()))),
+            REGS_FIRST..=REGS_LAST => Ok(Some(Word::from_u32_be(self.read_reg(addr)))),
+            _ => Ok(None),
+        }
+    }
+    pub fn write_word_for_cpu(&mut self, addr: u32, size: WriteSize, data: Word) -> Result<bool, MipsErr> {
+        match addr {
+            RDRAM_FIRST..=RDRAM_LAST => {
+                // Writes beyond the installed memory go nowhere
+                if let Some(word) = self.mem.get_mut(addr as usize / 4) {
+                    word.overwrite(data, (addr % 4) as u8, size.bytes());
+                }
+                Ok(true)
+            }
+            REGS_FIRST..=REGS_LAST => {
+                let mut word = Word::from_u32_be(self.read_reg(addr & !3));
+                word.overwrite(data, (addr % 4) as u8, size.bytes());
+                self.write_reg(addr & !3, word.to_u32_be());
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Registers of modules that do not answer to the address read as zero, and broadcast registers can not be read.
+    fn read_reg(&self, addr: u32) -> u32 {
+        if addr & BROADCAST != 0 {
+            return 0;
+        }
+        let reg = Self::decode_reg(addr);
+        match self.module(addr) {
+            // The current control bits of the mode register read back inverted
+            Some(module) if reg == REG_MODE => self.modules[module][reg] ^ MODE_CC_INVERTED,
+            Some(module) if reg < MODULE_REGS => self.modules[module][reg],
+            _ => 0,
+        }
+    }
+    fn write_reg(&mut self, addr: u32, value: u32) {
+        let reg = Self::decode_reg(addr);
+        let value = match reg {
+            REG_DEVICE_ID => value & DEVICE_ID_MASK,
+            REG_DELAY => value & DELAY_MASK,
+            REG_DEVICE_TYPE | REG_DEVICE_MANUF => return,
+            reg if reg >= MODULE_REGS => return,
+            _ => value,
+        };
+
+        if addr & BROADCAST != 0 {
+            for regs in &mut self.modules {
+                regs[reg] = value;
+            }
+        }
+        else if let Some(module) = self.module(addr) {
+            self.modules[module][reg] = value;
+        }
+    }
+    fn decode_reg(addr: u32) -> usize {
+        (addr as usize & 0x3FF) / 4
+    }
+    /// The module whose programmed device ID answers to the ID field of a register address.
+    /// Every module covers two IDs, one per megabyte, and the first one wins if several were given the same ID.
+    fn module(&self, addr: u32) -> Option<usize> {
+        let id = (addr >> 10) & 0x1FF;
+        self.modules.iter().position(|regs| {
+            let first = device_id(regs[REG_DEVICE_ID]);
+            (first..first + MODULE_IDS).contains(&id)
+        })
+    }
+}
+
+/// Gathers the device ID from the scattered bits of the RDRAM_DEVICE_ID register.
+fn device_id(reg: u32) -> u32 {
+    (reg >> 26 & 0x3F) | (reg >> 23 & 1) << 6 | (reg >> 8 & 0xFF) << 7 | (reg >> 7 & 1) << 15
+}
+
+pub const RDRAM_FIRST: u32 = 0x0000_0000;
+pub const RDRAM_LAST: u32 = 0x03EF_FFFF;
+pub const RDRAM_BYTES: usize = 4 * 1024 * 1024;
+pub const RDRAM_BYTES_EXPANDED: usize = 8 * 1024 * 1024;
+
+pub const REGS_FIRST: u32 = 0x03F0_0000;
+pub const REGS_LAST: u32 = 0x03FF_FFFF;
+
+pub const RDRAM_DEVICE_TYPE: u32 = 0x03F0_0000;
+pub const RDRAM_DEVICE_ID: u32 = 0x03F0_0004;
+pub const RDRAM_DELAY: u32 = 0x03F0_0008;
+pub const RDRAM_MODE: u32 = 0x03F0_000C;
+pub const RDRAM_REF_INTERVAL: u32 = 0x03F0_0010;
+pub const RDRAM_REF_ROW: u32 = 0x03F0_0014;
+pub const RDRAM_RAS_INTERVAL: u32 = 0x03F0_0018;
+pub const RDRAM_MIN_INTERVAL: u32 = 0x03F0_001C;
+pub const RDRAM_ADDR_SELECT: u32 = 0x03F0_0020;
+pub const RDRAM_DEVICE_MANUF: u32 = 0x03F0_0024;
+
+const BROADCAST: u32 = 0x0008_0000;
+const MODULE_BYTES: usize = 2 * 1024 * 1024;
+const MODULE_REGS: usize = 10;
+const MODULE_IDS: u32 = (MODULE_BYTES >> 20) as u32;
+const REG_DEVICE_TYPE: usize = 0;
+const REG_DEVICE_ID: usize = 1;
+const REG_DELAY: usize = 2;
+const REG_MODE: usize = 3;
+const REG_DEVICE_MANUF: usize = 9;
+const DEVICE_TYPE: u32 = 0xB419_0010;
+const DEVICE_MANUF: u32 = 0x0000_0500;
+const DEVICE_ID_MASK: u32 = 0xFC80_FF80;
+/// AckWinDelay, ReadDelay, AckDelay and WriteDelay.
+const DELAY_MASK: u32 = 0x3838_1838;
+const MODE_CC_INVERTED: u32 = 0xC0C0_C0C0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(rdram: &mut Rdram, addr: u32, value: u32) {
+        rdram.write_word_for_cpu(addr, WriteSize::Four, Word::from_u32_be(value)).unwrap();
+    }
+    fn read(rdram: &Rdram, addr: u32) -> u32 {
+        rdram.read_word_for_cpu(addr).unwrap().unwrap().to_u32_be()
+    }
+    fn reg_addr(id: u32, addr: u32) -> u32 {
+        addr | id << 10
+    }
+
+    #[test]
+    fn registers_follow_the_programmed_device_id() {
+        let mut rdram = Rdram::init(true);
+
+        // Move every module out of the way, then give them their final IDs one at a time, like IPL3 does
+        write(&mut rdram, RDRAM_DEVICE_ID | BROADCAST, 0x8000_0000);
+        for module in 0..4 {
+            write(&mut rdram, reg_addr(32, RDRAM_DEVICE_ID), (module * 2) << 26);
+        }
+        for module in 0..4 {
+            write(&mut rdram, reg_addr(module * 2, RDRAM_REF_ROW), module);
+        }
+
+        for module in 0..4 {
+            assert_eq!(read(&rdram, reg_addr(module * 2, RDRAM_DEVICE_ID)), (module * 2) << 26);
+            assert_eq!(read(&rdram, reg_addr(module * 2 + 1, RDRAM_REF_ROW)), module);
+        }
+        assert_eq!(read(&rdram, reg_addr(32, RDRAM_DEVICE_ID)), 0);
+    }
+
+    #[test]
+    fn mode_and_delay_read_back() {
+        let mut rdram = Rdram::init(false);
+        write(&mut rdram, RDRAM_DELAY | BROADCAST, 0xFFFF_FFFF);
+        write(&mut rdram, RDRAM_MODE | BROADCAST, 0xC4C0_C0C0);
+
+        assert_eq!(read(&rdram, RDRAM_DELAY), DELAY_MASK);
+        assert_eq!(read(&rdram, RDRAM_MODE), 0x0400_0000);
+    }
+}
@@ -0,0 +1,69 @@
+use cpu_mips3::{core::MipsErr, vr4300::WriteSize, word::Word};
+
+
+/// The RDRAM Interface, which the boot code configures before sizing memory.
+pub struct Ri {
+    mode: u32,
+    config: u32,
+    select: u32,
+    refresh: u32,
+    latency: u32,
+}
+impl Ri {
+    pub fn init() -> Self {
+        Self {
+            mode: 0,
+            config: 0,
+            select: 0,
+            refresh: 0,
+            latency: 0,
+        }
+    }
+
+    pub fn read_word_for_cpu(&self, addr: u32) -> Result<Option<Word>, MipsErr> {
+        if !(REGS_FIRST..=REGS_LAST).contains(&addr) { return Ok(None) }
+        Ok(Some(Word::from_u32_be(self.read_reg(addr & !3))))
+    }
+    pub fn write_word_for_cpu(&mut self, addr: u32, size: WriteSize, data: Word) -> Result<bool, MipsErr> {
+        if !(REGS_FIRST..=REGS_LAST).contains(&addr) { return Ok(false) }
+        let mut word = Word::from_u32_be(self.read_reg(addr & !3));
+        word.overwrite(data, (addr % 4) as u8, size.bytes());
+        self.write_reg(addr & !3, word.to_u32_be());
+        Ok(true)
+    }
+
+    fn read_reg(&self, addr: u32) -> u32 {
+        match addr & REGS_MASK {
+            RI_MODE => self.mode,
+            RI_CONFIG => self.config,
+            RI_SELECT => self.select,
+            RI_REFRESH => self.refresh,
+            RI_LATENCY => self.latency,
+            // RI_CURRENT_LOAD is write-only, and no transfer errors ever happen
+            _ => 0,
+        }
+    }
+    fn write_reg(&mut self, addr: u32, value: u32) {
+        match addr & REGS_MASK {
+            RI_MODE => self.mode = value & 0xF,
+            RI_CONFIG => self.config = value & 0x7F,
+            RI_SELECT => self.select = value & 0xFF,
+            RI_REFRESH => self.refresh = value & 0x7_FFFF,
+            RI_LATENCY => self.latency = value & 0xF,
+            _ => (),
+        }
+    }
+}
+
+pub const REGS_FIRST: u32 = 0x0470_0000;
+pub const REGS_LAST: u32 = 0x047F_FFFF;
+const REGS_MASK: u32 = 0x0470_001F;
+
+pub const RI_MODE: u32 = 0x0470_0000;
+pub const RI_CONFIG: u32 = 0x0470_0004;
+pub const RI_CURRENT_LOAD: u32 = 0x0470_0008;
+pub const RI_SELECT: u32 = 0x0470_000C;
+pub const RI_REFRESH: u32 = 0x0470_0010;
+pub const RI_LATENCY: u32 = 0x0470_0014;
+pub const RI_RERROR: u32 = 0x0470_0018;
+pub const RI_WERROR: u32 = 0x0470_001C;
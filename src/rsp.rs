@@ -1,28 +1,256 @@
-use cpu_mips3::{core::MipsErr, word::Word};
+use std::collections::VecDeque;
 
+use cpu_mips3::{core::MipsErr, vr4300::WriteSize, word::Word};
 
+use crate::mi::apply_set_clear;
+
+
+/// The register block of the RSP: its status, the semaphore, the program counter and the DMA engine
+/// that moves data between RDRAM and DMEM or IMEM.
 pub struct Rsp {
+    /// The persistent SP_STATUS bits, in their read layout. The DMA bits are derived from the queue.
+    status: u32,
+    semaphore: bool,
+    interrupt: bool,
+    pc: u32,
+    mem_addr: u32,
+    dram_addr: u32,
+    /// The skip field of the last length register write, which reads back after a transfer.
+    skip: u32,
+    /// The running DMA followed by at most one pending one.
+    dma_queue: VecDeque<SpDma>,
+    /// Cycles until the running DMA finishes.
+    dma_cycles: u32,
 }
 impl Rsp {
     pub fn init() -> Self {
         Self {
+            status: STATUS_HALT,
+            semaphore: false,
+            interrupt: false,
+            pc: 0,
+            mem_addr: 0,
+            dram_addr: 0,
+            skip: 0,
+            dma_queue: VecDeque::new(),
+            dma_cycles: 0,
+        }
+    }
+
+    pub fn interrupt(&self) -> bool {
+        self.interrupt
+    }
+    pub fn halted(&self) -> bool {
+        self.status & STATUS_HALT != 0
+    }
+    pub fn single_step(&self) -> bool {
+        self.status & STATUS_SINGLE_STEP != 0
+    }
+    pub fn pc(&self) -> u32 {
+        self.pc
+    }
+    pub fn set_pc(&mut self, pc: u32) {
+        self.pc = pc & PC_MASK;
+    }
+    /// Halts the RSP after an instruction executed in single-step mode.
+    pub fn halt(&mut self) {
+        self.status |= STATUS_HALT;
+    }
+    /// Executing BREAK halts the RSP, sets broke and raises the interrupt if the program asked for it.
+    pub fn on_break(&mut self) {
+        self.status |= STATUS_HALT | STATUS_BROKE;
+        if self.status & STATUS_INTERRUPT_ON_BREAK != 0 {
+            self.interrupt = true;
+        }
+    }
+
+    /// Advances the running DMA by one cycle, and returns it once its time is up.
+    /// The caller copies the data and then hands it back to [Rsp::complete_dma].
+    pub fn tick(&mut self) -> Option<SpDma> {
+        let dma = *self.dma_queue.front()?;
+        self.dma_cycles = self.dma_cycles.saturating_sub(1);
+        (self.dma_cycles == 0).then_some(dma)
+    }
+    /// Finishes the running DMA: the address registers point past the transferred data
+    /// and the pending DMA, if there is one, starts.
+    pub fn complete_dma(&mut self, dma: SpDma) {
+        self.dma_queue.pop_front();
+        self.mem_addr = dma.mem_addr_after();
+        self.dram_addr = dma.dram_addr_after();
+        if let Some(next) = self.dma_queue.front() {
+            self.dma_cycles = next.cycles();
+        }
+    }
 
+    /// Reads a register by its index, as seen from the RSP's COP0.
+    /// Reading the semaphore acquires it.
+    pub fn read_reg(&mut self, reg: u32) -> u32 {
+        let value = self.peek_reg(reg);
+        if reg == REG_SEMAPHORE {
+            self.semaphore = true;
+        }
+        value
+    }
+    /// Writes a register by its index, as seen from the RSP's COP0.
+    pub fn write_reg(&mut self, reg: u32, value: u32) {
+        match reg {
+            REG_DMA_SPADDR => self.mem_addr = value & MEM_ADDR_MASK,
+            REG_DMA_RAMADDR => self.dram_addr = value & DRAM_ADDR_MASK,
+            REG_DMA_RDLEN => self.queue_dma(SpDirection::ToSp, value),
+            REG_DMA_WRLEN => self.queue_dma(SpDirection::ToDram, value),
+            REG_STATUS => self.write_status(value),
+            REG_SEMAPHORE => self.semaphore = false,
+            _ => (),
         }
     }
-    
+
     pub fn read_word_for_cpu(&self, addr: u32) -> Result<Option<Word>, MipsErr> {
         if !(REGS_FIRST..=REGS_LAST).contains(&addr) { return Ok(None) }
-        let reg = (addr - REGS_FIRST) / 4;
+        let value = match addr & PC_REGS_MASK {
+            SP_PC => self.pc,
+            SP_IBIST => 0,
+            _ => self.peek_reg(Self::cpu_reg(addr)),
+        };
+        Ok(Some(Word::from_u32_be(value)))
+    }
+    pub fn write_word_for_cpu(&mut self, addr: u32, _size: WriteSize, data: Word) -> Result<bool, MipsErr> {
+        if !(REGS_FIRST..=REGS_LAST).contains(&addr) { return Ok(false) }
+        let value = data.to_u32_be();
+        match addr & PC_REGS_MASK {
+            SP_PC => self.set_pc(value),
+            SP_IBIST => (),
+            _ => self.write_reg(Self::cpu_reg(addr), value),
+        }
+        Ok(true)
+    }
+    /// Acquires the semaphore if the CPU just read it.
+    /// Called by the bus after reads, since [Rsp::read_word_for_cpu] must not have side effects.
+    pub fn after_cpu_read(&mut self, addr: u32) {
+        // SP_PC and SP_IBIST never decode to the semaphore, so they need no special case
+        if Self::cpu_reg(addr) == REG_SEMAPHORE {
+            self.semaphore = true;
+        }
+    }
+    /// The register a CPU access selects. The eight registers are mirrored through the whole block.
+    fn cpu_reg(addr: u32) -> u32 {
+        (addr & REGS_MASK) / 4 % 8
+    }
+
+    fn peek_reg(&self, reg: u32) -> u32 {
         match reg {
-            4 => Ok(Some(Word::from_u32_le(1))),
-            or => Err(MipsErr::new(format!("reading from RSP register {or} is not implemented"))),
+            REG_DMA_SPADDR => self.mem_addr,
+            REG_DMA_RAMADDR => self.dram_addr,
+            // The lengths count down to -8 during a transfer, which is all that remains once it is done
+            REG_DMA_RDLEN | REG_DMA_WRLEN => self.skip << 20 | LEN_DONE,
+            REG_STATUS => self.read_status(),
+            REG_DMA_FULL => self.dma_full() as u32,
+            REG_DMA_BUSY => self.dma_busy() as u32,
+            REG_SEMAPHORE => self.semaphore as u32,
+            _ => 0,
         }
     }
+
+    fn dma_busy(&self) -> bool {
+        !self.dma_queue.is_empty()
+    }
+    fn dma_full(&self) -> bool {
+        self.dma_queue.len() > 1
+    }
+
+    fn read_status(&self) -> u32 {
+        let mut status = self.status;
+        if self.dma_busy() {
+            status |= STATUS_DMA_BUSY;
+        }
+        if self.dma_full() {
+            status |= STATUS_DMA_FULL | STATUS_IO_FULL;
+        }
+        status
+    }
+    /// Every flag is driven by a pair of clear and set bits, except broke, which can only be cleared.
+    fn write_status(&mut self, value: u32) {
+        let halt = apply_set_clear(self.status & STATUS_HALT, value, 1);
+        let broke = if value & WRITE_CLEAR_BROKE != 0 { 0 } else { self.status & STATUS_BROKE };
+        let single_step = apply_set_clear(self.status >> 5 & 1, value >> 5, 1) << 5;
+        let interrupt_on_break = apply_set_clear(self.status >> 6 & 1, value >> 7, 1) << 6;
+        let signals = apply_set_clear(self.status >> 7 & 0xFF, value >> 9, 8) << 7;
+        self.status = halt | broke | single_step | interrupt_on_break | signals;
+
+        self.interrupt = apply_set_clear(self.interrupt as u32, value >> 3, 1) != 0;
+    }
+
+    /// Queues a DMA described by the current address registers and a length register value.
+    /// Requests while the queue is full are dropped; software is expected to check SP_DMA_FULL first.
+    fn queue_dma(&mut self, direction: SpDirection, value: u32) {
+        if self.dma_full() {
+            return;
+        }
+        self.skip = value >> 20 & 0xFFF;
+        let dma = SpDma {
+            direction,
+            mem_addr: self.mem_addr,
+            dram_addr: self.dram_addr,
+            len: (value & 0xFFF | 7) + 1,
+            count: (value >> 12 & 0xFF) + 1,
+            skip: self.skip,
+        };
+        if self.dma_queue.is_empty() {
+            self.dma_cycles = dma.cycles();
+        }
+        self.dma_queue.push_back(dma);
+    }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SpDirection {
+    ToSp,
+    ToDram,
+}
+
+/// A transfer of `count` rows of `len` bytes each. The RDRAM address skips `skip` bytes after every row,
+/// while the DMEM or IMEM address advances without gaps and wraps around within its memory.
+#[derive(Copy, Clone, Debug)]
+pub struct SpDma {
+    pub direction: SpDirection,
+    /// Bits 0 to 11 address DMEM, or IMEM if bit 12 is set.
+    pub mem_addr: u32,
+    pub dram_addr: u32,
+    pub len: u32,
+    pub count: u32,
+    pub skip: u32,
+}
+impl SpDma {
+    /// The byte addresses of the transfer, as pairs of a DMEM or IMEM address and an RDRAM address.
+    pub fn addresses(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (0..self.count).flat_map(move |row| {
+            (0..self.len).map(move |i| {
+                let offset = row * self.len + i;
+                let mem = self.mem_addr & MEM_SELECT_IMEM | (self.mem_addr + offset) & MEM_OFFSET_MASK;
+                let dram = (self.dram_addr + row * (self.len + self.skip) + i) & DRAM_BYTE_MASK;
+                (mem, dram)
+            })
+        })
+    }
 
+    fn mem_addr_after(&self) -> u32 {
+        self.mem_addr & MEM_SELECT_IMEM | (self.mem_addr + self.len * self.count) & MEM_OFFSET_MASK
+    }
+    fn dram_addr_after(&self) -> u32 {
+        (self.dram_addr + (self.len + self.skip) * self.count) & DRAM_ADDR_MASK
+    }
+    /// RDRAM moves about eight bytes per CPU cycle, plus some setup for every row.
+    fn cycles(&self) -> u32 {
+        (self.len / 8 + DMA_ROW_SETUP_CYCLES) * self.count
+    }
+}
+
+/// DMEM and IMEM, with their mirrors.
+pub const MEM_FIRST: u32 = 0x04000000;
+pub const MEM_LAST: u32 = 0x0403FFFF;
 pub const REGS_FIRST: u32 = 0x04040000;
 pub const REGS_LAST: u32 = 0x040BFFFF;
+const REGS_MASK: u32 = 0x0404_001F;
+const PC_REGS_MASK: u32 = 0x040C_001F;
 
 pub const SP_DMA_SPADDR: u32 = 0x0404_0000;
 pub const SP_DMA_RAMADDR: u32 = 0x0404_0004;
@@ -33,3 +261,53 @@ pub const SP_DMA_FULL: u32 = 0x0404_0014;
 pub const SP_DMA_BUSY: u32 = 0x0404_0018;
 pub const SP_SEMAPHORE: u32 = 0x0404_001C;
 pub const SP_PC: u32 = 0x0408_0000;
+pub const SP_IBIST: u32 = 0x0408_0004;
+
+/// The register indices, which are also the RSP's COP0 register numbers.
+pub const REG_DMA_SPADDR: u32 = 0;
+pub const REG_DMA_RAMADDR: u32 = 1;
+pub const REG_DMA_RDLEN: u32 = 2;
+pub const REG_DMA_WRLEN: u32 = 3;
+pub const REG_STATUS: u32 = 4;
+pub const REG_DMA_FULL: u32 = 5;
+pub const REG_DMA_BUSY: u32 = 6;
+pub const REG_SEMAPHORE: u32 = 7;
+
+const STATUS_HALT: u32 = 1 << 0;
+const STATUS_BROKE: u32 = 1 << 1;
+const STATUS_DMA_BUSY: u32 = 1 << 2;
+const STATUS_DMA_FULL: u32 = 1 << 3;
+const STATUS_IO_FULL: u32 = 1 << 4;
+const STATUS_SINGLE_STEP: u32 = 1 << 5;
+const STATUS_INTERRUPT_ON_BREAK: u32 = 1 << 6;
+const WRITE_CLEAR_BROKE: u32 = 1 << 2;
+
+const PC_MASK: u32 = 0xFFC;
+const MEM_ADDR_MASK: u32 = 0x1FF8;
+pub const MEM_SELECT_IMEM: u32 = 0x1000;
+const MEM_OFFSET_MASK: u32 = 0xFFF;
+const DRAM_ADDR_MASK: u32 = 0x00FF_FFF8;
+const DRAM_BYTE_MASK: u32 = 0x00FF_FFFF;
+const LEN_DONE: u32 = 0xFF8;
+const DMA_ROW_SETUP_CYCLES: u32 = 4;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn semaphore_after_read(addr: u32) -> bool {
+        let mut rsp = Rsp::init();
+        rsp.read_word_for_cpu(addr).unwrap();
+        rsp.after_cpu_read(addr);
+        rsp.peek_reg(REG_SEMAPHORE) != 0
+    }
+
+    #[test]
+    fn reading_the_semaphore_acquires_it() {
+        assert!(semaphore_after_read(SP_SEMAPHORE));
+        assert!(semaphore_after_read(SP_SEMAPHORE + 0x20));
+        assert!(semaphore_after_read(SP_SEMAPHORE + 0x1_0000));
+        assert!(!semaphore_after_read(SP_STATUS));
+        assert!(!semaphore_after_read(SP_PC));
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use anyhow::anyhow;
+use cpu_mips3::{vr4300::WriteSize, word::Word};
+
+use crate::{cartridge::Header, flashram::FlashRam, sram::Sram};
+
+
+/// The kinds of save memory a cartridge can contain.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SaveType {
+    None,
+    Eeprom4K,
+    Eeprom16K,
+    Sram32K,
+    Sram96K,
+    FlashRam,
+}
+impl SaveType {
+    /// Looks the game up in the built-in database. Unknown games get no save memory.
+    pub fn detect(header: &Header) -> Self {
+        let id = [header.game_code[1], header.game_code[2]];
+        DATABASE.iter().find(|(game, _)| **game == id).map_or(Self::None, |&(_, save)| save)
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            Self::None => 0,
+            Self::Eeprom4K => 0x200,
+            Self::Eeprom16K => 0x800,
+            Self::Sram32K => 0x8000,
+            Self::Sram96K => 0x18000,
+            Self::FlashRam => 0x20000,
+        }
+    }
+    /// The file extension other emulators use for saves of this type.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Eeprom4K | Self::Eeprom16K => "eep",
+            Self::Sram32K | Self::Sram96K => "sra",
+            Self::FlashRam => "fla",
+        }
+    }
+}
+impl FromStr for SaveType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "eeprom4k" => Ok(Self::Eeprom4K),
+            "eeprom16k" => Ok(Self::Eeprom16K),
+            "sram32k" => Ok(Self::Sram32K),
+            "sram96k" => Ok(Self::Sram96K),
+            "flashram" => Ok(Self::FlashRam),
+            _ => Err(anyhow!("unknown save type {s}")),
+        }
+    }
+}
+
+/// Save memory mirrored in a file, which is only rewritten when the contents changed.
+/// The file holds the bytes in the order the console addresses them.
+pub struct SaveFile {
+    data: Vec<u8>,
+    path: Option<PathBuf>,
+    dirty: bool,
+}
+impl SaveFile {
+    /// Loads the save from the file if there is one. Missing bytes are filled with `erased`.
+    pub fn open(path: Option<PathBuf>, size: usize, erased: u8) -> io::Result<Self> {
+        let mut data = match &path {
+            Some(path) => match fs::read(path) {
+                Ok(data) => data,
+                Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+                Err(err) => return Err(err),
+            },
+            None => Vec::new(),
+        };
+        data.resize(size, erased);
+
+        Ok(Self { data, path, dirty: false })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        self.dirty = true;
+        &mut self.data
+    }
+
+    /// Writes the save to its file, if it changed since the last flush.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let (true, Some(path)) = (self.dirty, &self.path) {
+            fs::write(path, &self.data)?;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// The save memory in cartridge domain 2. EEPROM is attached to the PIF instead.
+pub enum Backup {
+    Sram(Sram),
+    FlashRam(FlashRam),
+}
+impl Backup {
+    pub fn read_word(&self, addr: u32) -> Word {
+        match self {
+            Self::Sram(sram) => sram.read_word(addr),
+            Self::FlashRam(flash) => flash.read_word(addr),
+        }
+    }
+    pub fn write_word(&mut self, addr: u32, size: WriteSize, data: Word) {
+        match self {
+            Self::Sram(sram) => sram.write_word(addr, size, data),
+            Self::FlashRam(flash) => flash.write_word(addr, data),
+        }
+    }
+    /// Fills `data` with the bytes a PI DMA from `addr` transfers.
+    pub fn read_dma(&self, addr: u32, data: &mut [u8]) {
+        match self {
+            Self::Sram(sram) => sram.read_dma(addr, data),
+            Self::FlashRam(flash) => flash.read_dma(addr, data),
+        }
+    }
+    pub fn write_dma(&mut self, addr: u32, data: &[u8]) {
+        match self {
+            Self::Sram(sram) => sram.write_dma(addr, data),
+            Self::FlashRam(flash) => flash.write_dma(addr, data),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Sram(sram) => sram.flush(),
+            Self::FlashRam(flash) => flash.flush(),
+        }
+    }
+}
+
+/// Save types by the two characters of the game code that identify the game, independent of region.
+const DATABASE: &[(&[u8; 2], SaveType)] = &[
+    (b"SM", SaveType::Eeprom4K),  // Super Mario 64
+    (b"KT", SaveType::Eeprom4K),  // Mario Kart 64
+    (b"FX", SaveType::Eeprom4K),  // Star Fox 64
+    (b"WR", SaveType::Eeprom4K),  // Wave Race 64
+    (b"PW", SaveType::Eeprom4K),  // Pilotwings 64
+    (b"BK", SaveType::Eeprom4K),  // Banjo-Kazooie
+    (b"GE", SaveType::Eeprom4K),  // GoldenEye 007
+    (b"N6", SaveType::Eeprom4K),  // Dr. Mario 64
+    (b"DY", SaveType::Eeprom4K),  // Diddy Kong Racing
+    (b"YS", SaveType::Eeprom16K), // Yoshi's Story
+    (b"B7", SaveType::Eeprom16K), // Banjo-Tooie
+    (b"DO", SaveType::Eeprom16K), // Donkey Kong 64
+    (b"FU", SaveType::Eeprom16K), // Conker's Bad Fur Day
+    (b"MX", SaveType::Eeprom16K), // Excitebike 64
+    (b"M8", SaveType::Eeprom16K), // Mario Tennis
+    (b"PD", SaveType::Eeprom16K), // Perfect Dark
+    (b"JF", SaveType::Eeprom16K), // Jet Force Gemini
+    (b"ZL", SaveType::Sram32K),   // The Legend of Zelda: Ocarina of Time
+    (b"FZ", SaveType::Sram32K),   // F-Zero X
+    (b"TE", SaveType::Sram32K),   // 1080° Snowboarding
+    (b"AL", SaveType::Sram32K),   // Super Smash Bros.
+    (b"MF", SaveType::Sram32K),   // Mario Golf
+    (b"YW", SaveType::Sram32K),   // Harvest Moon 64
+    (b"ZS", SaveType::FlashRam),  // The Legend of Zelda: Majora's Mask
+    (b"MQ", SaveType::FlashRam),  // Paper Mario
+    (b"P3", SaveType::FlashRam),  // Pokémon Stadium 2
+    (b"PN", SaveType::FlashRam),  // Pokémon Puzzle League
+    (b"PF", SaveType::FlashRam),  // Pokémon Snap
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eeprom::Eeprom;
+
+    fn detect(game_code: &[u8; 4]) -> SaveType {
+        let header = Header {
+            pi_config: 0,
+            clock_rate: 0,
+            entry_point: 0,
+            release: 0,
+            crc1: 0,
+            crc2: 0,
+            title: String::new(),
+            game_code: *game_code,
+            revision: 0,
+        };
+        SaveType::detect(&header)
+    }
+
+    #[test]
+    fn detects_games_in_any_region() {
+        assert_eq!(detect(b"NSME"), SaveType::Eeprom4K);
+        assert_eq!(detect(b"NDYP"), SaveType::Eeprom4K);
+        assert_eq!(detect(b"NJFJ"), SaveType::Eeprom16K);
+        assert_eq!(detect(b"NZLE"), SaveType::Sram32K);
+        assert_eq!(detect(b"NZSP"), SaveType::FlashRam);
+        assert_eq!(detect(b"NXXE"), SaveType::None);
+    }
+
+    #[test]
+    fn every_game_is_listed_once() {
+        for (i, (game, _)) in DATABASE.iter().enumerate() {
+            assert!(DATABASE[i + 1..].iter().all(|(other, _)| other != game), "{game:?} is listed twice");
+        }
+    }
+
+    #[test]
+    fn opening_the_wrong_save_type_fails() {
+        assert!(Eeprom::open(SaveType::Sram32K, None).is_err());
+        assert!(Eeprom::open(SaveType::Eeprom16K, None).is_ok());
+        assert!(Sram::open(SaveType::FlashRam, None).is_err());
+        assert!(Sram::open(SaveType::Sram96K, None).is_ok());
+    }
+}
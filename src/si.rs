@@ -0,0 +1,116 @@
+use cpu_mips3::{core::MipsErr, vr4300::WriteSize, word::Word};
+
+
+/// The Serial Interface, which DMAs the 64 bytes of PIF RAM to and from RDRAM.
+/// Transfers take time, the data only moves once [Si::tick] reports them done.
+pub struct Si {
+    dram_addr: u32,
+    pif_addr: u32,
+    interrupt: bool,
+    dma: Option<SiDma>,
+    /// Cycles until the running DMA finishes.
+    busy_cycles: u32,
+}
+impl Si {
+    pub fn init() -> Self {
+        Self {
+            dram_addr: 0,
+            pif_addr: 0,
+            interrupt: false,
+            dma: None,
+            busy_cycles: 0,
+        }
+    }
+
+    pub fn interrupt(&self) -> bool {
+        self.interrupt
+    }
+
+    /// Advances the running DMA by one cycle, and returns it once its time is up.
+    pub fn tick(&mut self) -> Option<SiDma> {
+        self.dma?;
+        self.busy_cycles = self.busy_cycles.saturating_sub(1);
+        if self.busy_cycles == 0 { self.dma.take() } else { None }
+    }
+    /// Finishes a DMA by raising the interrupt.
+    pub fn complete_dma(&mut self) {
+        self.interrupt = true;
+    }
+
+    pub fn read_word_for_cpu(&self, addr: u32) -> Result<Option<Word>, MipsErr> {
+        if !(REGS_FIRST..=REGS_LAST).contains(&addr) { return Ok(None) }
+        let value = match addr & REGS_MASK {
+            SI_DRAM_ADDR => self.dram_addr,
+            SI_PIF_AD_RD64B | SI_PIF_AD_WR64B => self.pif_addr,
+            SI_STATUS => self.status(),
+            _ => 0,
+        };
+        Ok(Some(Word::from_u32_be(value)))
+    }
+    pub fn write_word_for_cpu(&mut self, addr: u32, _size: WriteSize, data: Word) -> Result<bool, MipsErr> {
+        if !(REGS_FIRST..=REGS_LAST).contains(&addr) { return Ok(false) }
+        let value = data.to_u32_be();
+        match addr & REGS_MASK {
+            SI_DRAM_ADDR => self.dram_addr = value & DRAM_ADDR_MASK,
+            SI_PIF_AD_RD64B => self.start_dma(SiDirection::ToDram, value),
+            SI_PIF_AD_WR64B => self.start_dma(SiDirection::ToPif, value),
+            // Any write to the status register acknowledges the interrupt
+            SI_STATUS => self.interrupt = false,
+            _ => (),
+        }
+        Ok(true)
+    }
+
+    fn status(&self) -> u32 {
+        let mut status = 0;
+        if self.dma.is_some() {
+            status |= STATUS_DMA_BUSY | STATUS_IO_BUSY;
+        }
+        if self.interrupt {
+            status |= STATUS_INTERRUPT;
+        }
+        status
+    }
+    /// Starting a DMA while another one runs is ignored, like on hardware.
+    fn start_dma(&mut self, direction: SiDirection, pif_addr: u32) {
+        if self.dma.is_some() {
+            return;
+        }
+        self.pif_addr = pif_addr;
+        self.busy_cycles = DMA_CYCLES;
+        self.dma = Some(SiDma {
+            direction,
+            dram_addr: self.dram_addr,
+        });
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SiDirection {
+    ToDram,
+    ToPif,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SiDma {
+    pub direction: SiDirection,
+    pub dram_addr: u32,
+}
+
+pub const REGS_FIRST: u32 = 0x0480_0000;
+pub const REGS_LAST: u32 = 0x048F_FFFF;
+const REGS_MASK: u32 = 0x0480_001F;
+
+pub const SI_DRAM_ADDR: u32 = 0x0480_0000;
+pub const SI_PIF_AD_RD64B: u32 = 0x0480_0004;
+pub const SI_PIF_AD_WR64B: u32 = 0x0480_0010;
+pub const SI_STATUS: u32 = 0x0480_0018;
+
+/// How long a transfer of all of PIF RAM keeps the SI busy, in CPU instructions.
+/// The serial link to the PIF is slow, so this is long enough for games to notice.
+const DMA_CYCLES: u32 = 2300;
+
+const DRAM_ADDR_MASK: u32 = 0x00FF_FFF8;
+const STATUS_DMA_BUSY: u32 = 1 << 0;
+const STATUS_IO_BUSY: u32 = 1 << 1;
+const STATUS_INTERRUPT: u32 = 1 << 12;
@@ -0,0 +1,108 @@
+use cpu_mips3::{core::MipsErr, vr4300::WriteSize, word::Word};
+
+use crate::rsp;
+
+
+/// One of the RSP's two 4 KB memories, DMEM or IMEM.
+/// They alternate every 4 KB through the SP memory range, told apart by [rsp::MEM_SELECT_IMEM].
+pub struct SpMem {
+    words: Vec<Word>,
+    /// The value of the select bit in the addresses that reach this memory.
+    select: u32,
+}
+impl SpMem {
+    pub fn dmem() -> Self {
+        Self::init(0)
+    }
+    pub fn imem() -> Self {
+        Self::init(rsp::MEM_SELECT_IMEM)
+    }
+    fn init(select: u32) -> Self {
+        Self {
+            words: vec![Word::zero(); SP_MEM_WORDS],
+            select,
+        }
+    }
+
+    /// Whether the CPU reaches this memory at `addr`.
+    pub fn contains(&self, addr: u32) -> bool {
+        (rsp::MEM_FIRST..=rsp::MEM_LAST).contains(&addr) && addr & rsp::MEM_SELECT_IMEM == self.select
+    }
+
+    pub fn read_word_for_cpu(&self, addr: u32) -> Result<Option<Word>, MipsErr> {
+        if !self.contains(addr) { return Ok(None) }
+        Ok(Some(self.words[Self::offset(addr) / 4]))
+    }
+    pub fn write_word_for_cpu(&mut self, addr: u32, size: WriteSize, data: Word) -> Result<bool, MipsErr> {
+        if !self.contains(addr) { return Ok(false) }
+        self.write(addr, size, data);
+        Ok(true)
+    }
+
+    /// Writes `size` bytes starting at `offset`, taking them from the byte lanes of `data` they occupy in the addressed word.
+    pub fn write(&mut self, offset: u32, size: WriteSize, data: Word) {
+        let offset = Self::offset(offset);
+        self.words[offset / 4].overwrite(data, (offset % 4) as u8, size.bytes());
+    }
+
+    /// Byte access for the RSP and for DMA, by offset into the memory.
+    /// Accesses wrap around at the end of the memory.
+    pub fn read_byte(&self, offset: u32) -> u8 {
+        let offset = Self::offset(offset);
+        self.words[offset / 4].0[offset % 4]
+    }
+    pub fn write_byte(&mut self, offset: u32, byte: u8) {
+        let offset = Self::offset(offset);
+        self.words[offset / 4].0[offset % 4] = byte;
+    }
+    pub fn read_half(&self, offset: u32) -> u16 {
+        u16::from_be_bytes([self.read_byte(offset), self.read_byte(offset + 1)])
+    }
+    pub fn write_half(&mut self, offset: u32, half: u16) {
+        for (i, byte) in half.to_be_bytes().into_iter().enumerate() {
+            self.write_byte(offset + i as u32, byte);
+        }
+    }
+    pub fn read_word(&self, offset: u32) -> u32 {
+        (0..4).fold(0, |word, i| word << 8 | self.read_byte(offset + i) as u32)
+    }
+    pub fn write_word(&mut self, offset: u32, word: u32) {
+        for (i, byte) in word.to_be_bytes().into_iter().enumerate() {
+            self.write_byte(offset + i as u32, byte);
+        }
+    }
+
+    fn offset(addr: u32) -> usize {
+        addr as usize % SP_MEM_BYTES
+    }
+}
+
+pub const SP_MEM_WORDS: usize = SP_MEM_BYTES / 4;
+pub const SP_MEM_BYTES: usize = 4096;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memories_alternate_and_mirror() {
+        let mut dmem = SpMem::dmem();
+        let imem = SpMem::imem();
+        assert!(dmem.contains(0x0400_0000) && !imem.contains(0x0400_0000));
+        assert!(imem.contains(0x0400_1FFC) && !dmem.contains(0x0400_1FFC));
+        assert!(dmem.contains(0x0403_E000) && !dmem.contains(0x0404_0000));
+
+        dmem.write_word_for_cpu(0x0400_2010, WriteSize::Four, Word::from_u32_be(0x1122_3344)).unwrap();
+        dmem.write_word_for_cpu(0x0400_0012, WriteSize::Two, Word([0, 0, 0xAA, 0xBB])).unwrap();
+        assert_eq!(dmem.read_word(0x10), 0x1122_AABB);
+        assert_eq!(dmem.read_word_for_cpu(0x0403_E010).unwrap(), Some(Word::from_u32_be(0x1122_AABB)));
+    }
+
+    #[test]
+    fn accesses_wrap_around() {
+        let mut dmem = SpMem::dmem();
+        dmem.write_word(0xFFE, 0x1122_3344);
+        assert_eq!(dmem.read_half(0xFFE), 0x1122);
+        assert_eq!(dmem.read_half(0), 0x3344);
+    }
+}
@@ -0,0 +1,66 @@
+use std::{
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+
+use cpu_mips3::{vr4300::WriteSize, word::Word};
+
+use crate::save::{SaveFile, SaveType};
+
+
+/// Battery backed SRAM in cartridge domain 2.
+/// The 96K variant consists of three 32K banks, selected by bits 18 and 19 of the address.
+pub struct Sram {
+    save: SaveFile,
+}
+impl Sram {
+    /// Opens SRAM of the given type. Save types other than SRAM are rejected.
+    pub fn open(save_type: SaveType, path: Option<PathBuf>) -> io::Result<Self> {
+        if !matches!(save_type, SaveType::Sram32K | SaveType::Sram96K) {
+            return Err(io::Error::new(ErrorKind::InvalidInput, format!("{save_type:?} is not SRAM")));
+        }
+        Ok(Self {
+            save: SaveFile::open(path, save_type.size(), 0)?,
+        })
+    }
+
+    pub fn read_word(&self, addr: u32) -> Word {
+        let addr = addr & !3;
+        Word(std::array::from_fn(|i| self.read_byte(addr + i as u32)))
+    }
+    pub fn write_word(&mut self, addr: u32, size: WriteSize, data: Word) {
+        let lane = addr as usize % 4;
+        for i in 0..size.bytes() as u32 {
+            self.write_byte(addr + i, data.0[lane + i as usize]);
+        }
+    }
+    pub fn read_dma(&self, addr: u32, data: &mut [u8]) {
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = self.read_byte(addr + i as u32);
+        }
+    }
+    pub fn write_dma(&mut self, addr: u32, data: &[u8]) {
+        for (i, &byte) in data.iter().enumerate() {
+            self.write_byte(addr + i as u32, byte);
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.save.flush()
+    }
+
+    fn read_byte(&self, addr: u32) -> u8 {
+        self.save.data()[self.offset(addr)]
+    }
+    fn write_byte(&mut self, addr: u32, byte: u8) {
+        let offset = self.offset(addr);
+        self.save.data_mut()[offset] = byte;
+    }
+    fn offset(&self, addr: u32) -> usize {
+        let bank = (addr >> 18 & 3) as usize;
+        let offset = bank * BANK_BYTES + (addr as usize & (BANK_BYTES - 1));
+        offset % self.save.data().len()
+    }
+}
+
+const BANK_BYTES: usize = 0x8000;